@@ -0,0 +1,233 @@
+use crate::types::*;
+use crate::http::normalize_base_url;
+use crate::qr::validate_qr_policy;
+use crate::limits::validate_tier_limits;
+use candid::Principal;
+
+// Longest a pending top-up may stay open
+const MAX_TOPUP_EXPIRY_SECONDS: u64 = 24 * 60 * 60;
+const MAX_RATE_FETCH_RETRIES: u32 = 10;
+// QRIS merchant ids travel in an EMV sub-tag
+const MAX_MERCHANT_ID_LEN: usize = 32;
+
+// Init/upgrade args as the equivalent list of field updates
+pub fn args_to_updates(args: CanisterArgs) -> Vec<ConfigUpdate> {
+    let mut updates = Vec::new();
+
+    if let Some(url) = args.public_base_url {
+        updates.push(ConfigUpdate::PublicBaseUrl(url));
+    }
+    if let Some(policy) = args.qr_policy {
+        updates.push(ConfigUpdate::QRPolicy(policy));
+    }
+    if let Some(seconds) = args.qris_expiry_seconds {
+        updates.push(ConfigUpdate::QrisExpirySeconds(seconds));
+    }
+    if let Some(seconds) = args.card_topup_expiry_seconds {
+        updates.push(ConfigUpdate::CardTopupExpirySeconds(seconds));
+    }
+    if let Some(seconds) = args.web3_topup_expiry_seconds {
+        updates.push(ConfigUpdate::Web3TopupExpirySeconds(seconds));
+    }
+    if let Some(e8s) = args.min_transaction_e8s {
+        updates.push(ConfigUpdate::MinTransactionE8s(e8s));
+    }
+    if let Some(e8s) = args.max_transaction_e8s {
+        updates.push(ConfigUpdate::MaxTransactionE8s(e8s));
+    }
+    if let Some(seconds) = args.rate_cache_seconds {
+        updates.push(ConfigUpdate::RateCacheSeconds(seconds));
+    }
+    if let Some(retries) = args.rate_fetch_max_retries {
+        updates.push(ConfigUpdate::RateFetchMaxRetries(retries));
+    }
+    if let Some(merchant_id) = args.merchant_id {
+        updates.push(ConfigUpdate::MerchantId(merchant_id));
+    }
+    if let Some(ledger) = args.ledger_canister_id {
+        updates.push(ConfigUpdate::LedgerCanisterId(Some(ledger)));
+    }
+    for limits in args.tier_limits.unwrap_or_default() {
+        updates.push(ConfigUpdate::TierLimits(limits));
+    }
+
+    updates
+}
+
+// Applies one update; returns (field, old value, new value) for the history
+pub fn apply_update(
+    config: &mut CanisterConfig,
+    update: ConfigUpdate,
+) -> Result<(String, String, String), String> {
+    let change = match update {
+        ConfigUpdate::PublicBaseUrl(url) => {
+            let url = normalize_base_url(&url)?;
+            let old = std::mem::replace(&mut config.public_base_url, url.clone());
+            ("public_base_url", old, url)
+        }
+        ConfigUpdate::QRPolicy(policy) => {
+            let new = format!("{:?}", policy);
+            let old = std::mem::replace(&mut config.qr_policy, policy);
+            ("qr_policy", format!("{:?}", old), new)
+        }
+        ConfigUpdate::QrisExpirySeconds(seconds) => {
+            let old = std::mem::replace(&mut config.qris_expiry_seconds, seconds);
+            ("qris_expiry_seconds", old.to_string(), seconds.to_string())
+        }
+        ConfigUpdate::CardTopupExpirySeconds(seconds) => {
+            let old = std::mem::replace(&mut config.card_topup_expiry_seconds, seconds);
+            ("card_topup_expiry_seconds", old.to_string(), seconds.to_string())
+        }
+        ConfigUpdate::Web3TopupExpirySeconds(seconds) => {
+            let old = std::mem::replace(&mut config.web3_topup_expiry_seconds, seconds);
+            ("web3_topup_expiry_seconds", old.to_string(), seconds.to_string())
+        }
+        ConfigUpdate::MinTransactionE8s(e8s) => {
+            let old = std::mem::replace(&mut config.min_transaction_e8s, e8s);
+            ("min_transaction_e8s", old.to_string(), e8s.to_string())
+        }
+        ConfigUpdate::MaxTransactionE8s(e8s) => {
+            let old = std::mem::replace(&mut config.max_transaction_e8s, e8s);
+            ("max_transaction_e8s", old.to_string(), e8s.to_string())
+        }
+        ConfigUpdate::RateCacheSeconds(seconds) => {
+            let old = std::mem::replace(&mut config.rate_cache_seconds, seconds);
+            ("rate_cache_seconds", old.to_string(), seconds.to_string())
+        }
+        ConfigUpdate::RateFetchMaxRetries(retries) => {
+            let old = std::mem::replace(&mut config.rate_fetch_max_retries, retries);
+            ("rate_fetch_max_retries", old.to_string(), retries.to_string())
+        }
+        ConfigUpdate::MerchantId(merchant_id) => {
+            let merchant_id = merchant_id.trim().to_string();
+            let old = std::mem::replace(&mut config.merchant_id, merchant_id.clone());
+            ("merchant_id", old, merchant_id)
+        }
+        ConfigUpdate::LedgerCanisterId(ledger) => {
+            let text = |ledger: Option<Principal>| ledger.map(|p| p.to_text()).unwrap_or_default();
+            let old = std::mem::replace(&mut config.ledger_canister_id, ledger);
+            ("ledger_canister_id", text(old), text(ledger))
+        }
+        ConfigUpdate::TierLimits(limits) => {
+            let field = format!("tier_limits.{:?}", limits.tier);
+            let new = format!("{:?}", limits);
+            let old = match config.tier_limits.iter_mut().find(|current| current.tier == limits.tier) {
+                Some(current) => format!("{:?}", std::mem::replace(current, limits)),
+                None => {
+                    config.tier_limits.push(limits);
+                    String::new()
+                }
+            };
+            return Ok((field, old, new));
+        }
+    };
+
+    Ok((change.0.to_string(), change.1, change.2))
+}
+
+// Checked on the whole config so cross-field rules (min <= max) hold
+pub fn validate_config(config: &CanisterConfig) -> Result<(), String> {
+    normalize_base_url(&config.public_base_url)?;
+    validate_qr_policy(&config.qr_policy)?;
+    validate_tier_limits(&config.tier_limits)?;
+
+    for (name, seconds) in [
+        ("QRIS expiry", config.qris_expiry_seconds),
+        ("Card top-up expiry", config.card_topup_expiry_seconds),
+        ("Web3 top-up expiry", config.web3_topup_expiry_seconds),
+    ] {
+        if seconds == 0 || seconds > MAX_TOPUP_EXPIRY_SECONDS {
+            return Err(format!("{} must be between 1 and {} seconds", name, MAX_TOPUP_EXPIRY_SECONDS));
+        }
+    }
+
+    if config.min_transaction_e8s == 0 || config.min_transaction_e8s > config.max_transaction_e8s {
+        return Err("Transaction limits must satisfy 0 < min <= max".to_string());
+    }
+
+    if config.rate_cache_seconds == 0 {
+        return Err("Rate cache duration must be at least 1 second".to_string());
+    }
+
+    if config.rate_fetch_max_retries == 0 || config.rate_fetch_max_retries > MAX_RATE_FETCH_RETRIES {
+        return Err(format!("Rate fetch retries must be between 1 and {}", MAX_RATE_FETCH_RETRIES));
+    }
+
+    if config.merchant_id.is_empty()
+        || config.merchant_id.len() > MAX_MERCHANT_ID_LEN
+        || !config.merchant_id.is_ascii()
+    {
+        return Err(format!("Merchant id must be 1-{} ASCII characters", MAX_MERCHANT_ID_LEN));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_config_is_valid() {
+        assert!(validate_config(&CanisterConfig::default()).is_ok());
+    }
+
+    #[test]
+    fn test_apply_update_records_old_and_new() {
+        let mut config = CanisterConfig::default();
+
+        let change = apply_update(&mut config, ConfigUpdate::PublicBaseUrl("https://abc.raw.icp0.io/".to_string()))
+            .unwrap();
+        assert_eq!(change, (
+            "public_base_url".to_string(),
+            "http://localhost:3000".to_string(),
+            "https://abc.raw.icp0.io".to_string(),
+        ));
+        assert_eq!(config.public_base_url, "https://abc.raw.icp0.io");
+
+        let change = apply_update(&mut config, ConfigUpdate::QrisExpirySeconds(600)).unwrap();
+        assert_eq!(change.1, "900");
+        assert_eq!(change.2, "600");
+        assert!(apply_update(&mut config, ConfigUpdate::PublicBaseUrl("ftp://x".to_string())).is_err());
+    }
+
+    #[test]
+    fn test_validate_config_cross_field_rules() {
+        let mut config = CanisterConfig::default();
+        apply_update(&mut config, ConfigUpdate::MinTransactionE8s(200_000_000_000)).unwrap();
+        assert!(validate_config(&config).is_err());
+
+        let mut config = CanisterConfig::default();
+        apply_update(&mut config, ConfigUpdate::RateFetchMaxRetries(0)).unwrap();
+        assert!(validate_config(&config).is_err());
+
+        let mut config = CanisterConfig::default();
+        apply_update(&mut config, ConfigUpdate::MerchantId("  ".to_string())).unwrap();
+        assert!(validate_config(&config).is_err());
+
+        let mut config = CanisterConfig::default();
+        let mut basic = config.tier_limits[1].clone();
+        basic.daily_outgoing_e8s = 0;
+        let change = apply_update(&mut config, ConfigUpdate::TierLimits(basic)).unwrap();
+        assert_eq!(change.0, "tier_limits.Basic");
+        assert_eq!(config.tier_limits.len(), 3);
+        assert!(validate_config(&config).is_err());
+    }
+
+    #[test]
+    fn test_args_to_updates() {
+        assert!(args_to_updates(CanisterArgs::default()).is_empty());
+
+        let args = CanisterArgs {
+            merchant_id: Some("SHOP_42".to_string()),
+            rate_cache_seconds: Some(60),
+            ..Default::default()
+        };
+        let mut config = CanisterConfig::default();
+        for update in args_to_updates(args) {
+            apply_update(&mut config, update).unwrap();
+        }
+        assert_eq!(config.merchant_id, "SHOP_42");
+        assert_eq!(config.rate_cache_seconds, 60);
+    }
+}
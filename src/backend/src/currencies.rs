@@ -0,0 +1,178 @@
+use crate::types::*;
+use crate::money::{Money, RoundingMode};
+
+const MAX_COUNTRIES_PER_CURRENCY: usize = 50;
+
+fn currency(
+    code: &str,
+    minor_units: u8,
+    symbol: &str,
+    display_name: &str,
+    countries: &[&str],
+) -> CurrencyInfo {
+    CurrencyInfo {
+        code: code.to_string(),
+        minor_units,
+        symbol: symbol.to_string(),
+        display_name: display_name.to_string(),
+        countries: countries.iter().map(|c| c.to_string()).collect(),
+        enabled: true,
+        min_payment_amount: None,
+        max_payment_amount: None,
+        updated_at: 0,
+    }
+}
+
+// Seed for an empty registry. Minor units follow ISO 4217, except IDR:
+// sen are no longer in circulation and QRIS settles whole rupiah.
+pub fn default_currencies() -> Vec<CurrencyInfo> {
+    vec![
+        currency("USD", 2, "$", "US Dollar", &["US"]),
+        currency(
+            "EUR",
+            2,
+            "€",
+            "Euro",
+            &[
+                "AT", "BE", "HR", "CY", "EE", "FI", "FR", "DE", "GR", "IE",
+                "IT", "LV", "LT", "LU", "MT", "NL", "PT", "SK", "SI", "ES",
+            ],
+        ),
+        currency("GBP", 2, "£", "British Pound", &["GB"]),
+        currency("JPY", 0, "¥", "Japanese Yen", &["JP"]),
+        currency("IDR", 0, "Rp", "Indonesian Rupiah", &["ID"]),
+        currency("SGD", 2, "S$", "Singapore Dollar", &["SG"]),
+        currency("MYR", 2, "RM", "Malaysian Ringgit", &["MY"]),
+        currency("PHP", 2, "₱", "Philippine Peso", &["PH"]),
+        currency("THB", 2, "฿", "Thai Baht", &["TH"]),
+        currency("VND", 0, "₫", "Vietnamese Dong", &["VN"]),
+    ]
+}
+
+// ISO 4217 numeric codes, used in EMVCo QR payloads (tag 53)
+pub fn iso_numeric_code(code: &str) -> Option<&'static str> {
+    match code.to_uppercase().as_str() {
+        "USD" => Some("840"),
+        "EUR" => Some("978"),
+        "GBP" => Some("826"),
+        "JPY" => Some("392"),
+        "IDR" => Some("360"),
+        "SGD" => Some("702"),
+        "MYR" => Some("458"),
+        "PHP" => Some("608"),
+        "THB" => Some("764"),
+        "VND" => Some("704"),
+        _ => None,
+    }
+}
+
+pub fn normalize_currency_info(mut info: CurrencyInfo) -> CurrencyInfo {
+    info.code = info.code.trim().to_uppercase();
+    info.symbol = info.symbol.trim().to_string();
+    info.display_name = info.display_name.trim().to_string();
+    info.countries = info.countries.iter().map(|c| c.trim().to_uppercase()).collect();
+    info.countries.sort();
+    info.countries.dedup();
+    info
+}
+
+pub fn validate_currency_info(info: &CurrencyInfo) -> Result<(), String> {
+    if info.code.len() != 3 || !info.code.chars().all(|c| c.is_ascii_uppercase()) {
+        return Err("Currency code must be 3 letters (ISO 4217)".to_string());
+    }
+
+    if info.minor_units > 4 {
+        return Err("Minor units must be between 0 and 4".to_string());
+    }
+
+    if info.symbol.is_empty() || info.display_name.is_empty() {
+        return Err("Symbol and display name are required".to_string());
+    }
+
+    if info.countries.is_empty() {
+        return Err("At least one country is required".to_string());
+    }
+
+    if info.countries.len() > MAX_COUNTRIES_PER_CURRENCY {
+        return Err(format!("At most {} countries per currency", MAX_COUNTRIES_PER_CURRENCY));
+    }
+
+    if info.countries.iter().any(|c| c.len() != 2 || !c.chars().all(|ch| ch.is_ascii_uppercase())) {
+        return Err("Countries must be ISO 3166-1 alpha-2 codes".to_string());
+    }
+
+    if let (Some(min), Some(max)) = (&info.min_payment_amount, &info.max_payment_amount) {
+        let scale = min.scale.max(max.scale);
+        let min = min.rescale(scale, RoundingMode::HalfEven);
+        let max = max.rescale(scale, RoundingMode::HalfEven);
+        if min.minor_units > max.minor_units {
+            return Err("Minimum payment amount exceeds maximum".to_string());
+        }
+    }
+
+    Ok(())
+}
+
+// Enforce the per-currency payment bounds, if any are configured
+pub fn check_payment_amount(info: &CurrencyInfo, amount: &Money) -> Result<(), String> {
+    if let Some(min) = &info.min_payment_amount {
+        let scale = min.scale.max(amount.scale);
+        let min_units = min.rescale(scale, RoundingMode::HalfEven).minor_units;
+        if amount.rescale(scale, RoundingMode::HalfEven).minor_units < min_units {
+            return Err(format!("Minimum payment is {} {}", min, info.code));
+        }
+    }
+
+    if let Some(max) = &info.max_payment_amount {
+        let scale = max.scale.max(amount.scale);
+        let max_units = max.rescale(scale, RoundingMode::HalfEven).minor_units;
+        if amount.rescale(scale, RoundingMode::HalfEven).minor_units > max_units {
+            return Err(format!("Maximum payment is {} {}", max, info.code));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_currencies_are_valid() {
+        for info in default_currencies() {
+            assert!(validate_currency_info(&info).is_ok(), "{} invalid", info.code);
+        }
+    }
+
+    #[test]
+    fn test_default_currencies_have_numeric_codes() {
+        for info in default_currencies() {
+            assert!(iso_numeric_code(&info.code).is_some(), "{} has no numeric code", info.code);
+        }
+        assert_eq!(iso_numeric_code("idr"), Some("360"));
+    }
+
+    #[test]
+    fn test_normalize_and_validate() {
+        let mut info = currency("usd", 2, "$", "US Dollar", &["us", "US", "ec"]);
+        info = normalize_currency_info(info);
+        assert_eq!(info.code, "USD");
+        assert_eq!(info.countries, vec!["EC".to_string(), "US".to_string()]);
+        assert!(validate_currency_info(&info).is_ok());
+
+        info.countries = vec![];
+        assert!(validate_currency_info(&info).is_err());
+    }
+
+    #[test]
+    fn test_check_payment_amount() {
+        let mut info = currency("USD", 2, "$", "US Dollar", &["US"]);
+        info.min_payment_amount = Some(Money::new(100, 2));
+        info.max_payment_amount = Some(Money::new(100_000, 2));
+
+        assert!(check_payment_amount(&info, &Money::new(100, 2)).is_ok());
+        assert!(check_payment_amount(&info, &Money::new(99, 2)).is_err());
+        assert!(check_payment_amount(&info, &Money::new(100_001, 2)).is_err());
+    }
+}
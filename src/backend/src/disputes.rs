@@ -0,0 +1,215 @@
+use crate::escrow::validate_dispute_text;
+use crate::types::*;
+use candid::Principal;
+
+// Payers can contest a transaction for this long after it completed
+const DISPUTE_WINDOW_NANOS: u64 = 120 * 24 * 60 * 60 * 1_000_000_000;
+const MAX_EVIDENCE_LEN: usize = 4000;
+
+// Completed balance payments are stored as "{base}_COMPLETED_{time}"
+pub fn payment_base_id(transaction_id: &str) -> &str {
+    transaction_id.split("_COMPLETED_").next().unwrap_or(transaction_id)
+}
+
+// Keyed by the payment so each one can only be disputed once
+pub fn dispute_id(transaction_id: &str) -> String {
+    format!("DSP_{}", payment_base_id(transaction_id).trim_start_matches("TX_"))
+}
+
+pub fn validate_evidence(evidence: Option<String>) -> Result<Option<String>, String> {
+    let Some(evidence) = evidence.map(|e| e.trim().to_string()).filter(|e| !e.is_empty()) else {
+        return Ok(None);
+    };
+    if evidence.chars().count() > MAX_EVIDENCE_LEN {
+        return Err(format!("Evidence must be at most {} characters", MAX_EVIDENCE_LEN));
+    }
+    Ok(Some(evidence))
+}
+
+pub fn check_disputable(tx: &Transaction, caller: Principal, now: u64) -> Result<(), String> {
+    if tx.from != caller {
+        return Err("Transaction not found".to_string());
+    }
+    if tx.status != TransactionStatus::Completed {
+        return Err("Only completed transactions can be disputed".to_string());
+    }
+    if now.saturating_sub(tx.timestamp) > DISPUTE_WINDOW_NANOS {
+        return Err("The dispute window for this transaction has closed".to_string());
+    }
+    Ok(())
+}
+
+pub fn build_dispute(tx: &Transaction, reason: &str, evidence: Option<String>, now: u64) -> Result<Dispute, String> {
+    Ok(Dispute {
+        id: dispute_id(&tx.id),
+        transaction_id: tx.id.clone(),
+        payer: tx.from,
+        merchant: tx.to,
+        icp_amount: tx.amount,
+        fiat_amount: tx.fiat_amount,
+        currency: tx.fiat_currency.clone(),
+        reason: validate_dispute_text(reason)?,
+        evidence: validate_evidence(evidence)?,
+        merchant_response: None,
+        status: DisputeStatus::Open,
+        decision: None,
+        refunded_amount: 0,
+        recovered_amount: 0,
+        timeline: vec![DisputeEvent { status: DisputeStatus::Open, actor: tx.from, note: None, timestamp: now }],
+        created_at: now,
+        resolved_at: None,
+    })
+}
+
+pub fn is_dispute_party(dispute: &Dispute, caller: Principal) -> bool {
+    dispute.payer == caller || dispute.merchant == caller
+}
+
+pub fn is_dispute_open(dispute: &Dispute) -> bool {
+    matches!(dispute.status, DisputeStatus::Open | DisputeStatus::Responded)
+}
+
+fn ensure_open(dispute: &Dispute) -> Result<(), String> {
+    if !is_dispute_open(dispute) {
+        return Err(format!("Dispute is closed ({:?})", dispute.status));
+    }
+    Ok(())
+}
+
+fn record_event(dispute: &mut Dispute, status: DisputeStatus, actor: Principal, note: Option<String>, now: u64) {
+    dispute.status = status.clone();
+    dispute.timeline.push(DisputeEvent { status, actor, note, timestamp: now });
+}
+
+// The merchant may update the response until an admin decides
+pub fn add_merchant_response(dispute: &mut Dispute, caller: Principal, response: &str, now: u64) -> Result<(), String> {
+    if dispute.merchant != caller {
+        return Err("Dispute not found".to_string());
+    }
+    ensure_open(dispute)?;
+    let response = validate_evidence(Some(response.to_string()))?
+        .ok_or("A response is required")?;
+    dispute.merchant_response = Some(response);
+    record_event(dispute, DisputeStatus::Responded, caller, None, now);
+    Ok(())
+}
+
+pub fn withdraw_case(dispute: &mut Dispute, caller: Principal, now: u64) -> Result<(), String> {
+    if dispute.payer != caller {
+        return Err("Dispute not found".to_string());
+    }
+    ensure_open(dispute)?;
+    dispute.resolved_at = Some(now);
+    record_event(dispute, DisputeStatus::Withdrawn, caller, None, now);
+    Ok(())
+}
+
+// ICP returned to the payer for a decision
+pub fn refund_for_decision(dispute: &Dispute, decision: &DisputeDecision) -> Result<u64, String> {
+    ensure_open(dispute)?;
+    match decision {
+        DisputeDecision::RefundPayer => Ok(dispute.icp_amount),
+        DisputeDecision::PartialRefund { amount } => {
+            if *amount == 0 || *amount > dispute.icp_amount {
+                return Err(format!("A partial refund must be between 1 and {} e8s", dispute.icp_amount));
+            }
+            Ok(*amount)
+        }
+        DisputeDecision::Reject => Ok(0),
+    }
+}
+
+pub fn record_decision(
+    dispute: &mut Dispute,
+    decision: DisputeDecision,
+    admin: Principal,
+    note: Option<String>,
+    refunded: u64,
+    recovered: u64,
+    now: u64,
+) {
+    dispute.decision = Some(decision);
+    dispute.refunded_amount = refunded;
+    dispute.recovered_amount = recovered;
+    dispute.resolved_at = Some(now);
+    record_event(dispute, DisputeStatus::Resolved, admin, note, now);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::money::Money;
+
+    fn principal(id: u8) -> Principal {
+        Principal::from_slice(&[id])
+    }
+
+    fn transaction(timestamp: u64) -> Transaction {
+        Transaction {
+            id: "TX_ABC_COMPLETED_5".to_string(),
+            from: principal(2),
+            to: principal(1),
+            amount: 1_000_000,
+            fiat_currency: "USD".to_string(),
+            fiat_amount: Money::new(500, 2),
+            icp_amount: 1_000_000,
+            timestamp,
+            status: TransactionStatus::Completed,
+            qr_id: "QR".to_string(),
+            transaction_hash: None,
+            fee: 10_000,
+            kind: TransactionKind::QrPayment,
+            memo: None,
+        }
+    }
+
+    #[test]
+    fn test_who_can_dispute() {
+        let tx = transaction(0);
+        assert_eq!(dispute_id(&tx.id), "DSP_ABC");
+        assert_eq!(dispute_id("TX_ICRC_7"), "DSP_ICRC_7");
+
+        assert!(check_disputable(&tx, principal(2), 10).is_ok());
+        assert!(check_disputable(&tx, principal(1), 10).is_err());
+        assert!(check_disputable(&tx, principal(2), DISPUTE_WINDOW_NANOS + 1).is_err());
+
+        let failed = Transaction { status: TransactionStatus::Failed, ..transaction(0) };
+        assert!(check_disputable(&failed, principal(2), 10).is_err());
+
+        assert!(build_dispute(&tx, " ", None, 10).is_err());
+        assert!(build_dispute(&tx, "Not delivered", Some("x".repeat(MAX_EVIDENCE_LEN + 1)), 10).is_err());
+    }
+
+    #[test]
+    fn test_response_and_decision() {
+        let tx = transaction(0);
+        let mut dispute = build_dispute(&tx, "Not delivered", Some("  ".to_string()), 10).unwrap();
+        assert_eq!(dispute.evidence, None);
+
+        assert!(add_merchant_response(&mut dispute, principal(2), "Shipped", 20).is_err());
+        add_merchant_response(&mut dispute, principal(1), "Shipped on the 3rd", 20).unwrap();
+        assert_eq!(dispute.status, DisputeStatus::Responded);
+
+        assert_eq!(refund_for_decision(&dispute, &DisputeDecision::RefundPayer), Ok(1_000_000));
+        assert_eq!(refund_for_decision(&dispute, &DisputeDecision::Reject), Ok(0));
+        assert!(refund_for_decision(&dispute, &DisputeDecision::PartialRefund { amount: 1_000_001 }).is_err());
+        assert_eq!(refund_for_decision(&dispute, &DisputeDecision::PartialRefund { amount: 400_000 }), Ok(400_000));
+
+        record_decision(&mut dispute, DisputeDecision::PartialRefund { amount: 400_000 }, principal(9), None, 400_000, 400_000, 30);
+        assert_eq!(dispute.status, DisputeStatus::Resolved);
+        let statuses: Vec<DisputeStatus> = dispute.timeline.iter().map(|e| e.status.clone()).collect();
+        assert_eq!(statuses, vec![DisputeStatus::Open, DisputeStatus::Responded, DisputeStatus::Resolved]);
+
+        assert!(refund_for_decision(&dispute, &DisputeDecision::RefundPayer).is_err());
+        assert!(withdraw_case(&mut dispute, principal(2), 40).is_err());
+    }
+
+    #[test]
+    fn test_withdraw() {
+        let mut dispute = build_dispute(&transaction(0), "Duplicate charge", None, 10).unwrap();
+        assert!(withdraw_case(&mut dispute, principal(1), 20).is_err());
+        withdraw_case(&mut dispute, principal(2), 20).unwrap();
+        assert_eq!(dispute.status, DisputeStatus::Withdrawn);
+        assert!(add_merchant_response(&mut dispute, principal(1), "Too late", 30).is_err());
+    }
+}
@@ -0,0 +1,461 @@
+use candid::{CandidType, Deserialize};
+use serde::Serialize;
+
+// EMVCo Merchant-Presented Mode QR (QRIS, PromptPay, DuitNow, ... all use it).
+// A payload is a flat list of tag / two-digit length / value fields, some of
+// which nest another TLV list, and always ends with a CRC-16 over everything
+// up to and including "6304".
+
+const TAG_PAYLOAD_FORMAT: &str = "00";
+const TAG_POINT_OF_INITIATION: &str = "01";
+const TAG_MERCHANT_CATEGORY: &str = "52";
+const TAG_CURRENCY: &str = "53";
+const TAG_AMOUNT: &str = "54";
+const TAG_COUNTRY: &str = "58";
+const TAG_MERCHANT_NAME: &str = "59";
+const TAG_MERCHANT_CITY: &str = "60";
+const TAG_POSTAL_CODE: &str = "61";
+const TAG_ADDITIONAL_DATA: &str = "62";
+const TAG_CRC: &str = "63";
+
+const PAYLOAD_FORMAT_VERSION: &str = "01";
+const MAX_VALUE_LENGTH: usize = 99;
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum PointOfInitiation {
+    // "11": printed code, payer enters the amount
+    Static,
+    // "12": generated per payment, carries the amount
+    Dynamic,
+}
+
+// Templates 26-51; sub-tag 00 identifies the scheme (e.g. "ID.CO.QRIS.WWW")
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct MerchantAccountInfo {
+    pub tag: String,
+    pub globally_unique_id: String,
+    pub merchant_pan: Option<String>,
+    pub merchant_id: Option<String>,
+    pub merchant_criteria: Option<String>,
+}
+
+// Template 62
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+pub struct AdditionalData {
+    pub bill_number: Option<String>,
+    pub reference_label: Option<String>,
+    pub terminal_label: Option<String>,
+    pub purpose: Option<String>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct EmvPayload {
+    pub point_of_initiation: PointOfInitiation,
+    pub merchant_accounts: Vec<MerchantAccountInfo>,
+    pub merchant_category_code: String,
+    // ISO 4217 numeric, e.g. "360" for IDR
+    pub currency_numeric: String,
+    // Decimal with "." separator, no grouping
+    pub amount: Option<String>,
+    pub country_code: String,
+    pub merchant_name: String,
+    pub merchant_city: String,
+    pub postal_code: Option<String>,
+    pub additional_data: Option<AdditionalData>,
+}
+
+// CRC-16/CCITT-FALSE (poly 0x1021, init 0xFFFF), as EMVCo specifies
+pub fn crc16_ccitt(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0xFFFF;
+    for byte in data {
+        crc ^= (*byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x1021 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+pub fn encode_tlv(tag: &str, value: &str) -> Result<String, String> {
+    if tag.len() != 2 || !tag.chars().all(|c| c.is_ascii_digit()) {
+        return Err(format!("Invalid EMV tag: {}", tag));
+    }
+
+    // Lengths count characters, not bytes
+    let length = value.chars().count();
+    if length == 0 || length > MAX_VALUE_LENGTH {
+        return Err(format!("EMV field {} must be 1-{} characters", tag, MAX_VALUE_LENGTH));
+    }
+
+    Ok(format!("{}{:02}{}", tag, length, value))
+}
+
+pub fn parse_tlv(data: &str) -> Result<Vec<(String, String)>, String> {
+    let chars: Vec<char> = data.chars().collect();
+    let mut fields = Vec::new();
+    let mut pos = 0;
+
+    while pos < chars.len() {
+        if pos + 4 > chars.len() {
+            return Err(format!("Truncated EMV field at position {}", pos));
+        }
+
+        let tag: String = chars[pos..pos + 2].iter().collect();
+        let length: String = chars[pos + 2..pos + 4].iter().collect();
+        let length: usize = length.parse()
+            .map_err(|_| format!("Invalid length for EMV field {}", tag))?;
+
+        let start = pos + 4;
+        if start + length > chars.len() {
+            return Err(format!("EMV field {} overruns the payload", tag));
+        }
+
+        fields.push((tag, chars[start..start + length].iter().collect()));
+        pos = start + length;
+    }
+
+    Ok(fields)
+}
+
+fn check_length(field: &str, value: &str, max: usize) -> Result<(), String> {
+    let length = value.chars().count();
+    if length == 0 || length > max {
+        return Err(format!("{} must be 1-{} characters", field, max));
+    }
+    Ok(())
+}
+
+fn check_digits(field: &str, value: &str, length: usize) -> Result<(), String> {
+    if value.len() != length || !value.chars().all(|c| c.is_ascii_digit()) {
+        return Err(format!("{} must be {} digits", field, length));
+    }
+    Ok(())
+}
+
+fn validate_amount(amount: &str) -> Result<(), String> {
+    check_length("Amount", amount, 13)?;
+
+    let mut parts = amount.split('.');
+    let whole = parts.next().unwrap_or("");
+    let fraction = parts.next();
+    let valid = !whole.is_empty()
+        && whole.chars().all(|c| c.is_ascii_digit())
+        && fraction.is_none_or(|f| !f.is_empty() && f.chars().all(|c| c.is_ascii_digit()))
+        && parts.next().is_none();
+
+    if !valid {
+        return Err(format!("Invalid amount: {}", amount));
+    }
+    Ok(())
+}
+
+pub fn validate_payload(payload: &EmvPayload) -> Result<(), String> {
+    if payload.merchant_accounts.is_empty() {
+        return Err("At least one merchant account is required".to_string());
+    }
+
+    for account in &payload.merchant_accounts {
+        let tag: u8 = account.tag.parse().map_err(|_| "Invalid merchant account tag".to_string())?;
+        if account.tag.len() != 2 || !(26..=51).contains(&tag) {
+            return Err(format!("Merchant account tag must be 26-51, got {}", account.tag));
+        }
+        check_length("Globally unique identifier", &account.globally_unique_id, 32)?;
+    }
+
+    check_digits("Merchant category code", &payload.merchant_category_code, 4)?;
+    check_digits("Currency code", &payload.currency_numeric, 3)?;
+
+    match (&payload.point_of_initiation, &payload.amount) {
+        (_, Some(amount)) => validate_amount(amount)?,
+        (PointOfInitiation::Dynamic, None) => {
+            return Err("Dynamic payloads must carry an amount".to_string());
+        }
+        (PointOfInitiation::Static, None) => {}
+    }
+
+    if payload.country_code.len() != 2 || !payload.country_code.chars().all(|c| c.is_ascii_uppercase()) {
+        return Err("Country code must be ISO 3166-1 alpha-2".to_string());
+    }
+
+    check_length("Merchant name", &payload.merchant_name, 25)?;
+    check_length("Merchant city", &payload.merchant_city, 15)?;
+
+    if let Some(postal_code) = &payload.postal_code {
+        check_length("Postal code", postal_code, 10)?;
+    }
+
+    if let Some(data) = &payload.additional_data {
+        for value in [&data.bill_number, &data.reference_label, &data.terminal_label, &data.purpose]
+            .into_iter()
+            .flatten()
+        {
+            check_length("Additional data field", value, 25)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn encode_merchant_account(account: &MerchantAccountInfo) -> Result<String, String> {
+    let mut value = encode_tlv("00", &account.globally_unique_id)?;
+    if let Some(pan) = &account.merchant_pan {
+        value.push_str(&encode_tlv("01", pan)?);
+    }
+    if let Some(merchant_id) = &account.merchant_id {
+        value.push_str(&encode_tlv("02", merchant_id)?);
+    }
+    if let Some(criteria) = &account.merchant_criteria {
+        value.push_str(&encode_tlv("03", criteria)?);
+    }
+    encode_tlv(&account.tag, &value)
+}
+
+fn encode_additional_data(data: &AdditionalData) -> Result<Option<String>, String> {
+    let mut value = String::new();
+    let fields = [
+        ("01", &data.bill_number),
+        ("05", &data.reference_label),
+        ("07", &data.terminal_label),
+        ("08", &data.purpose),
+    ];
+
+    for (tag, field) in fields {
+        if let Some(field) = field {
+            value.push_str(&encode_tlv(tag, field)?);
+        }
+    }
+
+    if value.is_empty() {
+        return Ok(None);
+    }
+    encode_tlv(TAG_ADDITIONAL_DATA, &value).map(Some)
+}
+
+pub fn encode_payload(payload: &EmvPayload) -> Result<String, String> {
+    validate_payload(payload)?;
+
+    let point_of_initiation = match payload.point_of_initiation {
+        PointOfInitiation::Static => "11",
+        PointOfInitiation::Dynamic => "12",
+    };
+
+    let mut data = encode_tlv(TAG_PAYLOAD_FORMAT, PAYLOAD_FORMAT_VERSION)?;
+    data.push_str(&encode_tlv(TAG_POINT_OF_INITIATION, point_of_initiation)?);
+
+    for account in &payload.merchant_accounts {
+        data.push_str(&encode_merchant_account(account)?);
+    }
+
+    data.push_str(&encode_tlv(TAG_MERCHANT_CATEGORY, &payload.merchant_category_code)?);
+    data.push_str(&encode_tlv(TAG_CURRENCY, &payload.currency_numeric)?);
+    if let Some(amount) = &payload.amount {
+        data.push_str(&encode_tlv(TAG_AMOUNT, amount)?);
+    }
+    data.push_str(&encode_tlv(TAG_COUNTRY, &payload.country_code)?);
+    data.push_str(&encode_tlv(TAG_MERCHANT_NAME, &payload.merchant_name)?);
+    data.push_str(&encode_tlv(TAG_MERCHANT_CITY, &payload.merchant_city)?);
+    if let Some(postal_code) = &payload.postal_code {
+        data.push_str(&encode_tlv(TAG_POSTAL_CODE, postal_code)?);
+    }
+    if let Some(additional) = &payload.additional_data {
+        if let Some(encoded) = encode_additional_data(additional)? {
+            data.push_str(&encoded);
+        }
+    }
+
+    // The CRC covers its own tag and length
+    data.push_str(TAG_CRC);
+    data.push_str("04");
+    let crc = crc16_ccitt(data.as_bytes());
+    Ok(format!("{}{:04X}", data, crc))
+}
+
+fn verify_crc(data: &str) -> Result<(), String> {
+    if data.len() < 8 || !data.is_char_boundary(data.len() - 4) {
+        return Err("Payload too short".to_string());
+    }
+
+    let (body, checksum) = data.split_at(data.len() - 4);
+    if !body.ends_with("6304") {
+        return Err("Payload must end with a CRC field".to_string());
+    }
+
+    let expected = u16::from_str_radix(checksum, 16)
+        .map_err(|_| "Invalid CRC field".to_string())?;
+    if crc16_ccitt(body.as_bytes()) != expected {
+        return Err("CRC mismatch".to_string());
+    }
+    Ok(())
+}
+
+fn sub_field(fields: &[(String, String)], tag: &str) -> Option<String> {
+    fields.iter().find(|(t, _)| t == tag).map(|(_, v)| v.clone())
+}
+
+pub fn decode_payload(data: &str) -> Result<EmvPayload, String> {
+    let data = data.trim();
+    verify_crc(data)?;
+
+    let fields = parse_tlv(data)?;
+    let field = |tag: &str| sub_field(&fields, tag);
+    let required = |tag: &str, name: &str| field(tag).ok_or(format!("Missing {}", name));
+
+    if fields.first().map(|(tag, value)| (tag.as_str(), value.as_str()))
+        != Some((TAG_PAYLOAD_FORMAT, PAYLOAD_FORMAT_VERSION))
+    {
+        return Err("Payload must start with format indicator 01".to_string());
+    }
+
+    let point_of_initiation = match field(TAG_POINT_OF_INITIATION).as_deref() {
+        Some("11") | None => PointOfInitiation::Static,
+        Some("12") => PointOfInitiation::Dynamic,
+        Some(other) => return Err(format!("Unknown point of initiation: {}", other)),
+    };
+
+    let mut merchant_accounts = Vec::new();
+    for (tag, value) in &fields {
+        let is_account = tag.parse::<u8>().is_ok_and(|t| (26..=51).contains(&t));
+        if !is_account {
+            continue;
+        }
+
+        let sub_fields = parse_tlv(value)?;
+        merchant_accounts.push(MerchantAccountInfo {
+            tag: tag.clone(),
+            globally_unique_id: sub_field(&sub_fields, "00")
+                .ok_or(format!("Merchant account {} has no identifier", tag))?,
+            merchant_pan: sub_field(&sub_fields, "01"),
+            merchant_id: sub_field(&sub_fields, "02"),
+            merchant_criteria: sub_field(&sub_fields, "03"),
+        });
+    }
+
+    let additional_data = match field(TAG_ADDITIONAL_DATA) {
+        Some(value) => {
+            let sub_fields = parse_tlv(&value)?;
+            Some(AdditionalData {
+                bill_number: sub_field(&sub_fields, "01"),
+                reference_label: sub_field(&sub_fields, "05"),
+                terminal_label: sub_field(&sub_fields, "07"),
+                purpose: sub_field(&sub_fields, "08"),
+            })
+        }
+        None => None,
+    };
+
+    let payload = EmvPayload {
+        point_of_initiation,
+        merchant_accounts,
+        merchant_category_code: required(TAG_MERCHANT_CATEGORY, "merchant category code")?,
+        currency_numeric: required(TAG_CURRENCY, "currency")?,
+        amount: field(TAG_AMOUNT),
+        country_code: required(TAG_COUNTRY, "country code")?,
+        merchant_name: required(TAG_MERCHANT_NAME, "merchant name")?,
+        merchant_city: required(TAG_MERCHANT_CITY, "merchant city")?,
+        postal_code: field(TAG_POSTAL_CODE),
+        additional_data,
+    };
+
+    validate_payload(&payload)?;
+    Ok(payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_payload() -> EmvPayload {
+        EmvPayload {
+            point_of_initiation: PointOfInitiation::Dynamic,
+            merchant_accounts: vec![MerchantAccountInfo {
+                tag: "26".to_string(),
+                globally_unique_id: "ID.CO.QRIS.WWW".to_string(),
+                merchant_pan: None,
+                merchant_id: Some("ICP_PAYMENT_001".to_string()),
+                merchant_criteria: None,
+            }],
+            merchant_category_code: "6051".to_string(),
+            currency_numeric: "360".to_string(),
+            amount: Some("150000".to_string()),
+            country_code: "ID".to_string(),
+            merchant_name: "ICP Payment".to_string(),
+            merchant_city: "Jakarta Selatan".to_string(),
+            postal_code: Some("12400".to_string()),
+            additional_data: Some(AdditionalData {
+                reference_label: Some("TU_0123456789ABCDEF".to_string()),
+                ..Default::default()
+            }),
+        }
+    }
+
+    #[test]
+    fn test_crc16_ccitt_check_value() {
+        assert_eq!(crc16_ccitt(b"123456789"), 0x29B1);
+        assert_eq!(crc16_ccitt(b""), 0xFFFF);
+    }
+
+    #[test]
+    fn test_encode_tlv() {
+        assert_eq!(encode_tlv("59", "ICP Payment"), Ok("5911ICP Payment".to_string()));
+        assert!(encode_tlv("5", "x").is_err());
+        assert!(encode_tlv("59", "").is_err());
+        assert!(encode_tlv("59", &"x".repeat(100)).is_err());
+    }
+
+    // Expected string cross-checked against Python's binascii.crc_hqx(data, 0xFFFF)
+    #[test]
+    fn test_encode_payload_vector() {
+        let encoded = encode_payload(&sample_payload()).unwrap();
+        assert_eq!(
+            encoded,
+            "00020101021226370014ID.CO.QRIS.WWW0215ICP_PAYMENT_001\
+             5204605153033605406150000\
+             5802ID5911ICP Payment6015Jakarta Selatan6105124006223\
+             0519TU_0123456789ABCDEF6304B4BB"
+        );
+    }
+
+    #[test]
+    fn test_decode_round_trip() {
+        let payload = sample_payload();
+        let decoded = decode_payload(&encode_payload(&payload).unwrap()).unwrap();
+        assert_eq!(decoded, payload);
+    }
+
+    #[test]
+    fn test_decode_rejects_bad_crc() {
+        let mut encoded = encode_payload(&sample_payload()).unwrap();
+        encoded.replace_range(60..61, "9");
+        assert_eq!(decode_payload(&encoded), Err("CRC mismatch".to_string()));
+    }
+
+    #[test]
+    fn test_static_payload_has_no_amount() {
+        let mut payload = sample_payload();
+        payload.point_of_initiation = PointOfInitiation::Static;
+        payload.amount = None;
+
+        let encoded = encode_payload(&payload).unwrap();
+        assert!(encoded.starts_with("000201010211"));
+        assert!(!encoded.contains("5406"));
+        assert_eq!(decode_payload(&encoded).unwrap(), payload);
+
+        payload.point_of_initiation = PointOfInitiation::Dynamic;
+        assert!(encode_payload(&payload).is_err());
+    }
+
+    #[test]
+    fn test_validate_payload_field_limits() {
+        let mut payload = sample_payload();
+        payload.merchant_city = "Jakarta Selatan Raya".to_string();
+        assert!(validate_payload(&payload).is_err());
+
+        let mut payload = sample_payload();
+        payload.amount = Some("1,000".to_string());
+        assert!(validate_payload(&payload).is_err());
+
+        let mut payload = sample_payload();
+        payload.merchant_accounts[0].tag = "52".to_string();
+        assert!(validate_payload(&payload).is_err());
+    }
+}
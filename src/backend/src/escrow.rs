@@ -0,0 +1,209 @@
+use crate::types::*;
+use candid::Principal;
+use std::collections::BTreeMap;
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+pub const DEFAULT_ESCROW_RELEASE_SECONDS: u64 = 14 * SECONDS_PER_DAY;
+const MIN_ESCROW_RELEASE_SECONDS: u64 = 60 * 60;
+const MAX_ESCROW_RELEASE_SECONDS: u64 = 90 * SECONDS_PER_DAY;
+const MAX_DISPUTE_TEXT_LEN: usize = 1000;
+const BPS: u64 = 10_000;
+
+pub fn resolve_release_seconds(terms: &EscrowTerms) -> Result<u64, String> {
+    let seconds = terms.release_after_seconds.unwrap_or(DEFAULT_ESCROW_RELEASE_SECONDS);
+    if !(MIN_ESCROW_RELEASE_SECONDS..=MAX_ESCROW_RELEASE_SECONDS).contains(&seconds) {
+        return Err(format!(
+            "Escrow release must be between {} and {} seconds",
+            MIN_ESCROW_RELEASE_SECONDS, MAX_ESCROW_RELEASE_SECONDS
+        ));
+    }
+    Ok(seconds)
+}
+
+pub fn is_escrow_party(escrow: &Escrow, caller: Principal) -> bool {
+    escrow.payer == caller || escrow.merchant == caller
+}
+
+// Still holding funds, disputed or not
+pub fn is_escrow_open(escrow: &Escrow) -> bool {
+    matches!(escrow.status, EscrowStatus::Held | EscrowStatus::Disputed)
+}
+
+// Undisputed escrows release themselves once the window closes
+pub fn is_due_for_release(escrow: &Escrow, now: u64) -> bool {
+    escrow.status == EscrowStatus::Held && now >= escrow.release_at
+}
+
+pub fn validate_dispute_text(text: &str) -> Result<String, String> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Err("A reason is required".to_string());
+    }
+    if text.chars().count() > MAX_DISPUTE_TEXT_LEN {
+        return Err(format!("Text must be at most {} characters", MAX_DISPUTE_TEXT_LEN));
+    }
+    Ok(text.to_string())
+}
+
+// Only the payer can release early
+pub fn check_release(escrow: &Escrow, caller: Principal) -> Result<(), String> {
+    if !is_escrow_party(escrow, caller) {
+        return Err("Escrow not found".to_string());
+    }
+    if escrow.payer != caller {
+        return Err("Only the payer can release an escrow".to_string());
+    }
+    if escrow.status != EscrowStatus::Held {
+        return Err(format!("Escrow cannot be released ({:?})", escrow.status));
+    }
+    Ok(())
+}
+
+pub fn open_escrow_dispute(escrow: &mut Escrow, caller: Principal, reason: &str, now: u64) -> Result<(), String> {
+    if !is_escrow_party(escrow, caller) {
+        return Err("Escrow not found".to_string());
+    }
+    if escrow.status != EscrowStatus::Held {
+        return Err(format!("Escrow cannot be disputed ({:?})", escrow.status));
+    }
+    escrow.dispute = Some(EscrowDispute {
+        opened_by: caller,
+        reason: validate_dispute_text(reason)?,
+        opened_at: now,
+        resolution: None,
+        resolved_by: None,
+        note: None,
+    });
+    escrow.status = EscrowStatus::Disputed;
+    Ok(())
+}
+
+// (merchant, payer) parts of the held amount for a resolution
+pub fn resolution_amounts(icp_amount: u64, resolution: &EscrowResolution) -> Result<(u64, u64), String> {
+    match resolution {
+        EscrowResolution::Release => Ok((icp_amount, 0)),
+        EscrowResolution::Refund => Ok((0, icp_amount)),
+        EscrowResolution::Split { payer_share_bps } => {
+            let bps = *payer_share_bps as u64;
+            if bps == 0 || bps >= BPS {
+                return Err("A split must give each side part of the payment".to_string());
+            }
+            let payer_part = (icp_amount as u128 * bps as u128 / BPS as u128) as u64;
+            Ok((icp_amount - payer_part, payer_part))
+        }
+    }
+}
+
+pub fn resolved_status(resolution: &EscrowResolution) -> EscrowStatus {
+    match resolution {
+        EscrowResolution::Release => EscrowStatus::Released,
+        EscrowResolution::Refund => EscrowStatus::Refunded,
+        EscrowResolution::Split { .. } => EscrowStatus::Split,
+    }
+}
+
+// Amounts still parked in the escrow account, per held payment (sorted by
+// reference), from the account's own ledger entries: received when held,
+// sent out when settled
+pub fn open_holds(account_logs: &[BalanceChangeLog]) -> Vec<(String, u64)> {
+    let mut holds: BTreeMap<&str, u64> = BTreeMap::new();
+    for log in account_logs {
+        let held = holds.entry(log.reference_id.as_str()).or_default();
+        match log.change_type {
+            BalanceChangeType::PaymentReceived => *held = held.saturating_add(log.amount),
+            BalanceChangeType::PaymentSent => *held = held.saturating_sub(log.amount),
+            _ => {}
+        }
+    }
+    holds.into_iter()
+        .filter(|(_, held)| *held > 0)
+        .map(|(reference, held)| (reference.to_string(), held))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::money::Money;
+
+    fn escrow() -> Escrow {
+        Escrow {
+            id: "ESC_1".to_string(),
+            qr_id: "QR".to_string(),
+            payer: Principal::from_slice(&[2]),
+            merchant: Principal::from_slice(&[1]),
+            amount: Money::new(5_000, 2),
+            currency: "USD".to_string(),
+            icp_amount: 1_000_000,
+            transaction_id: "TX_1_PROCESSING_1".to_string(),
+            status: EscrowStatus::Held,
+            created_at: 0,
+            release_at: 100,
+            dispute: None,
+            settled_at: None,
+        }
+    }
+
+    #[test]
+    fn test_release_rules() {
+        let esc = escrow();
+        assert!(check_release(&esc, esc.payer).is_ok());
+        assert!(check_release(&esc, esc.merchant).is_err());
+        assert!(check_release(&esc, Principal::from_slice(&[9])).is_err());
+        assert!(!is_due_for_release(&esc, 99));
+        assert!(is_due_for_release(&esc, 100));
+
+        assert_eq!(resolve_release_seconds(&EscrowTerms { release_after_seconds: None }), Ok(DEFAULT_ESCROW_RELEASE_SECONDS));
+        assert!(resolve_release_seconds(&EscrowTerms { release_after_seconds: Some(60) }).is_err());
+    }
+
+    #[test]
+    fn test_dispute_stops_auto_release() {
+        let mut esc = escrow();
+        let merchant = esc.merchant;
+        assert!(open_escrow_dispute(&mut esc, merchant, "  ", 10).is_err());
+        open_escrow_dispute(&mut esc, merchant, "Buyer never collected", 10).unwrap();
+        assert_eq!(esc.status, EscrowStatus::Disputed);
+        assert!(!is_due_for_release(&esc, 1_000));
+        assert!(is_escrow_open(&esc));
+        assert!(check_release(&esc, esc.payer).is_err());
+        assert!(open_escrow_dispute(&mut esc, merchant, "Again", 11).is_err());
+    }
+
+    #[test]
+    fn test_resolution_amounts() {
+        assert_eq!(resolution_amounts(1_000_000, &EscrowResolution::Release), Ok((1_000_000, 0)));
+        assert_eq!(resolution_amounts(1_000_000, &EscrowResolution::Refund), Ok((0, 1_000_000)));
+        assert_eq!(resolution_amounts(1_000_001, &EscrowResolution::Split { payer_share_bps: 2_500 }), Ok((750_001, 250_000)));
+        assert!(resolution_amounts(1_000_000, &EscrowResolution::Split { payer_share_bps: 10_000 }).is_err());
+        assert_eq!(resolved_status(&EscrowResolution::Split { payer_share_bps: 1 }), EscrowStatus::Split);
+    }
+
+    #[test]
+    fn test_open_holds() {
+        let account = Principal::from_slice(&[9]);
+        let log = |reference: &str, change_type: BalanceChangeType, amount: u64| BalanceChangeLog {
+            id: format!("BAL_ESCROW_{:?}_{}", change_type, reference),
+            user_id: account,
+            change_type,
+            amount,
+            previous_balance: 0,
+            new_balance: 0,
+            timestamp: 0,
+            reference_id: reference.to_string(),
+            description: "Escrow account".to_string(),
+        };
+
+        let logs = vec![
+            log("TX_1_PROCESSING_1", BalanceChangeType::PaymentReceived, 1_000),
+            log("TX_2_PROCESSING_2", BalanceChangeType::PaymentReceived, 2_000),
+            log("TX_3_PROCESSING_3", BalanceChangeType::PaymentReceived, 3_000),
+            log("TX_2_PROCESSING_2", BalanceChangeType::PaymentSent, 2_000),
+        ];
+        assert_eq!(
+            open_holds(&logs),
+            vec![("TX_1_PROCESSING_1".to_string(), 1_000), ("TX_3_PROCESSING_3".to_string(), 3_000)]
+        );
+        assert!(open_holds(&[]).is_empty());
+    }
+}
@@ -0,0 +1,207 @@
+use crate::money::{currency_minor_units, Money, RoundingMode};
+
+pub const DEFAULT_LANGUAGE: &str = "en";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SymbolPosition {
+    Before,
+    After,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LocaleFormat {
+    pub group_separator: &'static str,
+    pub decimal_separator: &'static str,
+    pub symbol_position: SymbolPosition,
+    pub symbol_spacing: bool,
+}
+
+// Number conventions per UserPreferences.language ("id", "id-ID", "ja_JP", ...)
+pub fn locale_format(language: &str) -> LocaleFormat {
+    let language = language
+        .split(['-', '_'])
+        .next()
+        .unwrap_or(DEFAULT_LANGUAGE)
+        .to_lowercase();
+
+    match language.as_str() {
+        "id" => LocaleFormat {
+            group_separator: ".",
+            decimal_separator: ",",
+            symbol_position: SymbolPosition::Before,
+            symbol_spacing: true,
+        },
+        "de" | "es" | "it" | "nl" | "pt" => LocaleFormat {
+            group_separator: ".",
+            decimal_separator: ",",
+            symbol_position: SymbolPosition::After,
+            symbol_spacing: true,
+        },
+        "fr" => LocaleFormat {
+            group_separator: "\u{202F}",
+            decimal_separator: ",",
+            symbol_position: SymbolPosition::After,
+            symbol_spacing: true,
+        },
+        "vi" => LocaleFormat {
+            group_separator: ".",
+            decimal_separator: ",",
+            symbol_position: SymbolPosition::After,
+            symbol_spacing: true,
+        },
+        // en, ja, th, ms, zh, ... and anything we don't know
+        _ => LocaleFormat {
+            group_separator: ",",
+            decimal_separator: ".",
+            symbol_position: SymbolPosition::Before,
+            symbol_spacing: false,
+        },
+    }
+}
+
+fn group_digits(digits: &str, separator: &str) -> String {
+    let mut grouped = String::new();
+    let len = digits.len();
+
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (len - i).is_multiple_of(3) {
+            grouped.push_str(separator);
+        }
+        grouped.push(c);
+    }
+
+    grouped
+}
+
+// Grouped number without a symbol, e.g. "100.000" (id) or "1,234.56" (en)
+pub fn format_number(amount: &Money, locale: &LocaleFormat) -> String {
+    let text = amount.to_string();
+    let (whole, fraction) = match text.split_once('.') {
+        Some((w, f)) => (w.to_string(), Some(f.to_string())),
+        None => (text, None),
+    };
+
+    let whole = group_digits(&whole, locale.group_separator);
+    match fraction {
+        Some(fraction) => format!("{}{}{}", whole, locale.decimal_separator, fraction),
+        None => whole,
+    }
+}
+
+fn attach_symbol(number: String, symbol: &str, locale: &LocaleFormat) -> String {
+    let space = if locale.symbol_spacing { " " } else { "" };
+    match locale.symbol_position {
+        SymbolPosition::Before => format!("{}{}{}", symbol, space, number),
+        SymbolPosition::After => format!("{}{}{}", number, space, symbol),
+    }
+}
+
+// Fiat amount with symbol and the currency's minor units, e.g. "Rp 100.000", "¥101"
+pub fn format_money(amount: &Money, currency: &str, language: &str) -> String {
+    let locale = locale_format(language);
+    let symbol = crate::get_currency_info(currency)
+        .map(|info| info.symbol)
+        .unwrap_or_else(|| currency.to_uppercase());
+
+    let rounded = amount.rescale(currency_minor_units(currency), RoundingMode::HalfUp);
+    attach_symbol(format_number(&rounded, &locale), &symbol, &locale)
+}
+
+// e8s as ICP with locale separators, e.g. "1.234,50000000 ICP" (id)
+pub fn format_icp(e8s: u64, language: &str) -> String {
+    let locale = locale_format(language);
+    format!("{} ICP", format_number(&Money::new(e8s, 8), &locale))
+}
+
+// Nanosecond timestamp as UTC, e.g. "2024-03-01 08:30:00 UTC"
+pub fn format_timestamp(nanos: u64) -> String {
+    let seconds = nanos / 1_000_000_000;
+    let days = (seconds / 86_400) as i64;
+    let time_of_day = seconds % 86_400;
+
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        time_of_day / 3_600,
+        time_of_day % 3_600 / 60,
+        time_of_day % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_money_by_language() {
+        crate::seed_currency_registry();
+
+        assert_eq!(format_money(&Money::new(100_000, 0), "IDR", "id"), "Rp 100.000");
+        assert_eq!(format_money(&Money::new(100_000, 0), "IDR", "en"), "Rp100,000");
+        assert_eq!(format_money(&Money::new(123_456, 2), "USD", "en-US"), "$1,234.56");
+        assert_eq!(format_money(&Money::new(123_456, 2), "EUR", "de"), "1.234,56 €");
+        assert_eq!(format_money(&Money::new(1005, 1), "JPY", "ja"), "¥101");
+    }
+
+    #[test]
+    fn test_format_money_rescales_to_minor_units() {
+        crate::seed_currency_registry();
+
+        // IDR records stored with ISO scale still display whole rupiah
+        assert_eq!(format_money(&Money::new(10_000_050, 2), "IDR", "id"), "Rp 100.001");
+    }
+
+    #[test]
+    fn test_format_icp() {
+        assert_eq!(format_icp(123_450_000_000, "en"), "1,234.50000000 ICP");
+        assert_eq!(format_icp(123_450_000_000, "id"), "1.234,50000000 ICP");
+        assert_eq!(format_icp(5, "ja"), "0.00000005 ICP");
+    }
+
+    // Ported from the transactions helper this replaced
+    #[test]
+    fn test_format_transaction_amount() {
+        assert_eq!(format_icp(100_000_000, "en"), "1.00000000 ICP");
+        assert_eq!(format_icp(50_000_000, "en"), "0.50000000 ICP");
+        assert_eq!(format_icp(12_345_678, "en"), "0.12345678 ICP");
+    }
+
+    // Ported from the rates helper this replaced; amounts round to the
+    // currency's minor units
+    #[test]
+    fn test_currency_formatting() {
+        crate::seed_currency_registry();
+
+        assert_eq!(format_money(&Money::parse("100.5", "USD").unwrap(), "USD", "en"), "$100.50");
+        assert_eq!(format_money(&Money::new(1005, 1), "JPY", "en"), "¥101");
+        assert_eq!(format_money(&Money::parse("100000", "IDR").unwrap(), "IDR", "en"), "Rp100,000");
+    }
+
+    #[test]
+    fn test_group_digits() {
+        assert_eq!(group_digits("1", ","), "1");
+        assert_eq!(group_digits("123", ","), "123");
+        assert_eq!(group_digits("1234", ","), "1,234");
+        assert_eq!(group_digits("1234567", "."), "1.234.567");
+    }
+
+    #[test]
+    fn test_format_timestamp() {
+        assert_eq!(format_timestamp(0), "1970-01-01 00:00:00 UTC");
+        assert_eq!(format_timestamp(951_782_400 * 1_000_000_000), "2000-02-29 00:00:00 UTC");
+        assert_eq!(format_timestamp(1_709_281_800 * 1_000_000_000), "2024-03-01 08:30:00 UTC");
+    }
+}
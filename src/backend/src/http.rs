@@ -0,0 +1,293 @@
+use crate::types::*;
+use crate::qr::QRDisplayInfo;
+use crate::qr_render::{render_qr, QRImage, QRImageFormat, QRRenderOptions};
+use crate::formatting::{format_icp, format_money, format_timestamp};
+use candid::{CandidType, Deserialize};
+use serde::Serialize;
+
+// Gateway interface (`http_request` query). Responses are not certified, so
+// the pages must be reached through the raw domain (<id>.raw.icp0.io).
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct HttpRequest {
+    pub method: String,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct HttpResponse {
+    pub status_code: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Route {
+    Pay(String),
+    Qris(String),
+    QrImage(String, QRImageFormat),
+    Receipt(String),
+    NotFound,
+}
+
+pub fn parse_route(url: &str) -> Route {
+    let path = url.split(['?', '#']).next().unwrap_or("");
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+
+    match segments.as_slice() {
+        ["pay", id] if !id.is_empty() => Route::Pay(id.to_string()),
+        ["qris", id] if !id.is_empty() => Route::Qris(id.to_string()),
+        ["receipt", id] if !id.is_empty() => Route::Receipt(id.to_string()),
+        ["qr", file] => match file.rsplit_once('.') {
+            Some((id, "svg")) if !id.is_empty() => Route::QrImage(id.to_string(), QRImageFormat::Svg),
+            Some((id, "png")) if !id.is_empty() => Route::QrImage(id.to_string(), QRImageFormat::Png),
+            _ => Route::NotFound,
+        },
+        _ => Route::NotFound,
+    }
+}
+
+// Base URLs are stored without a trailing slash
+pub fn normalize_base_url(url: &str) -> Result<String, String> {
+    let url = url.trim().trim_end_matches('/');
+    let host = url.strip_prefix("https://").or_else(|| url.strip_prefix("http://"));
+
+    match host {
+        Some(host) if !host.is_empty() && !host.contains(char::is_whitespace) => Ok(url.to_string()),
+        _ => Err("Base URL must be an absolute http(s) URL".to_string()),
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn page(title: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\">\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\
+         <title>{title} - ICP Payment</title>\
+         <style>body{{font-family:sans-serif;max-width:420px;margin:2rem auto;padding:0 1rem;color:#1d1d2b}}\
+         .qr{{width:100%;max-width:320px;display:block;margin:1rem auto}}\
+         dl{{display:grid;grid-template-columns:auto 1fr;gap:.4rem 1rem}}dt{{color:#6b6b80}}dd{{margin:0;word-break:break-all}}\
+         .amount{{font-size:2rem;font-weight:bold;text-align:center;margin:.5rem 0}}</style>\
+         </head><body><h1>{title}</h1>{body}</body></html>",
+        title = escape_html(title),
+        body = body
+    )
+}
+
+fn details(rows: &[(&str, String)]) -> String {
+    let mut html = String::from("<dl>");
+    for (label, value) in rows {
+        html.push_str(&format!("<dt>{}</dt><dd>{}</dd>", escape_html(label), escape_html(value)));
+    }
+    html.push_str("</dl>");
+    html
+}
+
+pub fn pay_page(info: &QRDisplayInfo, image_url: &str) -> String {
+    let amount = if info.kind == QRKind::Static {
+        format!("Any amount in {}", info.fiat_currency)
+    } else {
+        info.formatted_fiat.clone()
+    };
+
+    let mut rows = vec![("Status", format!("{:?}", info.status))];
+    if info.kind == QRKind::Dynamic {
+        rows.push(("ICP", info.formatted_icp.clone()));
+    }
+    if let Some(seconds) = info.time_remaining_seconds {
+        rows.push(("Expires in", format!("{}m {}s", seconds / 60, seconds % 60)));
+    }
+    if let Some(remaining) = info.remaining_uses {
+        rows.push(("Remaining uses", remaining.to_string()));
+    }
+    if let Some(description) = &info.description {
+        rows.push(("Description", description.clone()));
+    }
+    rows.push(("QR ID", info.id.clone()));
+
+    let wallet_link = info.payment_uri.as_ref()
+        .map(|uri| format!("<p><a href=\"{}\">Pay with an ICRC wallet</a></p>", escape_html(uri)))
+        .unwrap_or_default();
+
+    page(
+        "Payment request",
+        &format!(
+            "<p class=\"amount\">{}</p><img class=\"qr\" src=\"{}\" alt=\"Payment QR code\">{}\
+             <p>Scan this code with the ICP Payment app to pay.</p>{}",
+            escape_html(&amount),
+            escape_html(image_url),
+            details(&rows),
+            wallet_link
+        ),
+    )
+}
+
+pub fn qris_page(topup: &TopUpTransaction, language: &str) -> Result<String, String> {
+    let qris = topup.payment_data.qris_data.first()
+        .ok_or("Top-up has no QRIS payment data")?;
+
+    let options = QRRenderOptions {
+        format: QRImageFormat::Svg,
+        error_correction: None,
+        size_px: Some(320),
+        margin_modules: None,
+        logo: None,
+    };
+    let svg = String::from_utf8(render_qr(&qris.qr_code_data, &options)?.bytes)
+        .map_err(|_| "Invalid SVG output".to_string())?;
+
+    let rows = [
+        ("Status", format!("{:?}", topup.status)),
+        ("You receive", format_icp(topup.amount, language)),
+        ("Expires", format_timestamp(qris.expire_time)),
+        ("Merchant", qris.merchant_id.clone()),
+        ("Reference", topup.id.clone()),
+    ];
+
+    Ok(page(
+        "QRIS top-up",
+        &format!(
+            "<p class=\"amount\">{}</p><div class=\"qr\">{}</div>{}\
+             <p>Scan with any QRIS-enabled banking or e-wallet app.</p>",
+            escape_html(&format_money(&topup.fiat_amount, &topup.fiat_currency, language)),
+            svg,
+            details(&rows)
+        ),
+    ))
+}
+
+pub fn receipt_page(tx: &Transaction, language: &str) -> String {
+    let mut rows = vec![
+        ("Status", format!("{:?}", tx.status)),
+        ("Amount", format_icp(tx.icp_amount, language)),
+        ("Fee", format_icp(tx.fee, language)),
+        ("Date", format_timestamp(tx.timestamp)),
+        ("From", tx.from.to_text()),
+        ("To", tx.to.to_text()),
+    ];
+    if !tx.qr_id.is_empty() {
+        rows.push(("QR ID", tx.qr_id.clone()));
+    }
+    if let Some(memo) = &tx.memo {
+        rows.push(("Memo", memo.clone()));
+    }
+    rows.push(("Transaction", tx.id.clone()));
+
+    // Transfers entered in ICP have no fiat side
+    let headline = if tx.fiat_currency == "ICP" {
+        format_icp(tx.icp_amount, language)
+    } else {
+        format_money(&tx.fiat_amount, &tx.fiat_currency, language)
+    };
+
+    page(
+        "Payment receipt",
+        &format!(
+            "<p class=\"amount\">{}</p>{}",
+            escape_html(&headline),
+            details(&rows)
+        ),
+    )
+}
+
+pub fn html_response(html: String) -> HttpResponse {
+    HttpResponse {
+        status_code: 200,
+        headers: vec![
+            ("Content-Type".to_string(), "text/html; charset=utf-8".to_string()),
+            // Status and countdowns change, never cache
+            ("Cache-Control".to_string(), "no-store".to_string()),
+        ],
+        body: html.into_bytes(),
+    }
+}
+
+pub fn image_response(image: QRImage) -> HttpResponse {
+    HttpResponse {
+        status_code: 200,
+        headers: vec![
+            ("Content-Type".to_string(), image.content_type),
+            ("Cache-Control".to_string(), "public, max-age=86400".to_string()),
+        ],
+        body: image.bytes,
+    }
+}
+
+pub fn error_response(status_code: u16, message: &str) -> HttpResponse {
+    HttpResponse {
+        status_code,
+        headers: vec![("Content-Type".to_string(), "text/html; charset=utf-8".to_string())],
+        body: page("Error", &format!("<p>{}</p>", escape_html(message))).into_bytes(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::money::Money;
+    use candid::Principal;
+
+    #[test]
+    fn test_parse_route() {
+        assert_eq!(parse_route("/pay/ABC123"), Route::Pay("ABC123".to_string()));
+        assert_eq!(parse_route("/pay/ABC123/?lang=id"), Route::Pay("ABC123".to_string()));
+        assert_eq!(parse_route("/qris/TU_1"), Route::Qris("TU_1".to_string()));
+        assert_eq!(parse_route("/receipt/TX_1"), Route::Receipt("TX_1".to_string()));
+        assert_eq!(parse_route("/qr/ABC.svg"), Route::QrImage("ABC".to_string(), QRImageFormat::Svg));
+        assert_eq!(parse_route("/qr/ABC.png"), Route::QrImage("ABC".to_string(), QRImageFormat::Png));
+        assert_eq!(parse_route("/qr/ABC.gif"), Route::NotFound);
+        assert_eq!(parse_route("/pay/"), Route::NotFound);
+        assert_eq!(parse_route("/"), Route::NotFound);
+    }
+
+    #[test]
+    fn test_normalize_base_url() {
+        assert_eq!(normalize_base_url("https://abc.raw.icp0.io/"), Ok("https://abc.raw.icp0.io".to_string()));
+        assert_eq!(normalize_base_url("http://localhost:3000"), Ok("http://localhost:3000".to_string()));
+        assert!(normalize_base_url("abc.raw.icp0.io").is_err());
+        assert!(normalize_base_url("https://").is_err());
+    }
+
+    #[test]
+    fn test_receipt_page_escapes_content() {
+        crate::seed_currency_registry();
+
+        let tx = Transaction {
+            id: "TX_<script>".to_string(),
+            from: Principal::from_slice(&[1]),
+            to: Principal::from_slice(&[2]),
+            amount: 100_000_000,
+            fiat_currency: "USD".to_string(),
+            fiat_amount: Money::new(1_050, 2),
+            icp_amount: 100_000_000,
+            timestamp: 0,
+            status: TransactionStatus::Completed,
+            qr_id: "1234567890ABCDEF".to_string(),
+            transaction_hash: None,
+            fee: 1_000_000,
+            kind: TransactionKind::QrPayment,
+            memo: None,
+        };
+
+        let html = receipt_page(&tx, "en");
+        assert!(html.contains("$10.50"));
+        assert!(html.contains("1970-01-01 00:00:00 UTC"));
+        assert!(html.contains("TX_&lt;script&gt;"));
+        assert!(!html.contains("<script>"));
+    }
+}
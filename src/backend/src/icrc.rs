@@ -0,0 +1,491 @@
+use crate::money::E8S_PER_ICP;
+use crate::types::BlockRange;
+use candid::{CandidType, Deserialize, Int, Nat, Principal};
+use serde::Serialize;
+
+// ICRC-1 account; merchants are paid into a subaccount of this canister
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Account {
+    pub owner: Principal,
+    pub subaccount: Option<Vec<u8>>,
+}
+
+// Subaccount encoding a principal: length byte, principal bytes, zero padding
+pub fn merchant_subaccount(merchant: Principal) -> Vec<u8> {
+    let bytes = merchant.as_slice();
+    let mut subaccount = vec![0u8; 32];
+    subaccount[0] = bytes.len() as u8;
+    subaccount[1..1 + bytes.len()].copy_from_slice(bytes);
+    subaccount
+}
+
+pub fn merchant_from_subaccount(subaccount: &[u8]) -> Option<Principal> {
+    let len = *subaccount.first()? as usize;
+    if subaccount.len() != 32 || len == 0 || len > 29 {
+        return None;
+    }
+    if subaccount[1 + len..].iter().any(|b| *b != 0) {
+        return None;
+    }
+    Some(Principal::from_slice(&subaccount[1..1 + len]))
+}
+
+pub fn merchant_account(canister_id: Principal, merchant: Principal) -> Account {
+    Account {
+        owner: canister_id,
+        subaccount: Some(merchant_subaccount(merchant)),
+    }
+}
+
+// CRC-32 (IEEE), used by the ICRC-1 textual account checksum
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+// RFC 4648 base32, lowercase, no padding
+fn base32(data: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"abcdefghijklmnopqrstuvwxyz234567";
+    let mut out = String::new();
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for byte in data {
+        buffer = (buffer << 8) | *byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            out.push(ALPHABET[((buffer >> bits) & 31) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        out.push(ALPHABET[((buffer << (5 - bits)) & 31) as usize] as char);
+    }
+    out
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// ICRC-1 textual encoding: `owner-checksum.subaccount`, or just the owner
+pub fn encode_account(account: &Account) -> String {
+    let subaccount = match &account.subaccount {
+        Some(sub) if sub.iter().any(|b| *b != 0) => sub,
+        _ => return account.owner.to_text(),
+    };
+
+    let mut checked = account.owner.as_slice().to_vec();
+    checked.extend_from_slice(subaccount);
+    let checksum = base32(&crc32(&checked).to_be_bytes());
+
+    format!(
+        "{}-{}.{}",
+        account.owner.to_text(),
+        checksum,
+        hex(subaccount).trim_start_matches('0')
+    )
+}
+
+// e8s as a decimal token amount without trailing zeros
+fn format_token_amount(e8s: u64) -> String {
+    let whole = e8s as u128 / E8S_PER_ICP;
+    let fraction = e8s as u128 % E8S_PER_ICP;
+    if fraction == 0 {
+        return whole.to_string();
+    }
+    let fraction = format!("{:08}", fraction);
+    format!("{}.{}", whole, fraction.trim_end_matches('0'))
+}
+
+// ICRC-22 style request, e.g. `icp:<ledger>/transfer?to=<account>&amount=1.5&memo=<hex>`.
+// Static codes leave the amount to the payer's wallet.
+pub fn build_payment_uri(ledger: Principal, to: &Account, amount_e8s: Option<u64>, memo: &[u8]) -> String {
+    let mut uri = format!("icp:{}/transfer?to={}", ledger.to_text(), encode_account(to));
+    if let Some(amount) = amount_e8s {
+        uri.push_str(&format!("&amount={}", format_token_amount(amount)));
+    }
+    uri.push_str(&format!("&memo={}", hex(memo)));
+    uri
+}
+
+// ===================
+// ICRC-3 block log
+// ===================
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub enum Icrc3Value {
+    Blob(Vec<u8>),
+    Text(String),
+    Nat(Nat),
+    Nat64(u64),
+    Int(Int),
+    Array(Vec<Icrc3Value>),
+    Map(Vec<(String, Icrc3Value)>),
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct GetBlocksArgs {
+    pub start: Nat,
+    pub length: Nat,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct BlockWithId {
+    pub id: Nat,
+    pub block: Icrc3Value,
+}
+
+candid::define_function!(pub GetBlocksCallback : (Vec<GetBlocksArgs>) -> (GetBlocksResult) query);
+
+// Blocks the ledger moved to an archive canister; `callback` serves them
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct ArchivedBlocks {
+    pub args: Vec<GetBlocksArgs>,
+    pub callback: GetBlocksCallback,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct GetBlocksResult {
+    pub log_length: Nat,
+    pub blocks: Vec<BlockWithId>,
+    pub archived_blocks: Vec<ArchivedBlocks>,
+}
+
+// One past the last block a poll from `start` accounted for, received or
+// archived. A ledger that returns nothing while its log is longer dropped
+// the range without pointing at an archive.
+pub fn poll_end(start: u64, max_length: u64, received: &[u64], archived: &[ArchivedBlocks], log_length: u64) -> u64 {
+    let archived_ends = archived.iter()
+        .flat_map(|archived| &archived.args)
+        .filter_map(|args| Some(nat_to_u64(&args.start)?.saturating_add(nat_to_u64(&args.length)?)));
+    let end = received.iter().map(|index| index + 1).chain(archived_ends).max().unwrap_or(start);
+    let end = if end <= start { start.saturating_add(max_length) } else { end };
+    end.min(log_length).max(start)
+}
+
+// Ranges of [start, end) missing from the sorted `received` indices
+pub fn missing_block_ranges(start: u64, end: u64, received: &[u64]) -> Vec<BlockRange> {
+    let mut missing = Vec::new();
+    let mut next = start;
+    for &index in received.iter().filter(|&&index| index >= start && index < end) {
+        if index > next {
+            missing.push(BlockRange { start: next, end: index });
+        }
+        next = next.max(index + 1);
+    }
+    if next < end {
+        missing.push(BlockRange { start: next, end });
+    }
+    missing
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LedgerTransfer {
+    pub block_index: u64,
+    pub from: Account,
+    pub to: Account,
+    pub amount: u64,
+    pub memo: Option<Vec<u8>>,
+    pub timestamp: u64,
+    // Set on ICRC-2 transfer_from blocks
+    pub spender: Option<Account>,
+}
+
+pub fn nat_to_u64(nat: &Nat) -> Option<u64> {
+    match nat.0.to_u64_digits().as_slice() {
+        [] => Some(0),
+        [value] => Some(*value),
+        _ => None,
+    }
+}
+
+fn field<'a>(map: &'a [(String, Icrc3Value)], name: &str) -> Option<&'a Icrc3Value> {
+    map.iter().find(|(key, _)| key == name).map(|(_, value)| value)
+}
+
+fn value_u64(value: &Icrc3Value) -> Option<u64> {
+    match value {
+        Icrc3Value::Nat(nat) => nat_to_u64(nat),
+        Icrc3Value::Nat64(n) => Some(*n),
+        _ => None,
+    }
+}
+
+fn value_account(value: &Icrc3Value) -> Option<Account> {
+    let Icrc3Value::Array(parts) = value else { return None };
+    match parts.as_slice() {
+        [Icrc3Value::Blob(owner)] => Some(Account {
+            owner: Principal::try_from_slice(owner).ok()?,
+            subaccount: None,
+        }),
+        [Icrc3Value::Blob(owner), Icrc3Value::Blob(sub)] => Some(Account {
+            owner: Principal::try_from_slice(owner).ok()?,
+            subaccount: Some(sub.clone()),
+        }),
+        _ => None,
+    }
+}
+
+// Transfers only; mints, burns and approvals yield None
+pub fn transfer_from_block(block_index: u64, block: &Icrc3Value) -> Option<LedgerTransfer> {
+    let Icrc3Value::Map(block) = block else { return None };
+    let Some(Icrc3Value::Map(tx)) = field(block, "tx") else { return None };
+
+    // Older ledgers tag the operation inside `tx`, ICRC-3 ones on the block
+    let is_transfer = match (field(block, "btype"), field(tx, "op")) {
+        (Some(Icrc3Value::Text(btype)), _) => btype == "1xfer" || btype == "2xfer",
+        (None, Some(Icrc3Value::Text(op))) => op == "xfer",
+        _ => false,
+    };
+    if !is_transfer {
+        return None;
+    }
+
+    Some(LedgerTransfer {
+        block_index,
+        from: value_account(field(tx, "from")?)?,
+        to: value_account(field(tx, "to")?)?,
+        amount: value_u64(field(tx, "amt")?)?,
+        memo: match field(tx, "memo") {
+            Some(Icrc3Value::Blob(memo)) => Some(memo.clone()),
+            _ => None,
+        },
+        timestamp: field(block, "ts").and_then(value_u64).unwrap_or(0),
+        spender: field(tx, "spender").and_then(value_account),
+    })
+}
+
+// (merchant, qr id) when a transfer into one of our merchant subaccounts carries a QR memo.
+// Transfers the canister pulled itself (subscription charges) were recorded
+// when they were made and never match.
+pub fn match_qr_transfer(transfer: &LedgerTransfer, canister_id: Principal) -> Option<(Principal, String)> {
+    if transfer.to.owner != canister_id {
+        return None;
+    }
+    if transfer.spender.as_ref().is_some_and(|spender| spender.owner == canister_id) {
+        return None;
+    }
+    let merchant = merchant_from_subaccount(transfer.to.subaccount.as_deref()?)?;
+    let qr_id = String::from_utf8(transfer.memo.clone()?).ok()?;
+    Some((merchant, qr_id))
+}
+
+// ===================
+// ICRC-2 transfer_from
+// ===================
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct TransferFromArgs {
+    pub spender_subaccount: Option<Vec<u8>>,
+    pub from: Account,
+    pub to: Account,
+    pub amount: Nat,
+    pub fee: Option<Nat>,
+    pub memo: Option<Vec<u8>>,
+    pub created_at_time: Option<u64>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub enum TransferFromError {
+    BadFee { expected_fee: Nat },
+    BadBurn { min_burn_amount: Nat },
+    InsufficientFunds { balance: Nat },
+    InsufficientAllowance { allowance: Nat },
+    TooOld,
+    CreatedInFuture { ledger_time: u64 },
+    Duplicate { duplicate_of: Nat },
+    TemporarilyUnavailable,
+    GenericError { error_code: Nat, message: String },
+}
+
+// Pulls `amount` from an account that approved this canister; returns the block index
+pub async fn transfer_from(
+    ledger: Principal,
+    from: Account,
+    to: Account,
+    amount: u64,
+    memo: Vec<u8>,
+    created_at_time: u64,
+) -> Result<u64, String> {
+    let args = TransferFromArgs {
+        spender_subaccount: None,
+        from,
+        to,
+        amount: amount.into(),
+        fee: None,
+        memo: Some(memo),
+        // Lets the ledger deduplicate a retried call
+        created_at_time: Some(created_at_time),
+    };
+
+    let (result,): (Result<Nat, TransferFromError>,) = ic_cdk::call(ledger, "icrc2_transfer_from", (args,))
+        .await
+        .map_err(|(code, msg)| format!("icrc2_transfer_from failed: {:?} {}", code, msg))?;
+
+    match result {
+        Ok(block) => nat_to_u64(&block).ok_or("Invalid block index".to_string()),
+        Err(TransferFromError::InsufficientAllowance { allowance }) => {
+            Err(format!("Insufficient allowance: {} e8s approved", allowance))
+        }
+        Err(TransferFromError::InsufficientFunds { balance }) => {
+            Err(format!("Insufficient funds: {} e8s available", balance))
+        }
+        Err(e) => Err(format!("Ledger rejected transfer: {:?}", e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn owner() -> Principal {
+        Principal::from_text("k2t6j-2nvnp-4zjm3-25dtz-6xhaa-c7boj-5gayf-oj3xs-i43lp-teztq-6ae").unwrap()
+    }
+
+    #[test]
+    fn test_encode_account() {
+        let account = Account {
+            owner: owner(),
+            subaccount: Some((1..=32).collect()),
+        };
+        assert_eq!(
+            encode_account(&account),
+            "k2t6j-2nvnp-4zjm3-25dtz-6xhaa-c7boj-5gayf-oj3xs-i43lp-teztq-6ae-dfxgiyy.\
+             102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f20"
+        );
+
+        let default = Account { owner: owner(), subaccount: Some(vec![0; 32]) };
+        assert_eq!(encode_account(&default), owner().to_text());
+    }
+
+    #[test]
+    fn test_get_blocks_result_with_archives() {
+        let archive = Principal::from_slice(&[4]);
+        let result = GetBlocksResult {
+            log_length: Nat::from(3_000u64),
+            blocks: vec![],
+            archived_blocks: vec![ArchivedBlocks {
+                args: vec![GetBlocksArgs { start: Nat::from(100u64), length: Nat::from(500u64) }],
+                callback: GetBlocksCallback::new(archive, "get_blocks".to_string()),
+            }],
+        };
+        let bytes = candid::encode_one(&result).unwrap();
+        let decoded: GetBlocksResult = candid::decode_one(&bytes).unwrap();
+        assert_eq!(decoded.archived_blocks[0].callback.0.principal, archive);
+        assert_eq!(decoded.archived_blocks[0].callback.0.method, "get_blocks");
+        assert_eq!(poll_end(100, 500, &[], &decoded.archived_blocks, 3_000), 600);
+    }
+
+    #[test]
+    fn test_poll_coverage() {
+        // Archive answered for 100..102, local blocks 104.. came back
+        let received = [100, 101, 104, 105];
+        assert_eq!(poll_end(100, 500, &received, &[], 3_000), 106);
+        assert_eq!(
+            missing_block_ranges(100, 106, &received),
+            vec![BlockRange { start: 102, end: 104 }]
+        );
+
+        // Nothing came back and no archive was named
+        assert_eq!(poll_end(100, 500, &[], &[], 3_000), 600);
+        assert_eq!(missing_block_ranges(100, 600, &[]), vec![BlockRange { start: 100, end: 600 }]);
+
+        // Caught up with the tip
+        assert_eq!(poll_end(3_000, 500, &[], &[], 3_000), 3_000);
+        assert!(missing_block_ranges(3_000, 3_000, &[]).is_empty());
+    }
+
+    #[test]
+    fn test_merchant_subaccount_round_trip() {
+        let merchant = Principal::from_slice(&[7, 8, 9]);
+        let subaccount = merchant_subaccount(merchant);
+        assert_eq!(&subaccount[..4], &[3, 7, 8, 9]);
+        assert_eq!(merchant_from_subaccount(&subaccount), Some(merchant));
+        assert_eq!(merchant_from_subaccount(&[0; 32]), None);
+    }
+
+    #[test]
+    fn test_build_payment_uri() {
+        let ledger = Principal::from_text("ryjl3-tyaaa-aaaaa-aaaba-cai").unwrap();
+        let to = Account { owner: owner(), subaccount: None };
+
+        assert_eq!(
+            build_payment_uri(ledger, &to, Some(150_000_000), b"AB"),
+            format!("icp:ryjl3-tyaaa-aaaaa-aaaba-cai/transfer?to={}&amount=1.5&memo=4142", owner().to_text())
+        );
+        assert!(!build_payment_uri(ledger, &to, None, b"AB").contains("amount="));
+        assert_eq!(format_token_amount(1), "0.00000001");
+        assert_eq!(format_token_amount(200_000_000), "2");
+    }
+
+    #[test]
+    fn test_transfer_from_block_and_match() {
+        let canister = Principal::from_slice(&[1, 2]);
+        let merchant = Principal::from_slice(&[3, 4]);
+        let payer = Principal::from_slice(&[5, 6]);
+
+        let block = Icrc3Value::Map(vec![
+            ("ts".to_string(), Icrc3Value::Nat(Nat::from(42u64))),
+            ("tx".to_string(), Icrc3Value::Map(vec![
+                ("op".to_string(), Icrc3Value::Text("xfer".to_string())),
+                ("from".to_string(), Icrc3Value::Array(vec![Icrc3Value::Blob(payer.as_slice().to_vec())])),
+                ("to".to_string(), Icrc3Value::Array(vec![
+                    Icrc3Value::Blob(canister.as_slice().to_vec()),
+                    Icrc3Value::Blob(merchant_subaccount(merchant)),
+                ])),
+                ("amt".to_string(), Icrc3Value::Nat(Nat::from(1_000_000u64))),
+                ("memo".to_string(), Icrc3Value::Blob(b"QR123".to_vec())),
+            ])),
+        ]);
+
+        let transfer = transfer_from_block(9, &block).unwrap();
+        assert_eq!(transfer.block_index, 9);
+        assert_eq!(transfer.from.owner, payer);
+        assert_eq!(transfer.amount, 1_000_000);
+        assert_eq!(transfer.timestamp, 42);
+        assert_eq!(match_qr_transfer(&transfer, canister), Some((merchant, "QR123".to_string())));
+        assert_eq!(match_qr_transfer(&transfer, merchant), None);
+
+        let mint = Icrc3Value::Map(vec![
+            ("tx".to_string(), Icrc3Value::Map(vec![
+                ("op".to_string(), Icrc3Value::Text("mint".to_string())),
+            ])),
+        ]);
+        assert_eq!(transfer_from_block(10, &mint), None);
+    }
+
+    #[test]
+    fn test_own_transfer_from_is_not_matched() {
+        let canister = Principal::from_slice(&[1, 2]);
+        let merchant = Principal::from_slice(&[3, 4]);
+        let payer = Principal::from_slice(&[5, 6]);
+        let block = |spender: Principal| Icrc3Value::Map(vec![
+            ("btype".to_string(), Icrc3Value::Text("2xfer".to_string())),
+            ("tx".to_string(), Icrc3Value::Map(vec![
+                ("from".to_string(), Icrc3Value::Array(vec![Icrc3Value::Blob(payer.as_slice().to_vec())])),
+                ("to".to_string(), Icrc3Value::Array(vec![
+                    Icrc3Value::Blob(canister.as_slice().to_vec()),
+                    Icrc3Value::Blob(merchant_subaccount(merchant)),
+                ])),
+                ("spender".to_string(), Icrc3Value::Array(vec![Icrc3Value::Blob(spender.as_slice().to_vec())])),
+                ("amt".to_string(), Icrc3Value::Nat(Nat::from(1_000_000u64))),
+                ("memo".to_string(), Icrc3Value::Blob(b"SUB_1".to_vec())),
+            ])),
+        ]);
+
+        // A subscription charge this canister pulled with transfer_from
+        let charge = transfer_from_block(11, &block(canister)).unwrap();
+        assert_eq!(charge.spender.as_ref().map(|spender| spender.owner), Some(canister));
+        assert_eq!(match_qr_transfer(&charge, canister), None);
+
+        // Someone else's transfer_from into a merchant subaccount still counts
+        let other = transfer_from_block(12, &block(payer)).unwrap();
+        assert_eq!(match_qr_transfer(&other, canister), Some((merchant, "SUB_1".to_string())));
+    }
+}
@@ -0,0 +1,335 @@
+use crate::types::*;
+use crate::money::{div_round, Money, RoundingMode};
+use candid::Principal;
+use ic_cdk::api::time;
+use sha2::{Digest, Sha256};
+
+const MAX_LINE_ITEMS: usize = 50;
+const MAX_ITEM_NAME_LEN: usize = 100;
+const MAX_TEXT_LEN: usize = 500;
+const MAX_QRS_PER_INVOICE: usize = 20;
+const BPS_DENOMINATOR: u128 = 10_000;
+
+pub fn generate_invoice_id(merchant: Principal, sequence: u64) -> String {
+    let combined = format!("invoice-{}-{}-{}", merchant.to_text(), time(), sequence);
+
+    let mut hasher = Sha256::new();
+    hasher.update(combined.as_bytes());
+    let hash = hasher.finalize();
+
+    format!("INV_{:X}", hash)[..20].to_string()
+}
+
+fn parse_line_item(item: InvoiceLineItemInput, currency: &str) -> Result<InvoiceLineItem, String> {
+    let name = item.name.trim().to_string();
+    if name.is_empty() || name.len() > MAX_ITEM_NAME_LEN {
+        return Err(format!("Line item names must be 1-{} characters", MAX_ITEM_NAME_LEN));
+    }
+    if item.quantity == 0 {
+        return Err(format!("Quantity for {} must be at least 1", name));
+    }
+
+    let tax_rate_bps = item.tax_rate_bps.unwrap_or(0);
+    if tax_rate_bps as u128 > BPS_DENOMINATOR {
+        return Err(format!("Tax rate for {} cannot exceed 100%", name));
+    }
+
+    Ok(InvoiceLineItem {
+        unit_price: Money::parse(&item.unit_price, currency)?,
+        name,
+        quantity: item.quantity,
+        tax_rate_bps,
+    })
+}
+
+fn parse_discount(discount: InvoiceDiscountInput, currency: &str) -> Result<InvoiceDiscount, String> {
+    match discount {
+        InvoiceDiscountInput::PercentBps(bps) if bps as u128 > BPS_DENOMINATOR => {
+            Err("Discount cannot exceed 100%".to_string())
+        }
+        InvoiceDiscountInput::PercentBps(bps) => Ok(InvoiceDiscount::PercentBps(bps)),
+        InvoiceDiscountInput::Fixed(amount) => Ok(InvoiceDiscount::Fixed(Money::parse(&amount, currency)?)),
+    }
+}
+
+pub struct InvoiceTotals {
+    pub subtotal: Money,
+    pub discount_amount: Money,
+    pub tax_amount: Money,
+    pub total: Money,
+}
+
+// The discount is taken before tax and spread over the lines by value, so
+// each line is taxed on what the customer actually pays for it.
+pub fn compute_totals(
+    line_items: &[InvoiceLineItem],
+    discount: Option<&InvoiceDiscount>,
+    scale: u8,
+) -> Result<InvoiceTotals, String> {
+    let line_totals: Vec<u128> = line_items
+        .iter()
+        .map(|item| item.unit_price.rescale(scale, RoundingMode::HalfUp).minor_units as u128 * item.quantity as u128)
+        .collect();
+    let subtotal: u128 = line_totals.iter().sum();
+
+    let discount_units = match discount {
+        None => 0,
+        Some(InvoiceDiscount::PercentBps(bps)) => div_round(subtotal * *bps as u128, BPS_DENOMINATOR, RoundingMode::HalfUp),
+        Some(InvoiceDiscount::Fixed(amount)) => amount.rescale(scale, RoundingMode::HalfUp).minor_units as u128,
+    };
+    if discount_units > subtotal {
+        return Err("Discount cannot exceed the subtotal".to_string());
+    }
+
+    let mut tax_units = 0u128;
+    if subtotal > 0 {
+        for (item, line_total) in line_items.iter().zip(&line_totals) {
+            let line_discount = div_round(discount_units * line_total, subtotal, RoundingMode::Down);
+            let taxable = line_total - line_discount;
+            tax_units += div_round(taxable * item.tax_rate_bps as u128, BPS_DENOMINATOR, RoundingMode::HalfUp);
+        }
+    }
+
+    let total = subtotal - discount_units + tax_units;
+    let to_money = |units: u128| {
+        u64::try_from(units)
+            .map(|units| Money::new(units, scale))
+            .map_err(|_| "Invoice amount too large".to_string())
+    };
+
+    Ok(InvoiceTotals {
+        subtotal: to_money(subtotal)?,
+        discount_amount: to_money(discount_units)?,
+        tax_amount: to_money(tax_units)?,
+        total: to_money(total)?,
+    })
+}
+
+fn check_text(label: &str, text: &Option<String>) -> Result<(), String> {
+    if text.as_ref().is_some_and(|text| text.len() > MAX_TEXT_LEN) {
+        return Err(format!("{} must be at most {} characters", label, MAX_TEXT_LEN));
+    }
+    Ok(())
+}
+
+// A draft built from merchant input; editing a draft rebuilds it
+pub fn build_invoice(
+    id: String,
+    merchant: Principal,
+    input: InvoiceInput,
+    created_at: u64,
+    now: u64,
+) -> Result<Invoice, String> {
+    let currency_info = crate::get_currency_info(&input.currency)
+        .filter(|info| info.enabled)
+        .ok_or(format!("Unsupported currency: {}", input.currency))?;
+    let currency = currency_info.code;
+
+    if input.line_items.is_empty() || input.line_items.len() > MAX_LINE_ITEMS {
+        return Err(format!("An invoice needs 1-{} line items", MAX_LINE_ITEMS));
+    }
+    if input.due_date.is_some_and(|due| due <= now) {
+        return Err("Due date must be in the future".to_string());
+    }
+    check_text("Customer reference", &input.customer_reference)?;
+    check_text("Notes", &input.notes)?;
+
+    let line_items = input.line_items
+        .into_iter()
+        .map(|item| parse_line_item(item, &currency))
+        .collect::<Result<Vec<_>, _>>()?;
+    let discount = input.discount
+        .map(|discount| parse_discount(discount, &currency))
+        .transpose()?;
+
+    let totals = compute_totals(&line_items, discount.as_ref(), currency_info.minor_units)?;
+    if totals.total.is_zero() {
+        return Err("Invoice total must be greater than 0".to_string());
+    }
+
+    Ok(Invoice {
+        id,
+        merchant,
+        line_items,
+        discount,
+        subtotal: totals.subtotal,
+        discount_amount: totals.discount_amount,
+        tax_amount: totals.tax_amount,
+        total: totals.total,
+        amount_paid: Money::new(0, totals.total.scale),
+        due_date: input.due_date,
+        customer_reference: input.customer_reference,
+        notes: input.notes,
+        status: InvoiceStatus::Draft,
+        qr_ids: Vec::new(),
+        created_at,
+        issued_at: None,
+        paid_at: None,
+        currency,
+    })
+}
+
+pub fn outstanding(invoice: &Invoice) -> Money {
+    let paid = invoice.amount_paid.rescale(invoice.total.scale, RoundingMode::HalfUp);
+    Money::new(invoice.total.minor_units.saturating_sub(paid.minor_units), invoice.total.scale)
+}
+
+pub fn ensure_draft(invoice: &Invoice) -> Result<(), String> {
+    if invoice.status != InvoiceStatus::Draft {
+        return Err("Only draft invoices can be edited".to_string());
+    }
+    Ok(())
+}
+
+pub fn issue_invoice(invoice: &mut Invoice, now: u64) -> Result<(), String> {
+    ensure_draft(invoice)?;
+    invoice.status = InvoiceStatus::Issued;
+    invoice.issued_at = Some(now);
+    Ok(())
+}
+
+// Money already received has to be refunded before an invoice can be voided
+pub fn void_invoice(invoice: &mut Invoice) -> Result<(), String> {
+    match invoice.status {
+        InvoiceStatus::Draft | InvoiceStatus::Issued => {
+            invoice.status = InvoiceStatus::Void;
+            Ok(())
+        }
+        InvoiceStatus::PartiallyPaid => Err("Invoice has payments and cannot be voided".to_string()),
+        InvoiceStatus::Paid => Err("Invoice is already paid".to_string()),
+        InvoiceStatus::Void => Err("Invoice is already void".to_string()),
+    }
+}
+
+// `reserved` is what open QR codes for this invoice may still collect
+pub fn check_collectable(invoice: &Invoice, amount: &Money, reserved: &Money) -> Result<(), String> {
+    if !matches!(invoice.status, InvoiceStatus::Issued | InvoiceStatus::PartiallyPaid) {
+        return Err(format!("Invoice is {:?} and cannot be paid", invoice.status));
+    }
+    if invoice.qr_ids.len() >= MAX_QRS_PER_INVOICE {
+        return Err(format!("An invoice can have at most {} QR codes", MAX_QRS_PER_INVOICE));
+    }
+    if amount.is_zero() {
+        return Err("Amount must be greater than 0".to_string());
+    }
+
+    let available = outstanding(invoice).minor_units
+        .saturating_sub(reserved.rescale(invoice.total.scale, RoundingMode::HalfUp).minor_units);
+    if amount.rescale(invoice.total.scale, RoundingMode::HalfUp).minor_units > available {
+        return Err(format!(
+            "Amount exceeds the {} {} not yet covered by open QR codes",
+            Money::new(available, invoice.total.scale),
+            invoice.currency
+        ));
+    }
+    Ok(())
+}
+
+pub fn record_invoice_payment(invoice: &mut Invoice, amount: &Money, now: u64) {
+    invoice.amount_paid = invoice.amount_paid.saturating_add(amount);
+    if outstanding(invoice).is_zero() {
+        invoice.status = InvoiceStatus::Paid;
+        invoice.paid_at = Some(now);
+    } else {
+        invoice.status = InvoiceStatus::PartiallyPaid;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(name: &str, quantity: u32, unit_price: &str, tax_rate_bps: Option<u32>) -> InvoiceLineItemInput {
+        InvoiceLineItemInput {
+            name: name.to_string(),
+            quantity,
+            unit_price: unit_price.to_string(),
+            tax_rate_bps,
+        }
+    }
+
+    fn input(items: Vec<InvoiceLineItemInput>, discount: Option<InvoiceDiscountInput>) -> InvoiceInput {
+        InvoiceInput {
+            currency: "USD".to_string(),
+            line_items: items,
+            discount,
+            due_date: None,
+            customer_reference: Some("CUST-7".to_string()),
+            notes: None,
+        }
+    }
+
+    fn merchant() -> Principal {
+        Principal::from_slice(&[1])
+    }
+
+    #[test]
+    fn test_invoice_totals_with_tax_and_discount() {
+        crate::seed_currency_registry();
+
+        let invoice = build_invoice(
+            "INV_1".to_string(),
+            merchant(),
+            input(
+                vec![item("Coffee", 3, "4.50", Some(1000)), item("Book", 1, "20", None)],
+                Some(InvoiceDiscountInput::PercentBps(1000)),
+            ),
+            0,
+            0,
+        ).unwrap();
+
+        // 13.50 + 20.00 = 33.50, less 10% = 30.15; coffee taxed 10% on 12.15
+        assert_eq!(invoice.subtotal, Money::new(3_350, 2));
+        assert_eq!(invoice.discount_amount, Money::new(335, 2));
+        assert_eq!(invoice.tax_amount, Money::new(122, 2));
+        assert_eq!(invoice.total, Money::new(3_137, 2));
+        assert_eq!(invoice.status, InvoiceStatus::Draft);
+    }
+
+    #[test]
+    fn test_invoice_validation() {
+        crate::seed_currency_registry();
+
+        let build = |input| build_invoice("INV_1".to_string(), merchant(), input, 0, 100);
+        assert!(build(input(vec![], None)).is_err());
+        assert!(build(input(vec![item("Coffee", 0, "4.50", None)], None)).is_err());
+        assert!(build(input(vec![item("Coffee", 1, "4.505", None)], None)).is_err());
+        assert!(build(input(vec![item("Coffee", 1, "4.50", Some(10_001))], None)).is_err());
+        assert!(build(input(vec![item("Coffee", 1, "4.50", None)], Some(InvoiceDiscountInput::Fixed("5".to_string())))).is_err());
+
+        let mut past_due = input(vec![item("Coffee", 1, "4.50", None)], None);
+        past_due.due_date = Some(50);
+        assert!(build(past_due).is_err());
+    }
+
+    #[test]
+    fn test_invoice_partial_payments() {
+        crate::seed_currency_registry();
+
+        let mut invoice = build_invoice(
+            "INV_1".to_string(),
+            merchant(),
+            input(vec![item("Plan", 1, "100", None)], None),
+            0,
+            0,
+        ).unwrap();
+
+        assert!(check_collectable(&invoice, &Money::new(1_000, 2), &Money::new(0, 2)).is_err());
+        issue_invoice(&mut invoice, 1).unwrap();
+
+        let nothing = Money::new(0, 2);
+        assert!(check_collectable(&invoice, &Money::new(10_001, 2), &nothing).is_err());
+        assert!(check_collectable(&invoice, &Money::new(6_000, 2), &Money::new(5_000, 2)).is_err());
+        assert!(check_collectable(&invoice, &Money::new(4_000, 2), &nothing).is_ok());
+
+        record_invoice_payment(&mut invoice, &Money::new(4_000, 2), 2);
+        assert_eq!(invoice.status, InvoiceStatus::PartiallyPaid);
+        assert_eq!(outstanding(&invoice), Money::new(6_000, 2));
+        assert!(void_invoice(&mut invoice).is_err());
+
+        record_invoice_payment(&mut invoice, &Money::new(6_000, 2), 3);
+        assert_eq!(invoice.status, InvoiceStatus::Paid);
+        assert_eq!(invoice.paid_at, Some(3));
+        assert!(check_collectable(&invoice, &Money::new(1, 2), &nothing).is_err());
+    }
+}
@@ -0,0 +1,187 @@
+use crate::types::*;
+use candid::Principal;
+use sha2::{Digest, Sha256};
+
+const MAX_KYC_DOCUMENTS: usize = 10;
+const MAX_REFERENCE_LEN: usize = 512;
+const MAX_REVIEW_NOTE_LEN: usize = 1000;
+
+pub fn generate_kyc_submission_id(user_id: Principal, now: u64) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(format!("{}-{}", user_id.to_text(), now).as_bytes());
+    format!("KYC_{:X}", hasher.finalize())[..20].to_string()
+}
+
+// Salted with the submitter so equal references from different users
+// do not share a hash
+pub fn hash_document_reference(user_id: Principal, reference: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(user_id.as_slice());
+    hasher.update(reference.trim().as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+fn is_identity_document(document_type: KycDocumentType) -> bool {
+    matches!(
+        document_type,
+        KycDocumentType::NationalId | KycDocumentType::Passport | KycDocumentType::DriversLicense
+    )
+}
+
+// Basic needs an identity document; Full also needs proof of address
+pub fn validate_kyc_documents(requested_tier: KycTier, documents: &[KycDocumentInput]) -> Result<(), String> {
+    if documents.is_empty() || documents.len() > MAX_KYC_DOCUMENTS {
+        return Err(format!("Submit between 1 and {} documents", MAX_KYC_DOCUMENTS));
+    }
+    for document in documents {
+        let reference = document.reference.trim();
+        if reference.is_empty() || reference.len() > MAX_REFERENCE_LEN {
+            return Err(format!("Document references must be 1-{} characters", MAX_REFERENCE_LEN));
+        }
+    }
+
+    let has = |check: fn(KycDocumentType) -> bool| documents.iter().any(|d| check(d.document_type));
+    match requested_tier {
+        KycTier::None => return Err("Choose the Basic or Full tier".to_string()),
+        KycTier::Basic => {}
+        KycTier::Full => {
+            if !has(|t| t == KycDocumentType::ProofOfAddress) {
+                return Err("The Full tier needs a proof of address".to_string());
+            }
+        }
+    }
+    if !has(is_identity_document) {
+        return Err("An identity document is required".to_string());
+    }
+    Ok(())
+}
+
+fn tier_rank(tier: KycTier) -> u8 {
+    match tier {
+        KycTier::None => 0,
+        KycTier::Basic => 1,
+        KycTier::Full => 2,
+    }
+}
+
+pub fn build_kyc_submission(
+    id: String,
+    user: &User,
+    requested_tier: KycTier,
+    documents: Vec<KycDocumentInput>,
+    now: u64,
+) -> Result<KycSubmission, String> {
+    if tier_rank(requested_tier) <= tier_rank(user.kyc_tier) {
+        return Err(format!("You are already at the {:?} tier", user.kyc_tier));
+    }
+    validate_kyc_documents(requested_tier, &documents)?;
+
+    Ok(KycSubmission {
+        id,
+        user_id: user.id,
+        requested_tier,
+        documents: documents.into_iter()
+            .map(|document| KycDocument {
+                document_type: document.document_type,
+                reference_hash: hash_document_reference(user.id, &document.reference),
+            })
+            .collect(),
+        status: KycSubmissionStatus::Pending,
+        submitted_at: now,
+        reviewed_at: None,
+        reviewed_by: None,
+        review_note: None,
+    })
+}
+
+pub fn review_submission(
+    submission: &mut KycSubmission,
+    approve: bool,
+    admin: Principal,
+    note: Option<String>,
+    now: u64,
+) -> Result<(), String> {
+    if submission.status != KycSubmissionStatus::Pending {
+        return Err(format!("Submission was already reviewed ({:?})", submission.status));
+    }
+    let note = note.map(|note| note.trim().to_string()).filter(|note| !note.is_empty());
+    if note.as_ref().is_some_and(|note| note.chars().count() > MAX_REVIEW_NOTE_LEN) {
+        return Err(format!("Notes must be at most {} characters", MAX_REVIEW_NOTE_LEN));
+    }
+    if !approve && note.is_none() {
+        return Err("Give the user a reason for the rejection".to_string());
+    }
+
+    submission.status = if approve { KycSubmissionStatus::Approved } else { KycSubmissionStatus::Rejected };
+    submission.reviewed_at = Some(now);
+    submission.reviewed_by = Some(admin);
+    submission.review_note = note;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(tier: KycTier) -> User {
+        User {
+            id: Principal::from_slice(&[1]),
+            wallet_address: String::new(),
+            created_at: 0,
+            username: None,
+            email: None,
+            balance: 0,
+            kyc_tier: tier,
+            kyc_verified_at: None,
+        }
+    }
+
+    fn document(document_type: KycDocumentType, reference: &str) -> KycDocumentInput {
+        KycDocumentInput { document_type, reference: reference.to_string() }
+    }
+
+    #[test]
+    fn test_documents_per_tier() {
+        let id = document(KycDocumentType::Passport, "vault://docs/123");
+        let address = document(KycDocumentType::ProofOfAddress, "vault://docs/456");
+
+        assert!(validate_kyc_documents(KycTier::Basic, std::slice::from_ref(&id)).is_ok());
+        assert!(validate_kyc_documents(KycTier::Basic, std::slice::from_ref(&address)).is_err());
+        assert!(validate_kyc_documents(KycTier::Full, std::slice::from_ref(&id)).is_err());
+        assert!(validate_kyc_documents(KycTier::Full, &[id.clone(), address]).is_ok());
+        assert!(validate_kyc_documents(KycTier::None, &[id]).is_err());
+        assert!(validate_kyc_documents(KycTier::Basic, &[document(KycDocumentType::Passport, " ")]).is_err());
+    }
+
+    #[test]
+    fn test_submission_stores_only_hashes() {
+        let submission = build_kyc_submission(
+            "KYC_1".to_string(),
+            &user(KycTier::None),
+            KycTier::Basic,
+            vec![document(KycDocumentType::NationalId, "vault://docs/123")],
+            10,
+        )
+        .unwrap();
+        let hash = &submission.documents[0].reference_hash;
+        assert_eq!(hash.len(), 64);
+        assert_eq!(*hash, hash_document_reference(Principal::from_slice(&[1]), " vault://docs/123 "));
+        assert_ne!(*hash, hash_document_reference(Principal::from_slice(&[2]), "vault://docs/123"));
+
+        let docs = vec![document(KycDocumentType::NationalId, "vault://docs/123")];
+        assert!(build_kyc_submission("KYC_2".to_string(), &user(KycTier::Basic), KycTier::Basic, docs, 10).is_err());
+    }
+
+    #[test]
+    fn test_review() {
+        let admin = Principal::from_slice(&[9]);
+        let docs = vec![document(KycDocumentType::NationalId, "vault://docs/123")];
+        let mut submission = build_kyc_submission("KYC_1".to_string(), &user(KycTier::None), KycTier::Basic, docs, 10).unwrap();
+
+        assert!(review_submission(&mut submission, false, admin, None, 20).is_err());
+        review_submission(&mut submission, true, admin, None, 20).unwrap();
+        assert_eq!(submission.status, KycSubmissionStatus::Approved);
+        assert_eq!(submission.reviewed_by, Some(admin));
+        assert!(review_submission(&mut submission, false, admin, Some("Blurry".to_string()), 30).is_err());
+    }
+}
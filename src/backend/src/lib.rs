@@ -2,28 +2,64 @@ use candid::{candid_method, Principal};
 use ic_cdk::api::management_canister::http_request::{
     HttpResponse, TransformArgs,
 };
-use ic_cdk::{caller, query, update};
-use ic_cdk_macros::{heartbeat, init, post_upgrade, pre_upgrade};
+use ic_cdk::{caller, heartbeat, init, post_upgrade, pre_upgrade, query, update};
 use ic_stable_structures::memory_manager::{MemoryId, MemoryManager, VirtualMemory};
 use ic_stable_structures::{DefaultMemoryImpl, StableBTreeMap};
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 use ic_cdk::api::time;
 
 use sha2::{Sha256, Digest};
 use base64;
 
 mod types;
+mod money;
+mod currencies;
+mod emv;
+mod formatting;
 mod rates;
+mod rate_history;
 mod qr;
+mod qr_render;
 mod transactions;
 mod topup;
+mod http;
+mod config;
+mod icrc;
+mod invoices;
+mod subscriptions;
+mod payment_requests;
+mod split_bills;
+mod payouts;
+mod escrow;
+mod disputes;
+mod limits;
+mod kyc;
+mod risk;
 
 use types::*;
+use money::Money;
+use currencies::*;
+use emv::*;
+use formatting::*;
 use rates::*;
+use rate_history::*;
 use qr::*;
+use qr_render::*;
 use transactions::*;
 use topup::*;
+use http::{HttpRequest, Route};
+use invoices::*;
+use subscriptions::*;
+use payment_requests::*;
+use split_bills::*;
+use payouts::*;
+use escrow::*;
+use disputes::*;
+use limits::*;
+use kyc::*;
+use risk::*;
+use icrc::{Account, ArchivedBlocks, BlockWithId, GetBlocksArgs, GetBlocksResult, LedgerTransfer};
 
 type Memory = VirtualMemory<DefaultMemoryImpl>;
 type UserStore = StableBTreeMap<Principal, User, Memory>;
@@ -31,6 +67,23 @@ type TransactionStore = StableBTreeMap<String, Transaction, Memory>;
 type QRStore = StableBTreeMap<String, QRCode, Memory>;
 type TopUpStore = StableBTreeMap<String, TopUpTransaction, Memory>;
 type WalletIdentityStore = StableBTreeMap<String, EncryptedWalletIdentity, Memory>;
+type CurrencyStore = StableBTreeMap<String, CurrencyInfo, Memory>;
+type RateHistoryStore = StableBTreeMap<String, RateCandle, Memory>;
+type ConfigStore = StableBTreeMap<u8, CanisterConfig, Memory>;
+type ConfigHistoryStore = StableBTreeMap<u64, ConfigChange, Memory>;
+type LedgerWatcherStore = StableBTreeMap<u8, LedgerWatcherState, Memory>;
+type UnmatchedTransferStore = StableBTreeMap<String, UnmatchedLedgerTransfer, Memory>;
+type InvoiceStore = StableBTreeMap<String, Invoice, Memory>;
+type PlanStore = StableBTreeMap<String, SubscriptionPlan, Memory>;
+type SubscriptionStore = StableBTreeMap<String, Subscription, Memory>;
+type SubscriptionChargeStore = StableBTreeMap<String, SubscriptionCharge, Memory>;
+type PaymentRequestStore = StableBTreeMap<String, PaymentRequest, Memory>;
+type SplitBillStore = StableBTreeMap<String, SplitBill, Memory>;
+type PayoutBatchStore = StableBTreeMap<String, PayoutBatch, Memory>;
+type EscrowStore = StableBTreeMap<String, Escrow, Memory>;
+type DisputeStore = StableBTreeMap<String, Dispute, Memory>;
+type KycSubmissionStore = StableBTreeMap<String, KycSubmission, Memory>;
+type RiskAssessmentStore = StableBTreeMap<String, RiskAssessment, Memory>;
 
 thread_local! {
     static MEMORY_MANAGER: RefCell<MemoryManager<DefaultMemoryImpl>> = RefCell::new(
@@ -74,10 +127,108 @@ thread_local! {
         StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(8))))
     );
     
+    static CURRENCY_REGISTRY: RefCell<CurrencyStore> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(9))))
+    );
+    
+    static RATE_HISTORY: RefCell<RateHistoryStore> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(10))))
+    );
+    
+    // Single entry under CONFIG_KEY
+    static CONFIG: RefCell<ConfigStore> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(12))))
+    );
+    
+    // Keyed by change id, oldest first
+    static CONFIG_HISTORY: RefCell<ConfigHistoryStore> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(13))))
+    );
+    
+    // Single entry under LEDGER_WATCHER_KEY
+    static LEDGER_WATCHER: RefCell<LedgerWatcherStore> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(14))))
+    );
+    
+    static INVOICES: RefCell<InvoiceStore> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(15))))
+    );
+    
+    static SUBSCRIPTION_PLANS: RefCell<PlanStore> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(16))))
+    );
+    
+    static SUBSCRIPTIONS: RefCell<SubscriptionStore> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(17))))
+    );
+    
+    // Keyed by "<subscription id>_<sequence>" so a subscription's charges are one range
+    static SUBSCRIPTION_CHARGES: RefCell<SubscriptionChargeStore> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(18))))
+    );
+    
+    static PAYMENT_REQUESTS: RefCell<PaymentRequestStore> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(19))))
+    );
+    
+    static SPLIT_BILLS: RefCell<SplitBillStore> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(20))))
+    );
+    
+    static PAYOUT_BATCHES: RefCell<PayoutBatchStore> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(21))))
+    );
+    
+    static ESCROWS: RefCell<EscrowStore> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(22))))
+    );
+    
+    static DISPUTES: RefCell<DisputeStore> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(23))))
+    );
+    
+    static KYC_SUBMISSIONS: RefCell<KycSubmissionStore> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(24))))
+    );
+    
+    static RISK_ASSESSMENTS: RefCell<RiskAssessmentStore> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(25))))
+    );
+    
+    static UNMATCHED_LEDGER_TRANSFERS: RefCell<UnmatchedTransferStore> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(26))))
+    );
+    
+    // Tasks running across awaits; see InFlight
+    static IN_FLIGHT: RefCell<BTreeSet<String>> = const { RefCell::new(BTreeSet::new()) };
+    
     static EXCHANGE_RATES: RefCell<HashMap<String, ExchangeRate>> = RefCell::new(HashMap::new());
 
 }
 
+const LEDGER_POLL_TASK: &str = "ledger_poll";
+const SUBSCRIPTION_RUN_TASK: &str = "subscription_run";
+
+// Marks a task as running for as long as it is held. It is dropped on every
+// exit, including a trap in a callback (the call context is cleaned up), so
+// the task never stays stuck as running.
+struct InFlight(String);
+
+impl InFlight {
+    fn claim(task: &str) -> Option<InFlight> {
+        IN_FLIGHT.with(|tasks| tasks.borrow_mut().insert(task.to_string()))
+            .then(|| InFlight(task.to_string()))
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        IN_FLIGHT.with(|tasks| {
+            tasks.borrow_mut().remove(&self.0);
+        });
+    }
+}
+
 fn simple_encrypt(data: &str, password: &str) -> String {
 
     let mut hasher = Sha256::new();
@@ -118,13 +269,7 @@ fn generate_balance_log_id() -> String {
     let timestamp = time();
     let caller = caller();
     let random_suffix = timestamp % 1000000; 
-    format!("BAL_{}_{}", caller.to_text()[..8].to_string(), timestamp + random_suffix)
-}
-
-fn generate_qr_usage_log_id() -> String {
-    let timestamp = time();
-    let caller = caller();
-    format!("QRU_{}_{}", caller.to_text()[..8].to_string(), timestamp)
+    format!("BAL_{}_{}", &caller.to_text()[..8], timestamp + random_suffix)
 }
 
 fn get_current_balance(user_id: Principal) -> u64 {
@@ -147,7 +292,7 @@ fn get_current_balance(user_id: Principal) -> u64 {
         }
         
         // Sort by timestamp (oldest first for sequential calculation)
-        user_logs.sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
+        user_logs.sort_by_key(|log| log.timestamp);
         
 
         let mut calculated_balance = 0u64;
@@ -167,7 +312,7 @@ fn get_current_balance(user_id: Principal) -> u64 {
                 BalanceChangeType::TopupCompleted | BalanceChangeType::PaymentReceived => {
                     calculated_balance = calculated_balance.saturating_add(log.amount);
                 }
-                BalanceChangeType::PaymentSent | BalanceChangeType::FeeDeducted => {
+                BalanceChangeType::PaymentSent | BalanceChangeType::FeeDeducted | BalanceChangeType::Chargeback => {
                     calculated_balance = calculated_balance.saturating_sub(log.amount);
                 }
                 BalanceChangeType::Refund => {
@@ -247,31 +392,190 @@ fn create_qr_usage_log(
     transaction_id: String,
     usage_type: QRUsageType,
 ) -> QRUsageLog {
-    let log = QRUsageLog {
-        id: generate_qr_usage_log_id(),
-        qr_id,
-        user_id,
-        used_by,
-        transaction_id,
-        timestamp: time(),
-        usage_type,
-    };
-    
+    let log = build_qr_usage_log(qr_id, user_id, used_by, transaction_id, usage_type, time());
+    save_qr_usage_log(&log);
+    log
+}
+
+fn save_qr_usage_log(log: &QRUsageLog) {
     QR_USAGE_LOGS.with(|logs| {
         logs.borrow_mut().insert(log.id.clone(), log.clone());
     });
-    
-    log
 }
 
-fn is_qr_already_used(qr_id: &str) -> bool {
-    QR_USAGE_LOGS.with(|logs| {
+fn count_qr_payments(qr_id: &str) -> u64 {
+    let logs: Vec<QRUsageLog> = QR_USAGE_LOGS.with(|logs| {
         logs.borrow()
             .iter()
-            .any(|(_, log)| log.qr_id == qr_id && matches!(log.usage_type, QRUsageType::PaymentCompleted))
+            .filter(|(_, log)| log.qr_id == qr_id)
+            .map(|(_, log)| log)
+            .collect()
+    });
+    count_qr_uses(&logs)
+}
+
+fn current_qr_policy() -> QRPolicy {
+    current_config().qr_policy
+}
+
+const CONFIG_KEY: u8 = 0;
+
+fn current_config() -> CanisterConfig {
+    CONFIG.with(|config| config.borrow().get(&CONFIG_KEY)).unwrap_or_default()
+}
+
+fn public_base_url() -> String {
+    current_config().public_base_url
+}
+
+// All-or-nothing: the config is validated as a whole before anything is stored
+fn apply_config_updates(updates: Vec<ConfigUpdate>, changed_by: Principal) -> Result<CanisterConfig, String> {
+    let mut config = current_config();
+    let mut changes = Vec::new();
+    for update in updates {
+        changes.push(config::apply_update(&mut config, update)?);
+    }
+    config::validate_config(&config)?;
+
+    CONFIG.with(|store| {
+        store.borrow_mut().insert(CONFIG_KEY, config.clone());
+    });
+
+    let now = time();
+    CONFIG_HISTORY.with(|history| {
+        let mut history = history.borrow_mut();
+        for (field, old_value, new_value) in changes {
+            if old_value == new_value {
+                continue;
+            }
+            let id = history.last_key_value().map(|(id, _)| id + 1).unwrap_or(0);
+            ic_cdk::println!("🔧 Config {}: {} -> {} by {}", field, old_value, new_value, changed_by.to_text());
+            history.insert(id, ConfigChange {
+                id,
+                field,
+                old_value,
+                new_value,
+                changed_by,
+                changed_at: now,
+            });
+        }
+    });
+
+    Ok(config)
+}
+
+// Install/upgrade arguments; a bad value traps so the deploy fails loudly
+fn apply_canister_args(args: Option<CanisterArgs>) {
+    let updates = config::args_to_updates(args.unwrap_or_default());
+    let config = apply_config_updates(updates, caller())
+        .unwrap_or_else(|e| ic_cdk::trap(&e));
+    ic_cdk::println!("🌐 Public base URL: {}", config.public_base_url);
+}
+
+// Controllers administer the canister
+fn ensure_admin() -> Result<Principal, String> {
+    let caller = caller();
+    if !ic_cdk::api::is_controller(&caller) {
+        return Err("Only admins can perform this action".to_string());
+    }
+    Ok(caller)
+}
+
+fn get_currency_info(code: &str) -> Option<CurrencyInfo> {
+    let code_upper = code.to_uppercase();
+    CURRENCY_REGISTRY.with(|registry| registry.borrow().get(&code_upper))
+}
+
+fn get_enabled_currencies() -> Vec<CurrencyInfo> {
+    CURRENCY_REGISTRY.with(|registry| {
+        registry.borrow()
+            .iter()
+            .filter(|(_, info)| info.enabled)
+            .map(|(_, info)| info)
+            .collect()
+    })
+}
+
+// Called for every live rate fetch
+fn record_rate_sample(rate: &ExchangeRate) {
+    let key = rate_history_key(&rate.currency, bucket_start(rate.timestamp, BASE_BUCKET_SECONDS));
+
+    RATE_HISTORY.with(|history| {
+        let mut history = history.borrow_mut();
+        let candle = match history.get(&key) {
+            Some(mut candle) => {
+                apply_sample(&mut candle, rate.rate, rate.timestamp);
+                candle
+            }
+            None => new_candle(&rate.currency, rate.rate, rate.timestamp),
+        };
+        history.insert(key, candle);
+    });
+}
+
+// Cached rate if we have one, otherwise the latest recorded in history
+fn lookup_known_rate(currency: &str) -> Option<ExchangeRate> {
+    let currency_upper = currency.to_uppercase();
+    EXCHANGE_RATES.with(|rates| rates.borrow().get(&currency_upper).cloned())
+        .or_else(|| get_rate_at(currency_upper, time()).ok())
+}
+
+fn convert_with_known_rates(amount: &Money, from_currency: &str, to_currency: &str) -> Result<CurrencyConversion, String> {
+    let from_upper = from_currency.to_uppercase();
+    let to_upper = to_currency.to_uppercase();
+
+    if from_upper == to_upper {
+        return Ok(CurrencyConversion {
+            amount: *amount,
+            from_currency: from_upper.clone(),
+            converted_amount: *amount,
+            to_currency: to_upper,
+            cross_rate: 1.0,
+            rates_used: vec![],
+        });
+    }
+
+    let from_rate = lookup_known_rate(&from_upper)
+        .ok_or(format!("No {} rate available", from_upper))?;
+    let to_rate = lookup_known_rate(&to_upper)
+        .ok_or(format!("No {} rate available", to_upper))?;
+
+    convert_amount(amount, &from_rate, &to_rate)
+}
+
+fn payer_equivalent(qr_code: &QRCode, payer: Principal) -> Option<CurrencyConversion> {
+    let preferred = USER_PREFERENCES.with(|prefs| prefs.borrow().get(&payer))?
+        .preferred_currency
+        .to_uppercase();
+
+    if preferred == qr_code.fiat_currency || !is_supported_currency(&preferred) {
+        return None;
+    }
+
+    convert_with_known_rates(&qr_code.fiat_amount, &qr_code.fiat_currency, &preferred).ok()
+}
+
+fn get_user_language(user_id: Principal) -> String {
+    USER_PREFERENCES.with(|prefs| {
+        prefs.borrow()
+            .get(&user_id)
+            .map(|prefs| prefs.language)
+            .unwrap_or_else(|| DEFAULT_LANGUAGE.to_string())
     })
 }
 
+fn seed_currency_registry() {
+    CURRENCY_REGISTRY.with(|registry| {
+        let mut registry = registry.borrow_mut();
+        if !registry.is_empty() {
+            return;
+        }
+        for info in default_currencies() {
+            registry.insert(info.code.clone(), info);
+        }
+    });
+}
+
 // ===================
 // USER MANAGEMENT
 // ===================
@@ -462,6 +766,8 @@ async fn register_user_by_email(
         username,
         email: Some(email),
         balance: 0,
+        kyc_tier: KycTier::None,
+        kyc_verified_at: None,
     };
 
     USERS.with(|users| {
@@ -528,7 +834,7 @@ fn debug_get_all_users() -> Vec<(String, User)> {
 #[query]
 #[candid_method(query)]
 fn debug_get_user_count() -> u64 {
-    USERS.with(|users| users.borrow().len() as u64)
+    USERS.with(|users| users.borrow().len())
 }
 
 #[update]
@@ -624,6 +930,8 @@ async fn register_user(wallet_address: String, username: Option<String>, email:
         username,
         email,
         balance: 0, 
+        kyc_tier: KycTier::None,
+        kyc_verified_at: None,
     };
 
     USERS.with(|users| {
@@ -745,15 +1053,68 @@ fn get_user_by_id(user_id: Principal) -> Option<User> {
 fn get_user_balance() -> Option<UserBalance> {
     let caller = caller();
     let current_balance = get_current_balance(caller);
+    let reserved = reserved_balance(caller, current_balance);
+    let (held, pending) = incoming_holds(caller);
     
     Some(UserBalance {
         user_id: caller,
         balance: current_balance,
-        formatted_balance: format_balance(current_balance),
+        formatted_balance: format_icp(current_balance, &get_user_language(caller)),
         last_updated: time(),
+        held,
+        reserved,
+        available: current_balance - reserved,
+        pending,
+        total: current_balance.saturating_add(held).saturating_add(pending),
     })
 }
 
+// Part of `balance` reserved for open disputes the user may have to refund
+fn reserved_balance(user_id: Principal, balance: u64) -> u64 {
+    let disputed: u64 = DISPUTES.with(|disputes| {
+        disputes.borrow()
+            .iter()
+            .filter(|(_, dispute)| dispute.merchant == user_id && is_dispute_open(dispute))
+            .map(|(_, dispute)| dispute.icp_amount)
+            .sum()
+    });
+    disputed.min(balance)
+}
+
+// What the user can spend right now
+fn get_available_balance(user_id: Principal) -> u64 {
+    let balance = get_current_balance(user_id);
+    balance - reserved_balance(user_id, balance)
+}
+
+// Payments to the user still in the escrow account: escrowed or under review
+// (held) and split-bill contributions waiting for the bill to be covered
+// (pending)
+fn incoming_holds(user_id: Principal) -> (u64, u64) {
+    let account = escrow_account();
+    let account_logs: Vec<BalanceChangeLog> = BALANCE_CHANGE_LOGS.with(|logs| {
+        logs.borrow()
+            .iter()
+            .filter(|(_, log)| log.user_id == account)
+            .map(|(_, log)| log)
+            .collect()
+    });
+
+    let (mut held, mut pending) = (0u64, 0u64);
+    for (reference_id, amount) in open_holds(&account_logs) {
+        let Some(held_tx) = TRANSACTIONS.with(|transactions| transactions.borrow().get(&reference_id)) else { continue };
+        if held_tx.to != user_id {
+            continue;
+        }
+        if held_tx.kind == TransactionKind::SplitBill {
+            pending = pending.saturating_add(amount);
+        } else {
+            held = held.saturating_add(amount);
+        }
+    }
+    (held, pending)
+}
+
 #[query]
 #[candid_method(query)]
 fn get_user_balance_history() -> Vec<BalanceChangeLog> {
@@ -767,7 +1128,7 @@ fn get_user_balance_history() -> Vec<BalanceChangeLog> {
             .collect();
         
         // Sort by timestamp (newest first)
-        user_logs.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+        user_logs.sort_by_key(|log| std::cmp::Reverse(log.timestamp));
         user_logs
     })
 }
@@ -783,11 +1144,40 @@ fn get_qr_usage_history(qr_id: String) -> Vec<QRUsageLog> {
             .collect();
         
         // Sort by timestamp (newest first)
-        qr_logs.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+        qr_logs.sort_by_key(|log| std::cmp::Reverse(log.timestamp));
         qr_logs
     })
 }
 
+// Only the QR owner can see how their code performs
+#[query]
+#[candid_method(query)]
+fn get_qr_usage_stats(qr_id: String) -> Result<QRUsageStats, String> {
+    let qr_code = QR_CODES.with(|qr_codes| qr_codes.borrow().get(&qr_id))
+        .ok_or("QR code not found")?;
+
+    if qr_code.user_id != caller() {
+        return Err("Only the QR code owner can view its stats".to_string());
+    }
+
+    let usage_logs: Vec<QRUsageLog> = QR_USAGE_LOGS.with(|logs| {
+        logs.borrow()
+            .iter()
+            .filter(|(_, log)| log.qr_id == qr_id)
+            .map(|(_, log)| log)
+            .collect()
+    });
+
+    let transactions: Vec<Transaction> = TRANSACTIONS.with(|transactions| {
+        let transactions = transactions.borrow();
+        usage_logs.iter()
+            .filter_map(|log| transactions.get(&log.transaction_id))
+            .collect()
+    });
+
+    Ok(summarize_qr_usage(&qr_code, &usage_logs, &transactions))
+}
+
 #[query]
 #[candid_method(query)]
 fn get_all_balance_changes() -> Vec<BalanceChangeLog> {
@@ -798,7 +1188,7 @@ fn get_all_balance_changes() -> Vec<BalanceChangeLog> {
             .collect();
         
         // Sort by timestamp (newest first)
-        all_logs.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+        all_logs.sort_by_key(|log| std::cmp::Reverse(log.timestamp));
         all_logs
     })
 }
@@ -813,15 +1203,22 @@ fn get_all_qr_usage_logs() -> Vec<QRUsageLog> {
             .collect();
         
 
-        all_logs.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+        all_logs.sort_by_key(|log| std::cmp::Reverse(log.timestamp));
         all_logs
     })
 }
 
+// Validate a scanned EMVCo merchant QR (QRIS and friends) and return its fields
+#[query]
+#[candid_method(query)]
+fn parse_emv_qr(payload: String) -> Result<EmvPayload, String> {
+    decode_payload(&payload)
+}
+
 #[update]
 #[candid_method(update)]
 async fn create_qris_topup(
-    amount: f64,
+    amount: String,
     currency: String,
 ) -> Result<TopUpTransaction, String> {
     let caller = caller();
@@ -834,11 +1231,15 @@ async fn create_qris_topup(
         return Err("User not registered".to_string());
     }
     
-    if amount <= 0.0 {
+    let amount = Money::parse(&amount, &currency)?;
+    if amount.is_zero() {
         return Err("Amount must be greater than 0".to_string());
     }
     
     let topup = topup::create_qris_topup(caller, amount, currency).await?;
+    check_topup_limits(user_tier(caller), caller, topup.amount)?;
+    let context = risk_context(RiskAction::QrisTopup, caller, None, topup.amount);
+    let risk = screen_risk(caller, None, &context)?;
     
     // Debug print
     ic_cdk::print(format!("QRIS topup created: id={}, method={:?}", topup.id, topup.payment_method));
@@ -847,6 +1248,10 @@ async fn create_qris_topup(
         topups.borrow_mut().insert(topup.id.clone(), topup.clone());
     });
     
+    // A held QRIS top-up cannot be claimed until an admin approves it
+    let held = (risk.decision == RiskDecision::Review).then(|| topup.id.clone());
+    record_risk_assessment(caller, None, &context, risk, held);
+    
     Ok(topup)
 }
 
@@ -855,7 +1260,7 @@ async fn create_qris_topup(
 #[update]
 #[candid_method(update)]
 async fn create_card_topup(
-    amount: f64,
+    amount: String,
     currency: String,
     card_data: CardDataInput,
     is_credit: bool,
@@ -868,14 +1273,15 @@ async fn create_card_topup(
         return Err("User not registered".to_string());
     }
     
-    if amount <= 0.0 {
+    let amount = Money::parse(&amount, &currency)?;
+    if amount.is_zero() {
         return Err("Amount must be greater than 0".to_string());
     }
     
-    let current_time = time();
-    
-
     let pending_topup = topup::create_card_topup(caller, amount, currency, card_data, is_credit).await?;
+    check_topup_limits(user_tier(caller), caller, pending_topup.amount)?;
+    let context = risk_context(RiskAction::CardTopup, caller, None, pending_topup.amount);
+    let risk = screen_risk(caller, None, &context)?;
     
     TOPUP_TRANSACTIONS.with(|topups| {
         topups.borrow_mut().insert(pending_topup.id.clone(), pending_topup.clone());
@@ -883,6 +1289,22 @@ async fn create_card_topup(
     
     ic_cdk::println!("📝 Created PENDING card topup: {}", pending_topup.id);
     
+    // A held card top-up stays PENDING until an admin approves the charge
+    let held = (risk.decision == RiskDecision::Review).then(|| pending_topup.id.clone());
+    record_risk_assessment(caller, None, &context, risk, held.clone());
+    if held.is_some() {
+        return Ok(pending_topup);
+    }
+    process_card_topup(&pending_topup)
+}
+
+// Charges the card and credits the user
+fn process_card_topup(pending_topup: &TopUpTransaction) -> Result<TopUpTransaction, String> {
+    let caller = pending_topup.user_id;
+    // Checked again at charge time: other top-ups may have completed since
+    // this one was created, or the tier may have changed
+    check_topup_limits(user_tier(caller), caller, pending_topup.amount)?;
+    let current_time = time();
 
     let processing_topup = TopUpTransaction {
         id: format!("{}_PROCESSING_{}", pending_topup.id, current_time),
@@ -919,6 +1341,8 @@ async fn create_card_topup(
                     username: user.username.clone(),
                     email: user.email.clone(),
                     balance: user.balance.saturating_add(pending_topup.amount),
+                    kyc_tier: user.kyc_tier,
+                    kyc_verified_at: user.kyc_verified_at,
                 };
                 
                 users_borrow.insert(caller, updated_user);
@@ -1010,6 +1434,17 @@ async fn claim_qris_payment(topup_id: String) -> Result<TopUpTransaction, String
     if original_topup.status != TopUpStatus::Pending {
         return Err("Top-up already processed".to_string());
     }
+    // Card top-ups are charged by the canister, never claimed
+    if original_topup.payment_method != TopUpMethod::QRIS {
+        return Err("Only QRIS top-ups can be claimed".to_string());
+    }
+    match risk_review_for(&topup_id).and_then(|assessment| assessment.review_status) {
+        Some(RiskReviewStatus::Pending) => return Err("Top-up is under review".to_string()),
+        Some(RiskReviewStatus::Rejected) => return Err("Top-up was declined after review".to_string()),
+        _ => {}
+    }
+    // Other top-ups may have completed since this one was created
+    check_topup_limits(user_tier(original_topup.user_id), original_topup.user_id, original_topup.amount)?;
     
     let current_time = time();
     
@@ -1116,7 +1551,7 @@ fn get_user_topup_history() -> Vec<TopUpTransaction> {
             .collect();
         
         // Sort by timestamp (newest first)
-        user_topups.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+        user_topups.sort_by_key(|topup| std::cmp::Reverse(topup.created_at));
         
         ic_cdk::println!("📊 Returning {} topup records for {}", user_topups.len(), caller.to_text());
         user_topups
@@ -1134,96 +1569,596 @@ fn simulate_card_processing(topup: &TopUpTransaction) -> bool {
 }
 
 // ===================
-// ENHANCED EXCHANGE RATE MANAGEMENT
+// CURRENCY REGISTRY
 // ===================
 
+#[query]
+#[candid_method(query)]
+fn get_currency_registry() -> Vec<CurrencyInfo> {
+    CURRENCY_REGISTRY.with(|registry| {
+        registry.borrow().iter().map(|(_, info)| info).collect()
+    })
+}
+
+#[query]
+#[candid_method(query)]
+fn get_currency(code: String) -> Option<CurrencyInfo> {
+    get_currency_info(&code)
+}
+
 #[update]
 #[candid_method(update)]
-async fn fetch_exchange_rate(currency: String) -> Result<ExchangeRate, String> {
-    let currency_upper = currency.to_uppercase();
-    
-    if !is_supported_currency(&currency_upper) {
-        return Err(format!("Unsupported currency: {}", currency_upper));
-    }
+fn upsert_currency(info: CurrencyInfo) -> Result<CurrencyInfo, String> {
+    let admin = ensure_admin()?;
 
-    // Check if we have a valid cached rate first
-    let cached_rate = EXCHANGE_RATES.with(|rates| {
-        rates.borrow().get(&currency_upper).cloned()
+    let mut info = normalize_currency_info(info);
+    validate_currency_info(&info)?;
+    info.updated_at = time();
+
+    CURRENCY_REGISTRY.with(|registry| {
+        registry.borrow_mut().insert(info.code.clone(), info.clone());
     });
-    
-    if let Some(rate) = cached_rate.as_ref() {
-        if is_rate_cache_valid(rate) {
-            ic_cdk::println!("✅ Using valid cached rate for {}", currency_upper);
-            return Ok(rate.clone());
-        }
-    }
 
+    ic_cdk::println!("💱 Currency {} updated by {}", info.code, admin.to_text());
+    Ok(info)
+}
 
-    match fetch_exchange_rate_with_retry_internal(currency_upper.clone(), cached_rate.clone()).await {
-        Ok(exchange_rate) => {
+#[update]
+#[candid_method(update)]
+fn set_currency_enabled(code: String, enabled: bool) -> Result<CurrencyInfo, String> {
+    let admin = ensure_admin()?;
 
-            EXCHANGE_RATES.with(|rates| {
-                rates.borrow_mut().insert(currency_upper.clone(), exchange_rate.clone());
-            });
-            
-            ic_cdk::println!("✅ Fresh rate fetched and cached for {}", currency_upper);
-            Ok(exchange_rate)
-        }
-        Err(e) => {
-            ic_cdk::println!("❌ Failed to fetch fresh rate: {}", e);
-            
+    let mut info = get_currency_info(&code)
+        .ok_or(format!("Unknown currency: {}", code.to_uppercase()))?;
+    info.enabled = enabled;
+    info.updated_at = time();
 
-            if let Some(cached) = cached_rate {
-                let age_minutes = (ic_cdk::api::time() - cached.timestamp) / (60 * 1_000_000_000);
-                
-                let stale_rate = ExchangeRate {
-                    currency: cached.currency,
-                    rate: cached.rate,
-                    timestamp: cached.timestamp,
-                    source: format!("coingecko_stale_{}min", age_minutes),
-                };
-                
+    CURRENCY_REGISTRY.with(|registry| {
+        registry.borrow_mut().insert(info.code.clone(), info.clone());
+    });
 
-                EXCHANGE_RATES.with(|rates| {
-                    rates.borrow_mut().insert(currency_upper.clone(), stale_rate.clone());
-                });
-                
-                ic_cdk::println!("⚠️ Using stale cache for {} ({}min old)", currency_upper, age_minutes);
-                return Ok(stale_rate);
-            }
-            
-            Err(format!("Could not fetch current rate for {}: {}", currency_upper, e))
-        }
-    }
+    ic_cdk::println!("💱 Currency {} {} by {}", info.code,
+        if enabled { "enabled" } else { "disabled" }, admin.to_text());
+    Ok(info)
 }
 
 #[query]
 #[candid_method(query)]
-fn get_cached_exchange_rate(currency: String) -> Option<ExchangeRate> {
-    let currency_upper = currency.to_uppercase();
-    EXCHANGE_RATES.with(|rates| rates.borrow().get(&currency_upper).cloned())
+fn get_qr_policy() -> QRPolicy {
+    current_qr_policy()
+}
+
+#[update]
+#[candid_method(update)]
+fn set_qr_policy(policy: QRPolicy) -> Result<QRPolicy, String> {
+    let admin = ensure_admin()?;
+    let config = apply_config_updates(vec![ConfigUpdate::QRPolicy(policy)], admin)?;
+    Ok(config.qr_policy)
 }
 
 #[query]
 #[candid_method(query)]
-fn get_cached_exchange_rate_with_validity(currency: String) -> Option<(ExchangeRate, bool)> {
-    let currency_upper = currency.to_uppercase();
-    EXCHANGE_RATES.with(|rates| {
-        rates.borrow().get(&currency_upper).map(|rate| {
-            let is_valid = is_rate_cache_valid(rate);
-            (rate.clone(), is_valid)
-        })
-    })
+fn get_config() -> CanisterConfig {
+    current_config()
 }
 
-
 #[update]
 #[candid_method(update)]
-async fn force_refresh_exchange_rate(currency: String) -> Result<ExchangeRate, String> {
-    let currency_upper = currency.to_uppercase();
-    
-    if !is_supported_currency(&currency_upper) {
-        return Err(format!("Unsupported currency: {}", currency_upper));
+fn update_config(updates: Vec<ConfigUpdate>) -> Result<CanisterConfig, String> {
+    let admin = ensure_admin()?;
+    if updates.is_empty() {
+        return Err("No config updates given".to_string());
+    }
+    apply_config_updates(updates, admin)
+}
+
+// Newest first
+#[query]
+#[candid_method(query)]
+fn get_config_history(limit: Option<u32>) -> Result<Vec<ConfigChange>, String> {
+    ensure_admin()?;
+    let limit = limit.unwrap_or(50).min(500) as usize;
+
+    Ok(CONFIG_HISTORY.with(|history| {
+        history.borrow().iter().rev().take(limit).map(|(_, change)| change).collect()
+    }))
+}
+
+// ===================
+// LIMITS
+// ===================
+
+fn configured_limits(tier: KycTier) -> TierLimits {
+    limits_for_tier(&current_config().tier_limits, tier)
+}
+
+// Payments the user sent over the last 30 days, from the balance ledger
+fn outgoing_usage_of(user_id: Principal, now: u64) -> OutgoingUsage {
+    let since = now.saturating_sub(MONTH_NANOS);
+    let (sent, refunds): (Vec<BalanceChangeLog>, Vec<BalanceChangeLog>) = BALANCE_CHANGE_LOGS.with(|logs| {
+        logs.borrow()
+            .iter()
+            .map(|(_, log)| log)
+            .filter(|log| {
+                log.user_id == user_id
+                    && log.timestamp >= since
+                    && matches!(log.change_type, BalanceChangeType::PaymentSent | BalanceChangeType::Refund)
+            })
+            .partition(|log| log.change_type == BalanceChangeType::PaymentSent)
+    });
+
+    // Batch payouts are one debit for all their recipients
+    let payments_in = |reference_id: &String| {
+        PAYOUT_BATCHES.with(|batches| batches.borrow().get(reference_id))
+            .map_or(1, |batch| batch.results.len() as u32)
+    };
+    outgoing_usage(
+        net_of_refunds(&sent, &refunds)
+            .into_iter()
+            .map(|(log, amount)| (log.timestamp, amount, payments_in(&log.reference_id))),
+        now,
+    )
+}
+
+// Every way out of the balance goes through here. There is no withdrawal
+// flow yet; when one is added it must call this as well.
+fn check_spending_limits(tier: KycTier, user_id: Principal, amount: u64, payments: u32) -> Result<(), String> {
+    check_outgoing(&configured_limits(tier), &outgoing_usage_of(user_id, time()), amount, payments)
+}
+
+// Completed top-ups over the last 30 days, card and QRIS alike
+fn monthly_topups_of(user_id: Principal, now: u64) -> u64 {
+    let since = now.saturating_sub(MONTH_NANOS);
+    TOPUP_TRANSACTIONS.with(|topups| {
+        topups.borrow()
+            .iter()
+            .filter(|(_, topup)| {
+                topup.user_id == user_id
+                    && topup.status == TopUpStatus::Completed
+                    && topup.created_at >= since
+            })
+            .map(|(_, topup)| topup.amount)
+            .sum()
+    })
+}
+
+fn check_topup_limits(tier: KycTier, user_id: Principal, amount: u64) -> Result<(), String> {
+    check_topup(&configured_limits(tier), amount, monthly_topups_of(user_id, time()))
+}
+
+#[query]
+#[candid_method(query)]
+fn get_my_limits() -> Result<UserLimits, String> {
+    let caller = caller();
+    if !USERS.with(|users| users.borrow().contains_key(&caller)) {
+        return Err("User not registered".to_string());
+    }
+    let now = time();
+    Ok(remaining_limits(configured_limits(user_tier(caller)), &outgoing_usage_of(caller, now), monthly_topups_of(caller, now)))
+}
+
+// ===================
+// KYC
+// ===================
+
+fn user_tier(user_id: Principal) -> KycTier {
+    USERS.with(|users| users.borrow().get(&user_id))
+        .map(|user| user.kyc_tier)
+        .unwrap_or_default()
+}
+
+// For reviews done entirely off-chain
+#[update]
+#[candid_method(update)]
+fn set_user_kyc_tier(user_id: Principal, tier: KycTier) -> Result<User, String> {
+    ensure_admin()?;
+    USERS.with(|users| {
+        let mut users = users.borrow_mut();
+        let mut user = users.get(&user_id).ok_or("User not found")?;
+        user.kyc_tier = tier;
+        users.insert(user_id, user.clone());
+        Ok(user)
+    })
+}
+
+#[update]
+#[candid_method(update)]
+fn submit_kyc(requested_tier: KycTier, documents: Vec<KycDocumentInput>) -> Result<KycSubmission, String> {
+    let caller = caller();
+    let user = USERS.with(|users| users.borrow().get(&caller))
+        .ok_or("User not registered")?;
+
+    let has_pending = KYC_SUBMISSIONS.with(|submissions| {
+        submissions.borrow()
+            .iter()
+            .any(|(_, s)| s.user_id == caller && s.status == KycSubmissionStatus::Pending)
+    });
+    if has_pending {
+        return Err("You already have a submission waiting for review".to_string());
+    }
+
+    let now = time();
+    let submission = build_kyc_submission(
+        generate_kyc_submission_id(caller, now),
+        &user,
+        requested_tier,
+        documents,
+        now,
+    )?;
+    KYC_SUBMISSIONS.with(|submissions| {
+        submissions.borrow_mut().insert(submission.id.clone(), submission.clone());
+    });
+    Ok(submission)
+}
+
+#[query]
+#[candid_method(query)]
+fn get_my_kyc_submissions() -> Vec<KycSubmission> {
+    let caller = caller();
+    let mut submissions: Vec<KycSubmission> = KYC_SUBMISSIONS.with(|submissions| {
+        submissions.borrow()
+            .iter()
+            .filter(|(_, s)| s.user_id == caller)
+            .map(|(_, s)| s)
+            .collect()
+    });
+    submissions.sort_by_key(|s| std::cmp::Reverse(s.submitted_at));
+    submissions
+}
+
+// Oldest first
+#[query]
+#[candid_method(query)]
+fn get_pending_kyc_submissions() -> Result<Vec<KycSubmission>, String> {
+    ensure_admin()?;
+    let mut submissions: Vec<KycSubmission> = KYC_SUBMISSIONS.with(|submissions| {
+        submissions.borrow()
+            .iter()
+            .filter(|(_, s)| s.status == KycSubmissionStatus::Pending)
+            .map(|(_, s)| s)
+            .collect()
+    });
+    submissions.sort_by_key(|s| s.submitted_at);
+    Ok(submissions)
+}
+
+// Called after the documents were checked off-chain; approval moves the
+// user to the requested tier
+#[update]
+#[candid_method(update)]
+fn review_kyc_submission(submission_id: String, approve: bool, note: Option<String>) -> Result<KycSubmission, String> {
+    let admin = ensure_admin()?;
+    let mut submission = KYC_SUBMISSIONS.with(|submissions| submissions.borrow().get(&submission_id))
+        .ok_or("Submission not found")?;
+    let mut user = USERS.with(|users| users.borrow().get(&submission.user_id))
+        .ok_or("User not found")?;
+
+    let now = time();
+    review_submission(&mut submission, approve, admin, note, now)?;
+    if approve {
+        user.kyc_tier = submission.requested_tier;
+        user.kyc_verified_at = Some(now);
+        USERS.with(|users| {
+            users.borrow_mut().insert(user.id, user);
+        });
+    }
+    KYC_SUBMISSIONS.with(|submissions| {
+        submissions.borrow_mut().insert(submission.id.clone(), submission.clone());
+    });
+    Ok(submission)
+}
+
+// ===================
+// RISK CHECKS
+// ===================
+
+const RISK_VELOCITY_NANOS: u64 = 60 * 60 * 1_000_000_000;
+const DEFAULT_RISK_ASSESSMENT_LIMIT: u32 = 100;
+
+fn is_topup_channel(action: RiskAction, method: &TopUpMethod) -> bool {
+    match action {
+        RiskAction::Payment => false,
+        RiskAction::CardTopup => matches!(method, TopUpMethod::CreditCard | TopUpMethod::DebitCard),
+        RiskAction::QrisTopup => *method == TopUpMethod::QRIS,
+    }
+}
+
+// What the rules need about the user's recent activity. Runs before the new
+// payment or top-up is stored, so it only sees earlier ones.
+fn risk_context(action: RiskAction, user_id: Principal, counterparty: Option<Principal>, amount: u64) -> RiskContext {
+    let now = time();
+    let since = now.saturating_sub(MONTH_NANOS);
+    let hour_ago = now.saturating_sub(RISK_VELOCITY_NANOS);
+    let account_age_nanos = USERS.with(|users| users.borrow().get(&user_id))
+        .map(|user| now.saturating_sub(user.created_at))
+        .unwrap_or(0);
+    let mut context = RiskContext { action, amount, account_age_nanos, ..RiskContext::default() };
+
+    if action == RiskAction::Payment {
+        BALANCE_CHANGE_LOGS.with(|logs| {
+            for (_, log) in logs.borrow().iter() {
+                if log.user_id == user_id && log.change_type == BalanceChangeType::PaymentSent && log.timestamp >= since {
+                    context.history.push(log.amount);
+                    if log.timestamp >= hour_ago {
+                        context.recent_count += 1;
+                    }
+                }
+            }
+        });
+        if let Some(payee) = counterparty {
+            TRANSACTIONS.with(|transactions| {
+                for (_, tx) in transactions.borrow().iter() {
+                    if tx.status != TransactionStatus::Completed || tx.timestamp < hour_ago {
+                        continue;
+                    }
+                    if tx.from == user_id && tx.to == payee {
+                        context.pair_count += 1;
+                    } else if tx.from == payee && tx.to == user_id {
+                        context.reverse_count += 1;
+                    }
+                }
+            });
+        }
+    } else {
+        TOPUP_TRANSACTIONS.with(|topups| {
+            for (_, topup) in topups.borrow().iter() {
+                if topup.user_id != user_id || topup.created_at < since || !is_topup_channel(action, &topup.payment_method) {
+                    continue;
+                }
+                match topup.status {
+                    TopUpStatus::Completed => context.history.push(topup.amount),
+                    // Each attempt starts as one PENDING record
+                    TopUpStatus::Pending if topup.created_at >= hour_ago => context.recent_count += 1,
+                    _ => {}
+                }
+            }
+        });
+    }
+    context
+}
+
+// Refuses denied actions; everything else is recorded by the caller once it
+// knows whether anything is held
+fn screen_risk(user_id: Principal, counterparty: Option<Principal>, context: &RiskContext) -> Result<RiskEvaluation, String> {
+    let evaluation = evaluate_risk(context);
+    if evaluation.decision == RiskDecision::Deny {
+        record_risk_assessment(user_id, counterparty, context, evaluation, None);
+        return Err("Declined by our risk checks; contact support if this is a mistake".to_string());
+    }
+    Ok(evaluation)
+}
+
+// Checks that matched no rule are only logged
+fn record_risk_assessment(
+    user_id: Principal,
+    counterparty: Option<Principal>,
+    context: &RiskContext,
+    evaluation: RiskEvaluation,
+    reference_id: Option<String>,
+) {
+    ic_cdk::println!(
+        "🛡️ Risk {:?} (score {}) for {:?} by {}: {:?}",
+        evaluation.decision, evaluation.score, context.action, user_id.to_text(), evaluation.reasons
+    );
+    if evaluation.reasons.is_empty() {
+        return;
+    }
+    RISK_ASSESSMENTS.with(|assessments| {
+        let mut assessments = assessments.borrow_mut();
+        let id = format!("RISK_{:010}", assessments.len());
+        let assessment = build_assessment(id, user_id, counterparty, context, evaluation, reference_id, time());
+        assessments.insert(assessment.id.clone(), assessment);
+    });
+}
+
+fn risk_review_for(reference_id: &str) -> Option<RiskAssessment> {
+    RISK_ASSESSMENTS.with(|assessments| {
+        assessments.borrow()
+            .iter()
+            .find(|(_, a)| a.reference_id.as_deref() == Some(reference_id))
+            .map(|(_, a)| a)
+    })
+}
+
+fn under_risk_review(reference_id: &str) -> bool {
+    risk_review_for(reference_id).is_some_and(|assessment| is_awaiting_review(&assessment))
+}
+
+// Escrowed payments go back to their release window when approved
+fn settle_reviewed_payment(held_tx_id: &str, approve: bool, now: u64) -> Result<(), String> {
+    let held_tx = TRANSACTIONS.with(|transactions| transactions.borrow().get(&held_tx_id.to_string()))
+        .ok_or("Held payment not found")?;
+    let escrow = ESCROWS.with(|escrows| escrows.borrow().get(&escrow_id_for(&held_tx)));
+    match (escrow, approve) {
+        (Some(_), true) => {}
+        (Some(mut escrow), false) => {
+            if is_escrow_open(&escrow) {
+                settle_escrow(&mut escrow, &EscrowResolution::Refund)?;
+            }
+        }
+        (None, true) => {
+            let completed_tx = complete_held_payment(&held_tx, now);
+            release_qr_hold(&held_tx, &completed_tx);
+        }
+        (None, false) => {
+            let refunded_tx = refund_held_payment(&held_tx, TransactionStatus::Failed, "declined after risk review", now);
+            refund_qr_hold(&held_tx, &refunded_tx);
+        }
+    }
+    Ok(())
+}
+
+// Approved QRIS top-ups can then be claimed as usual
+fn settle_reviewed_topup(topup_id: &str, approve: bool, now: u64) -> Result<(), String> {
+    let topup = TOPUP_TRANSACTIONS.with(|topups| topups.borrow().get(&topup_id.to_string()))
+        .ok_or("Held top-up not found")?;
+    if approve {
+        if topup.payment_method != TopUpMethod::QRIS {
+            process_card_topup(&topup)?;
+        }
+        return Ok(());
+    }
+
+    let failed_topup = TopUpTransaction {
+        id: format!("{}_FAILED_{}", topup.id, now),
+        status: TopUpStatus::Failed,
+        created_at: now,
+        processed_at: Some(now),
+        ..topup
+    };
+    TOPUP_TRANSACTIONS.with(|topups| {
+        topups.borrow_mut().insert(failed_topup.id.clone(), failed_topup.clone());
+    });
+    ic_cdk::println!("❌ Created FAILED topup after risk review: {}", failed_topup.id);
+    Ok(())
+}
+
+// Held payments and top-ups, oldest first
+#[query]
+#[candid_method(query)]
+fn get_risk_review_queue() -> Result<Vec<RiskAssessment>, String> {
+    ensure_admin()?;
+    let mut queue: Vec<RiskAssessment> = RISK_ASSESSMENTS.with(|assessments| {
+        assessments.borrow()
+            .iter()
+            .filter(|(_, a)| is_awaiting_review(a))
+            .map(|(_, a)| a)
+            .collect()
+    });
+    queue.sort_by_key(|a| a.created_at);
+    Ok(queue)
+}
+
+// Newest first, optionally for one user
+#[query]
+#[candid_method(query)]
+fn get_risk_assessments(user_id: Option<Principal>, limit: Option<u32>) -> Result<Vec<RiskAssessment>, String> {
+    ensure_admin()?;
+    let mut assessments: Vec<RiskAssessment> = RISK_ASSESSMENTS.with(|assessments| {
+        assessments.borrow()
+            .iter()
+            .filter(|(_, a)| user_id.is_none_or(|user_id| a.user_id == user_id))
+            .map(|(_, a)| a)
+            .collect()
+    });
+    assessments.sort_by_key(|a| std::cmp::Reverse(a.created_at));
+    assessments.truncate(limit.unwrap_or(DEFAULT_RISK_ASSESSMENT_LIMIT) as usize);
+    Ok(assessments)
+}
+
+// Approving lets the held item go through; rejecting refunds the payment or
+// fails the top-up
+#[update]
+#[candid_method(update)]
+fn resolve_risk_review(assessment_id: String, approve: bool, note: Option<String>) -> Result<RiskAssessment, String> {
+    let admin = ensure_admin()?;
+    let mut assessment = RISK_ASSESSMENTS.with(|assessments| assessments.borrow().get(&assessment_id))
+        .ok_or("Assessment not found")?;
+
+    let now = time();
+    resolve_review(&mut assessment, approve, admin, note, now)?;
+    let reference_id = assessment.reference_id.clone().ok_or("Nothing is held for this assessment")?;
+    match assessment.action {
+        RiskAction::Payment => settle_reviewed_payment(&reference_id, approve, now)?,
+        RiskAction::CardTopup | RiskAction::QrisTopup => settle_reviewed_topup(&reference_id, approve, now)?,
+    }
+    RISK_ASSESSMENTS.with(|assessments| {
+        assessments.borrow_mut().insert(assessment.id.clone(), assessment.clone());
+    });
+    Ok(assessment)
+}
+
+// ===================
+// ENHANCED EXCHANGE RATE MANAGEMENT
+// ===================
+
+#[update]
+#[candid_method(update)]
+async fn fetch_exchange_rate(currency: String) -> Result<ExchangeRate, String> {
+    let currency_upper = currency.to_uppercase();
+    
+    if !is_supported_currency(&currency_upper) {
+        return Err(format!("Unsupported currency: {}", currency_upper));
+    }
+
+    // Check if we have a valid cached rate first
+    let cached_rate = EXCHANGE_RATES.with(|rates| {
+        rates.borrow().get(&currency_upper).cloned()
+    });
+    
+    if let Some(rate) = cached_rate.as_ref() {
+        if is_rate_cache_valid(rate) {
+            ic_cdk::println!("✅ Using valid cached rate for {}", currency_upper);
+            return Ok(rate.clone());
+        }
+    }
+
+
+    match fetch_exchange_rate_with_retry_internal(currency_upper.clone(), cached_rate.clone()).await {
+        Ok(exchange_rate) => {
+
+            EXCHANGE_RATES.with(|rates| {
+                rates.borrow_mut().insert(currency_upper.clone(), exchange_rate.clone());
+            });
+            
+            ic_cdk::println!("✅ Fresh rate fetched and cached for {}", currency_upper);
+            Ok(exchange_rate)
+        }
+        Err(e) => {
+            ic_cdk::println!("❌ Failed to fetch fresh rate: {}", e);
+            
+
+            if let Some(cached) = cached_rate {
+                let age_minutes = (ic_cdk::api::time() - cached.timestamp) / (60 * 1_000_000_000);
+                
+                let stale_rate = ExchangeRate {
+                    currency: cached.currency,
+                    rate: cached.rate,
+                    timestamp: cached.timestamp,
+                    source: format!("coingecko_stale_{}min", age_minutes),
+                };
+                
+
+                EXCHANGE_RATES.with(|rates| {
+                    rates.borrow_mut().insert(currency_upper.clone(), stale_rate.clone());
+                });
+                
+                ic_cdk::println!("⚠️ Using stale cache for {} ({}min old)", currency_upper, age_minutes);
+                return Ok(stale_rate);
+            }
+            
+            Err(format!("Could not fetch current rate for {}: {}", currency_upper, e))
+        }
+    }
+}
+
+#[query]
+#[candid_method(query)]
+fn get_cached_exchange_rate(currency: String) -> Option<ExchangeRate> {
+    let currency_upper = currency.to_uppercase();
+    EXCHANGE_RATES.with(|rates| rates.borrow().get(&currency_upper).cloned())
+}
+
+#[query]
+#[candid_method(query)]
+fn get_cached_exchange_rate_with_validity(currency: String) -> Option<(ExchangeRate, bool)> {
+    let currency_upper = currency.to_uppercase();
+    EXCHANGE_RATES.with(|rates| {
+        rates.borrow().get(&currency_upper).map(|rate| {
+            let is_valid = is_rate_cache_valid(rate);
+            (rate.clone(), is_valid)
+        })
+    })
+}
+
+
+#[update]
+#[candid_method(update)]
+async fn force_refresh_exchange_rate(currency: String) -> Result<ExchangeRate, String> {
+    let currency_upper = currency.to_uppercase();
+    
+    if !is_supported_currency(&currency_upper) {
+        return Err(format!("Unsupported currency: {}", currency_upper));
     }
 
     // Force fresh fetch
@@ -1244,148 +2179,1878 @@ async fn force_refresh_exchange_rate(currency: String) -> Result<ExchangeRate, S
     }
 }
 
+#[query]
+#[candid_method(query)]
+fn convert(amount: String, from_currency: String, to_currency: String) -> Result<CurrencyConversion, String> {
+    if !is_supported_currency(&from_currency) {
+        return Err(format!("Unsupported currency: {}", from_currency.to_uppercase()));
+    }
+    if !is_supported_currency(&to_currency) {
+        return Err(format!("Unsupported currency: {}", to_currency.to_uppercase()));
+    }
+
+    let amount = Money::parse(&amount, &from_currency)?;
+    convert_with_known_rates(&amount, &from_currency, &to_currency)
+}
+
+// ===================
+// RATE HISTORY
+// ===================
+
+// Last recorded rate at or before `timestamp` (close of its bucket)
+#[query]
+#[candid_method(query)]
+fn get_rate_at(currency: String, timestamp: u64) -> Result<ExchangeRate, String> {
+    let currency_upper = currency.to_uppercase();
+    let lower = rate_history_key(&currency_upper, 0);
+    let upper = rate_history_key(&currency_upper, bucket_start(timestamp, BASE_BUCKET_SECONDS));
+
+    RATE_HISTORY.with(|history| {
+        history.borrow()
+            .range(lower..=upper)
+            .next_back()
+            .map(|(_, candle)| candle_to_exchange_rate(&candle))
+    })
+    .ok_or(format!("No {} rate recorded before {}", currency_upper, timestamp))
+}
+
+#[query]
+#[candid_method(query)]
+fn get_rate_history(
+    currency: String,
+    from: u64,
+    to: u64,
+    bucket: RateBucket,
+) -> Result<Vec<RateCandle>, String> {
+    if from > to {
+        return Err("'from' must not be after 'to'".to_string());
+    }
+
+    let currency_upper = currency.to_uppercase();
+    let lower = rate_history_key(&currency_upper, bucket_start(from, BASE_BUCKET_SECONDS));
+    let upper = rate_history_key(&currency_upper, to);
+
+    let candles: Vec<RateCandle> = RATE_HISTORY.with(|history| {
+        history.borrow()
+            .range(lower..=upper)
+            .map(|(_, candle)| candle)
+            .collect()
+    });
+
+    let aggregated = aggregate_candles(&candles, bucket_seconds(bucket));
+    if aggregated.len() > MAX_HISTORY_POINTS {
+        return Err(format!(
+            "Range yields {} points (max {}); use a larger bucket or a shorter range",
+            aggregated.len(),
+            MAX_HISTORY_POINTS
+        ));
+    }
+
+    Ok(aggregated)
+}
+
+// ===================
+// QR CODE MANAGEMENT
+// ===================
+
+#[update]
+#[candid_method(update)]
+async fn generate_qr(
+    fiat_amount: String,
+    fiat_currency: String,
+    description: Option<String>,
+    expiry_seconds: Option<u64>,
+    max_uses: Option<u32>,
+    allowed_payer: Option<Principal>,
+) -> Result<QRCode, String> {
+    let caller = caller();
+    if caller == Principal::anonymous() {
+        return Err("Anonymous users cannot generate QR codes".to_string());
+    }
+
+    let fiat_amount = Money::parse(&fiat_amount, &fiat_currency)?;
+
+    // Check if user is registered
+    let user = USERS.with(|users| users.borrow().get(&caller));
+    if user.is_none() {
+        return Err("User not registered. Please register first".to_string());
+    }
+
+    let policy = current_qr_policy();
+    let expiry_seconds = resolve_expiry_seconds(&policy, expiry_seconds)?;
+    validate_max_uses(&policy, max_uses)?;
+
+    if allowed_payer == Some(caller) {
+        return Err("Cannot reserve a QR code for yourself".to_string());
+    }
+
+    let qr_code = create_qr_code(
+        caller,
+        fiat_amount,
+        fiat_currency,
+        description,
+        expiry_seconds,
+        max_uses,
+        allowed_payer,
+    ).await?;
+
+    QR_CODES.with(|qr_codes| {
+        qr_codes.borrow_mut().insert(qr_code.id.clone(), qr_code.clone());
+    });
+
+    ic_cdk::println!("QR code generated: {}", qr_code.id);
+    Ok(qr_code)
+}
+
+#[update]
+#[candid_method(update)]
+fn generate_static_qr(
+    fiat_currency: String,
+    description: Option<String>,
+) -> Result<QRCode, String> {
+    let caller = caller();
+    if caller == Principal::anonymous() {
+        return Err("Anonymous users cannot generate QR codes".to_string());
+    }
+
+    // Check if user is registered
+    let user = USERS.with(|users| users.borrow().get(&caller));
+    if user.is_none() {
+        return Err("User not registered. Please register first".to_string());
+    }
+
+    let qr_code = create_static_qr_code(caller, fiat_currency, description)?;
+
+    QR_CODES.with(|qr_codes| {
+        qr_codes.borrow_mut().insert(qr_code.id.clone(), qr_code.clone());
+    });
+
+    ic_cdk::println!("Static QR code generated: {}", qr_code.id);
+    Ok(qr_code)
+}
+
+// Printable image of a QR code's payment link
+#[query]
+#[candid_method(query)]
+fn render_qr_image(qr_id: String, options: QRRenderOptions) -> Result<QRImage, String> {
+    let qr_code = QR_CODES.with(|qr_codes| qr_codes.borrow().get(&qr_id))
+        .ok_or("QR code not found")?;
+
+    render_qr(&generate_qr_data_url(&qr_code.id, &public_base_url()), &options)
+}
+
+// Image for any payment URL or payload (e.g. an EMVCo/QRIS string)
+#[query]
+#[candid_method(query)]
+fn render_payment_qr(data: String, options: QRRenderOptions) -> Result<QRImage, String> {
+    render_qr(&data, &options)
+}
+
+#[update]
+#[candid_method(update)]
+fn cancel_qr(qr_id: String) -> Result<QRCode, String> {
+    let caller = caller();
+    let mut qr_code = QR_CODES.with(|qr_codes| qr_codes.borrow().get(&qr_id))
+        .ok_or("QR code not found")?;
+
+    cancel_qr_code(&mut qr_code, caller, time())?;
+
+    QR_CODES.with(|qr_codes| {
+        qr_codes.borrow_mut().insert(qr_id.clone(), qr_code.clone());
+    });
+
+    // No transaction behind a cancellation
+    create_qr_usage_log(
+        qr_id.clone(),
+        qr_code.user_id,
+        caller,
+        String::new(),
+        QRUsageType::Cancelled,
+    );
+
+    if qr_code.kind == QRKind::Split {
+        close_split_bill(&qr_id, SplitStatus::Cancelled);
+    }
+    // The payment request behind the code closes with it
+    if let Some(mut request) = PAYMENT_REQUESTS.with(|requests| requests.borrow().get(&qr_id)) {
+        if cancel_request(&mut request, caller, time()).is_ok() {
+            save_payment_request(&request);
+        }
+    }
+
+    ic_cdk::println!("QR code cancelled: {}", qr_id);
+    Ok(qr_code)
+}
+
+#[query]
+#[candid_method(query)]
+fn get_qr(qr_id: String) -> Option<QRCode> {
+    QR_CODES.with(|qr_codes| qr_codes.borrow().get(&qr_id))
+}
+
+#[query]
+#[candid_method(query)]
+fn get_qr_display_info_by_id(qr_id: String) -> Option<QRDisplayInfo> {
+    let caller = caller();
+    let language = get_user_language(caller);
+    QR_CODES.with(|qr_codes| qr_codes.borrow().get(&qr_id))
+        .map(|qr| {
+            let completed_uses = count_qr_payments(&qr.id);
+            get_qr_display_info(&qr, &language, payer_equivalent(&qr, caller), completed_uses)
+        })
+}
+
+#[query]
+#[candid_method(query)]
+fn get_user_qr_codes() -> Vec<QRCode> {
+    let caller = caller();
+    QR_CODES.with(|qr_codes| {
+        qr_codes
+            .borrow()
+            .iter()
+            .filter(|(_, qr)| qr.user_id == caller)
+            .map(|(_, qr)| qr.clone())
+            .collect()
+    })
+}
+
+#[update]
+#[candid_method(update)]
+async fn validate_qr_code(qr_id: String) -> Result<QRDisplayInfo, String> {
+    let qr_code = QR_CODES.with(|qr_codes| qr_codes.borrow().get(&qr_id))
+        .ok_or("QR code not found")?;
+
+    let caller = caller();
+    let completed_uses = count_qr_payments(&qr_id);
+    is_qr_code_valid(&qr_code, caller, completed_uses)?;
+    Ok(get_qr_display_info(
+        &qr_code,
+        &get_user_language(caller),
+        payer_equivalent(&qr_code, caller),
+        completed_uses,
+    ))
+}
+
+// ===================
+// INVOICES
+// ===================
+
+fn get_own_invoice(invoice_id: &str, caller: Principal) -> Result<Invoice, String> {
+    let invoice = INVOICES.with(|invoices| invoices.borrow().get(&invoice_id.to_string()))
+        .ok_or("Invoice not found")?;
+    if invoice.merchant != caller {
+        return Err("Invoice not found".to_string());
+    }
+    Ok(invoice)
+}
+
+fn save_invoice(invoice: &Invoice) {
+    INVOICES.with(|invoices| {
+        invoices.borrow_mut().insert(invoice.id.clone(), invoice.clone());
+    });
+}
+
+// Cancels the invoice's QR codes that can still be paid
+fn cancel_open_invoice_qrs(invoice: &Invoice) {
+    let now = time();
+    for qr_id in &invoice.qr_ids {
+        let Some(mut qr_code) = QR_CODES.with(|qr_codes| qr_codes.borrow().get(qr_id)) else { continue };
+        if cancel_qr_code(&mut qr_code, invoice.merchant, now).is_ok() {
+            QR_CODES.with(|qr_codes| {
+                qr_codes.borrow_mut().insert(qr_id.clone(), qr_code);
+            });
+            create_qr_usage_log(qr_id.clone(), invoice.merchant, invoice.merchant, String::new(), QRUsageType::Cancelled);
+        }
+    }
+}
+
+// Open codes may still be paid, so their amounts are spoken for
+fn invoice_reserved(invoice: &Invoice) -> Money {
+    let now = time();
+    invoice.qr_ids.iter()
+        .filter_map(|qr_id| QR_CODES.with(|qr_codes| qr_codes.borrow().get(qr_id)))
+        .filter(|qr| qr_status(qr, now) == QRStatus::Active)
+        .fold(Money::new(0, invoice.total.scale), |sum, qr| sum.saturating_add(&qr.fiat_amount))
+}
+
+// Called once a payment through an invoice QR has completed
+fn apply_invoice_payment(qr_code: &QRCode, fiat_amount: &Money) {
+    let Some(invoice_id) = &qr_code.invoice_id else { return };
+    let Some(mut invoice) = INVOICES.with(|invoices| invoices.borrow().get(invoice_id)) else { return };
+
+    record_invoice_payment(&mut invoice, fiat_amount, time());
+    save_invoice(&invoice);
+    ic_cdk::println!("🧾 Invoice {} {:?}, paid {} of {}", invoice.id, invoice.status, invoice.amount_paid, invoice.total);
+
+    if invoice.status == InvoiceStatus::Paid {
+        cancel_open_invoice_qrs(&invoice);
+    }
+}
+
+#[update]
+#[candid_method(update)]
+fn create_invoice(input: InvoiceInput) -> Result<Invoice, String> {
+    let caller = caller();
+    if !USERS.with(|users| users.borrow().contains_key(&caller)) {
+        return Err("User not registered. Please register first".to_string());
+    }
+
+    let now = time();
+    let sequence = INVOICES.with(|invoices| invoices.borrow().len());
+    let invoice = build_invoice(generate_invoice_id(caller, sequence), caller, input, now, now)?;
+    save_invoice(&invoice);
+
+    ic_cdk::println!("🧾 Invoice created: {} ({} {})", invoice.id, invoice.total, invoice.currency);
+    Ok(invoice)
+}
+
+#[update]
+#[candid_method(update)]
+fn update_invoice(invoice_id: String, input: InvoiceInput) -> Result<Invoice, String> {
+    let existing = get_own_invoice(&invoice_id, caller())?;
+    ensure_draft(&existing)?;
+
+    let invoice = build_invoice(existing.id, existing.merchant, input, existing.created_at, time())?;
+    save_invoice(&invoice);
+    Ok(invoice)
+}
+
+#[update]
+#[candid_method(update)]
+fn issue_invoice(invoice_id: String) -> Result<Invoice, String> {
+    let mut invoice = get_own_invoice(&invoice_id, caller())?;
+    invoices::issue_invoice(&mut invoice, time())?;
+    save_invoice(&invoice);
+    Ok(invoice)
+}
+
+#[update]
+#[candid_method(update)]
+fn void_invoice(invoice_id: String) -> Result<Invoice, String> {
+    let mut invoice = get_own_invoice(&invoice_id, caller())?;
+    invoices::void_invoice(&mut invoice)?;
+    save_invoice(&invoice);
+    cancel_open_invoice_qrs(&invoice);
+    Ok(invoice)
+}
+
+// A QR collecting `amount` (default: everything not yet covered) of an invoice
+#[update]
+#[candid_method(update)]
+async fn create_invoice_qr(
+    invoice_id: String,
+    amount: Option<String>,
+    expiry_seconds: Option<u64>,
+) -> Result<QRCode, String> {
+    let caller = caller();
+    let invoice = get_own_invoice(&invoice_id, caller)?;
+
+    let reserved = invoice_reserved(&invoice);
+
+    let amount = match amount {
+        Some(amount) => Money::parse(&amount, &invoice.currency)?,
+        None => Money::new(
+            outstanding(&invoice).minor_units.saturating_sub(reserved.minor_units),
+            invoice.total.scale,
+        ),
+    };
+    check_collectable(&invoice, &amount, &reserved)?;
+
+    let expiry_seconds = resolve_expiry_seconds(&current_qr_policy(), expiry_seconds)?;
+    let description = Some(match &invoice.customer_reference {
+        Some(reference) => format!("Invoice {} ({})", invoice.id, reference),
+        None => format!("Invoice {}", invoice.id),
+    });
+
+    let mut qr_code = create_qr_code(
+        caller,
+        amount,
+        invoice.currency.clone(),
+        description,
+        expiry_seconds,
+        None,
+        None,
+    ).await?;
+    qr_code.invoice_id = Some(invoice.id.clone());
+
+    // Re-read: the invoice may have changed while the rate was fetched
+    let mut invoice = get_own_invoice(&invoice_id, caller)?;
+    check_collectable(&invoice, &amount, &invoice_reserved(&invoice))?;
+    invoice.qr_ids.push(qr_code.id.clone());
+    save_invoice(&invoice);
+
+    QR_CODES.with(|qr_codes| {
+        qr_codes.borrow_mut().insert(qr_code.id.clone(), qr_code.clone());
+    });
+
+    ic_cdk::println!("🧾 QR {} created for invoice {}", qr_code.id, invoice.id);
+    Ok(qr_code)
+}
+
+#[query]
+#[candid_method(query)]
+fn get_invoice(invoice_id: String) -> Result<Invoice, String> {
+    get_own_invoice(&invoice_id, caller())
+}
+
+#[query]
+#[candid_method(query)]
+fn get_my_invoices() -> Vec<Invoice> {
+    let caller = caller();
+    let mut invoices: Vec<Invoice> = INVOICES.with(|invoices| {
+        invoices.borrow()
+            .iter()
+            .filter(|(_, invoice)| invoice.merchant == caller)
+            .map(|(_, invoice)| invoice)
+            .collect()
+    });
+    invoices.sort_by_key(|invoice| std::cmp::Reverse(invoice.created_at));
+    invoices
+}
+
+// ===================
+// PAYMENT REQUESTS
+// ===================
+
+fn save_payment_request(request: &PaymentRequest) {
+    PAYMENT_REQUESTS.with(|requests| {
+        requests.borrow_mut().insert(request.id.clone(), request.clone());
+    });
+}
+
+fn get_payment_request(request_id: &str) -> Result<PaymentRequest, String> {
+    PAYMENT_REQUESTS.with(|requests| requests.borrow().get(&request_id.to_string()))
+        .ok_or("Payment request not found".to_string())
+}
+
+// Called once the QR behind a request has been paid
+fn apply_payment_request_payment(qr_code: &QRCode, tx: &Transaction) {
+    let Some(mut request) = PAYMENT_REQUESTS.with(|requests| requests.borrow().get(&qr_code.id)) else { return };
+    mark_request_paid(&mut request, tx.id.clone(), time());
+    save_payment_request(&request);
+}
+
+// Declined or cancelled requests take their QR code with them
+fn close_payment_request_qr(request: &PaymentRequest, actor: Principal) {
+    let Some(mut qr_code) = QR_CODES.with(|qr_codes| qr_codes.borrow().get(&request.id)) else { return };
+    if cancel_qr_code(&mut qr_code, request.requester, time()).is_ok() {
+        QR_CODES.with(|qr_codes| {
+            qr_codes.borrow_mut().insert(qr_code.id.clone(), qr_code);
+        });
+        create_qr_usage_log(request.id.clone(), request.requester, actor, String::new(), QRUsageType::Cancelled);
+    }
+}
+
+fn payment_requests_where(filter: impl Fn(&PaymentRequest) -> bool) -> Vec<PaymentRequest> {
+    let now = time();
+    let mut requests: Vec<PaymentRequest> = PAYMENT_REQUESTS.with(|requests| {
+        requests.borrow()
+            .iter()
+            .map(|(_, request)| request)
+            .filter(|request| filter(request))
+            .collect()
+    });
+    for request in &mut requests {
+        refresh_request_status(request, now);
+    }
+    requests.sort_by_key(|request| std::cmp::Reverse(request.created_at));
+    requests
+}
+
+// Ask `from_user` (principal, email or @username) for money. The request is
+// a single-use QR code only they can pay, priced when it is created.
+#[update]
+#[candid_method(update)]
+async fn request_payment(
+    from_user: String,
+    amount: String,
+    currency: String,
+    note: Option<String>,
+    expiry_seconds: Option<u64>,
+) -> Result<PaymentRequest, String> {
+    let caller = caller();
+    if caller == Principal::anonymous() {
+        return Err("Anonymous users cannot request payments".to_string());
+    }
+    if !USERS.with(|users| users.borrow().contains_key(&caller)) {
+        return Err("User not registered. Please register first".to_string());
+    }
+
+    let payer = USERS.with(|users| {
+        resolve_recipient(&from_user, users.borrow().iter().map(|(_, user)| user))
+    })?;
+    if payer == caller {
+        return Err("Cannot request a payment from yourself".to_string());
+    }
+
+    let note = normalize_memo(note)?;
+    let amount = Money::parse(&amount, &currency)?;
+    let expiry_seconds = resolve_expiry_seconds(&current_qr_policy(), expiry_seconds)?;
+
+    let qr_code = create_qr_code(
+        caller,
+        amount,
+        currency,
+        note.clone(),
+        expiry_seconds,
+        Some(1),
+        Some(payer),
+    ).await?;
+    let request = build_payment_request(&qr_code, payer, note);
+
+    QR_CODES.with(|qr_codes| {
+        qr_codes.borrow_mut().insert(qr_code.id.clone(), qr_code.clone());
+    });
+    save_payment_request(&request);
+
+    ic_cdk::println!("🙏 Payment request {} sent to {}", request.id, payer.to_text());
+    Ok(request)
+}
+
+#[query]
+#[candid_method(query)]
+fn get_incoming_payment_requests() -> Vec<PaymentRequest> {
+    let caller = caller();
+    payment_requests_where(|request| request.payer == caller)
+}
+
+#[query]
+#[candid_method(query)]
+fn get_outgoing_payment_requests() -> Vec<PaymentRequest> {
+    let caller = caller();
+    payment_requests_where(|request| request.requester == caller)
+}
+
+// Pays the request from the caller's balance, same checks as process_payment
+#[update]
+#[candid_method(update)]
+async fn accept_payment_request(request_id: String) -> Result<Transaction, String> {
+    let caller = caller();
+    let request = get_payment_request(&request_id)?;
+    if request.payer != caller {
+        return Err("Payment request not found".to_string());
+    }
+    ensure_pending(&request, time())?;
+
+    pay_qr_code(caller, request_id, None, None, None).await
+}
+
+#[update]
+#[candid_method(update)]
+fn decline_payment_request(request_id: String) -> Result<PaymentRequest, String> {
+    let caller = caller();
+    let mut request = get_payment_request(&request_id)?;
+    decline_request(&mut request, caller, time())?;
+    save_payment_request(&request);
+    close_payment_request_qr(&request, caller);
+    Ok(request)
+}
+
+#[update]
+#[candid_method(update)]
+fn cancel_payment_request(request_id: String) -> Result<PaymentRequest, String> {
+    let caller = caller();
+    let mut request = get_payment_request(&request_id)?;
+    cancel_request(&mut request, caller, time())?;
+    save_payment_request(&request);
+    close_payment_request_qr(&request, caller);
+    Ok(request)
+}
+
+// ===================
+// SPLIT BILLS
+// ===================
+
+const SWEEP_INTERVAL_SECONDS: u64 = 60;
+const SPLIT_REFUNDS_PER_SWEEP: usize = 50;
+
+fn get_split_bill_record(bill_id: &str) -> Result<SplitBill, String> {
+    SPLIT_BILLS.with(|bills| bills.borrow().get(&bill_id.to_string()))
+        .ok_or("Split bill not found".to_string())
+}
+
+fn save_split_bill(bill: &SplitBill) {
+    SPLIT_BILLS.with(|bills| {
+        bills.borrow_mut().insert(bill.id.clone(), bill.clone());
+    });
+}
+
+fn held_contribution(contribution: &SplitContribution) -> Option<Transaction> {
+    TRANSACTIONS.with(|transactions| transactions.borrow().get(&contribution.transaction_id))
+}
+
+// Pays every held contribution out to the merchant and closes the code
+fn release_split_bill(bill: &mut SplitBill) -> Vec<Transaction> {
+    let now = time();
+    let mut completed = Vec::new();
+    let mut settled = Vec::new();
+    for contribution in &bill.contributions {
+        let Some(processing_tx) = held_contribution(contribution) else { continue };
+        let tx = complete_held_payment(&processing_tx, now);
+        settled.push((contribution.payer, tx.id.clone()));
+        completed.push(tx);
+    }
+    for log in settlement_usage_logs(bill, &settled, QRUsageType::PaymentCompleted, now) {
+        save_qr_usage_log(&log);
+    }
+    bill.status = SplitStatus::Completed;
+    bill.settled_at = Some(now);
+
+    if let Some(mut qr_code) = QR_CODES.with(|qr_codes| qr_codes.borrow().get(&bill.id)) {
+        qr_code.status = QRStatus::Paid;
+        QR_CODES.with(|qr_codes| {
+            qr_codes.borrow_mut().insert(qr_code.id.clone(), qr_code);
+        });
+    }
+
+    ic_cdk::println!("🍽️ Split bill {} covered by {} payers", bill.id, completed.len());
+    completed
+}
+
+// Gives every held contribution back; used on expiry and cancellation
+fn close_split_bill(bill_id: &str, status: SplitStatus) {
+    let Ok(mut bill) = get_split_bill_record(bill_id) else { return };
+    if bill.status != SplitStatus::Collecting {
+        return;
+    }
+
+    let now = time();
+    let (tx_status, reason) = match status {
+        SplitStatus::Cancelled => (TransactionStatus::Failed, "split bill cancelled"),
+        _ => (TransactionStatus::Expired, "split bill expired before it was covered"),
+    };
+    let mut settled = Vec::new();
+    for contribution in &bill.contributions {
+        let Some(processing_tx) = held_contribution(contribution) else { continue };
+        let tx = refund_held_payment(&processing_tx, tx_status.clone(), reason, now);
+        settled.push((contribution.payer, tx.id));
+    }
+    for log in settlement_usage_logs(&bill, &settled, QRUsageType::ContributionRefunded, now) {
+        save_qr_usage_log(&log);
+    }
+    bill.status = status;
+    bill.settled_at = Some(now);
+    save_split_bill(&bill);
+
+    ic_cdk::println!("↩️ Split bill {} closed, {} contributions refunded", bill.id, bill.contributions.len());
+}
+
+// One payer's part of a split bill: debited now, held until the bill is
+// covered and then released to the merchant together
+async fn contribute_to_split(
+    payer: Principal,
+    qr_code: &QRCode,
+    payer_amount: Option<String>,
+    transaction_hash: Option<String>,
+) -> Result<Transaction, String> {
+    let requested = payer_amount
+        .map(|amount| Money::parse(&amount, &qr_code.fiat_currency))
+        .transpose()?;
+    let bill = get_split_bill_record(&qr_code.id)?;
+    let amount = next_contribution(&bill, payer, requested)?;
+    let icp_amount = quote_fiat_payment(&bill.currency, &amount).await?;
+
+    // Re-read: others may have paid, or the bill closed, while the rate was fetched
+    let mut bill = get_split_bill_record(&qr_code.id)?;
+    if time() > bill.expires_at {
+        return Err("QR code has expired".to_string());
+    }
+    if next_contribution(&bill, payer, requested)? != amount {
+        return Err("The bill changed while your share was priced, please try again".to_string());
+    }
+
+    // Contributions are already held until the bill is covered, so there is
+    // nothing to hold a flagged one against
+    let context = risk_context(RiskAction::Payment, payer, Some(bill.merchant), icp_amount);
+    let risk = screen_risk(payer, Some(bill.merchant), &context)?;
+    let flagged = risk.decision == RiskDecision::Review;
+    record_risk_assessment(payer, Some(bill.merchant), &context, risk, None);
+    if flagged {
+        return Err("This payment needs a manual check and cannot join a split bill".to_string());
+    }
+
+    let processing_tx = hold_balance_payment(
+        payer,
+        bill.merchant,
+        icp_amount,
+        amount,
+        &bill.currency,
+        PaymentReference {
+            kind: TransactionKind::SplitBill,
+            reference_id: bill.id.clone(),
+            memo: None,
+            transaction_hash,
+        },
+    )?;
+    bill.contributions.push(SplitContribution {
+        payer,
+        amount,
+        icp_amount,
+        transaction_id: processing_tx.id.clone(),
+        timestamp: time(),
+    });
+    create_qr_usage_log(bill.id.clone(), bill.merchant, payer, processing_tx.id.clone(), QRUsageType::ContributionHeld);
+
+    let result = if is_covered(&bill) {
+        release_split_bill(&mut bill)
+            .into_iter()
+            .find(|tx| tx.id.starts_with(held_payment_base_id(&processing_tx)))
+            .unwrap_or(processing_tx)
+    } else {
+        processing_tx
+    };
+    save_split_bill(&bill);
+    Ok(result)
+}
+
+fn refund_expired_split_bills() {
+    let now = time();
+    let expired: Vec<String> = SPLIT_BILLS.with(|bills| {
+        bills.borrow()
+            .iter()
+            .filter(|(_, bill)| bill.status == SplitStatus::Collecting && now > bill.expires_at)
+            .take(SPLIT_REFUNDS_PER_SWEEP)
+            .map(|(id, _)| id)
+            .collect()
+    });
+    for bill_id in expired {
+        close_split_bill(&bill_id, SplitStatus::Refunded);
+    }
+}
+
+// Closes expired split bills and releases escrows whose window has passed
+fn start_sweeper() {
+    ic_cdk_timers::set_timer_interval(std::time::Duration::from_secs(SWEEP_INTERVAL_SECONDS), || {
+        refund_expired_split_bills();
+        release_due_escrows();
+    });
+}
+
+// A QR several people pay into until `fiat_amount` is covered. Contributions
+// are held and refunded if the bill is not covered before it expires.
+#[update]
+#[candid_method(update)]
+async fn create_split_qr(
+    fiat_amount: String,
+    fiat_currency: String,
+    mode: SplitMode,
+    description: Option<String>,
+    expiry_seconds: Option<u64>,
+) -> Result<SplitBill, String> {
+    let caller = caller();
+    if caller == Principal::anonymous() {
+        return Err("Anonymous users cannot generate QR codes".to_string());
+    }
+    if !USERS.with(|users| users.borrow().contains_key(&caller)) {
+        return Err("User not registered. Please register first".to_string());
+    }
+
+    let target = Money::parse(&fiat_amount, &fiat_currency)?;
+    validate_split_mode(&mode, &target, caller)?;
+    let expiry_seconds = resolve_expiry_seconds(&current_qr_policy(), expiry_seconds)?;
+
+    let mut qr_code = create_qr_code(
+        caller,
+        target,
+        fiat_currency,
+        description,
+        expiry_seconds,
+        None,
+        None,
+    ).await?;
+    qr_code.kind = QRKind::Split;
+
+    let bill = SplitBill {
+        id: qr_code.id.clone(),
+        merchant: caller,
+        currency: qr_code.fiat_currency.clone(),
+        target,
+        mode,
+        contributions: Vec::new(),
+        status: SplitStatus::Collecting,
+        created_at: qr_code.created_at,
+        expires_at: qr_code.expire_time,
+        settled_at: None,
+    };
+
+    QR_CODES.with(|qr_codes| {
+        qr_codes.borrow_mut().insert(qr_code.id.clone(), qr_code.clone());
+    });
+    save_split_bill(&bill);
+
+    ic_cdk::println!("Split QR code generated: {}", qr_code.id);
+    Ok(bill)
+}
+
+#[query]
+#[candid_method(query)]
+fn get_split_bill(bill_id: String) -> Result<SplitBill, String> {
+    get_split_bill_record(&bill_id)
+}
+
+// ===================
+// BATCH PAYOUTS
+// ===================
+
+fn get_own_payout_batch(batch_id: &str, caller: Principal) -> Result<PayoutBatch, String> {
+    PAYOUT_BATCHES.with(|batches| batches.borrow().get(&batch_id.to_string()))
+        .filter(|batch| batch.payer == caller)
+        .ok_or("Payout batch not found".to_string())
+}
+
+fn save_payout_batch(batch: &PayoutBatch) {
+    PAYOUT_BATCHES.with(|batches| {
+        batches.borrow_mut().insert(batch.id.clone(), batch.clone());
+    });
+}
+
+// Resolves and prices one entry; ICP amounts unless the batch has a fiat
+// rate
+fn price_payout_entry(
+    entry: &PayoutEntry,
+    payer: Principal,
+    users: &[User],
+    currency: &str,
+    rate: Option<f64>,
+) -> Result<PayoutResult, String> {
+    let recipient = resolve_recipient(&entry.recipient, users.iter().cloned())?;
+    if recipient == payer {
+        return Err("Cannot pay to yourself".to_string());
+    }
+    let memo = normalize_memo(entry.memo.clone())?;
+
+    let (amount, icp_amount) = match rate {
+        None => {
+            let icp_amount = parse_icp_amount(&entry.amount)?;
+            (Money::new(icp_amount, 8), icp_amount)
+        }
+        Some(rate) => {
+            let amount = Money::parse(&entry.amount, currency)?;
+            (amount, calculate_icp_amount(&amount, rate, money::RoundingMode::Up)?)
+        }
+    };
+    validate_transaction_amount(icp_amount)?;
+
+    Ok(PayoutResult {
+        recipient: entry.recipient.clone(),
+        recipient_id: Some(recipient),
+        amount,
+        icp_amount,
+        fee: calculate_transaction_fee(icp_amount),
+        memo,
+        transaction_id: None,
+        error: None,
+    })
+}
+
+// Credits the next chunk of recipients. The payer was debited for the whole
+// batch up front, so this cannot fail part-way.
+fn post_payout_chunk(batch: &mut PayoutBatch) {
+    let now = time();
+    let (batch_id, payer, currency) = (batch.id.clone(), batch.payer, batch.currency.clone());
+    let chunk = next_payout_chunk(batch);
+    let mut balances: HashMap<Principal, u64> = HashMap::new();
+
+    for index in chunk.clone() {
+        let result = &mut batch.results[index];
+        let Some(recipient) = result.recipient_id else { continue };
+
+        let tx = Transaction {
+            id: format!("TX_{}_{:04}", batch_id, index),
+            from: payer,
+            to: recipient,
+            amount: result.icp_amount,
+            fiat_currency: currency.clone(),
+            fiat_amount: result.amount,
+            icp_amount: result.icp_amount,
+            timestamp: now,
+            status: TransactionStatus::Completed,
+            qr_id: batch_id.clone(),
+            transaction_hash: None,
+            fee: result.fee,
+            kind: TransactionKind::Payout,
+            memo: result.memo.clone(),
+        };
+
+        let previous_balance = *balances.entry(recipient).or_insert_with(|| get_current_balance(recipient));
+        let received_log = BalanceChangeLog {
+            id: format!("BAL_RECEIVED_{}_{}", tx.id, now),
+            user_id: recipient,
+            change_type: BalanceChangeType::PaymentReceived,
+            amount: tx.amount,
+            previous_balance,
+            new_balance: previous_balance.saturating_add(tx.amount),
+            timestamp: now,
+            reference_id: tx.id.clone(),
+            description: format!("Payout received: {} {}", tx.fiat_amount, tx.fiat_currency),
+        };
+        balances.insert(recipient, received_log.new_balance);
+
+        BALANCE_CHANGE_LOGS.with(|logs| {
+            logs.borrow_mut().insert(received_log.id.clone(), received_log);
+        });
+        TRANSACTIONS.with(|transactions| {
+            transactions.borrow_mut().insert(tx.id.clone(), tx.clone());
+        });
+        result.transaction_id = Some(tx.id);
+    }
+
+    batch.next_index = chunk.end as u32;
+    if chunk.end >= batch.results.len() {
+        batch.status = PayoutBatchStatus::Completed;
+        batch.completed_at = Some(now);
+    }
+}
+
+fn continue_payout_batch(batch_id: &str) {
+    let Some(mut batch) = PAYOUT_BATCHES.with(|batches| batches.borrow().get(&batch_id.to_string())) else { return };
+    if batch.status != PayoutBatchStatus::Processing {
+        return;
+    }
+    post_payout_chunk(&mut batch);
+    save_payout_batch(&batch);
+
+    if batch.status == PayoutBatchStatus::Processing {
+        schedule_payout_continuation(batch.id.clone());
+    }
+}
+
+// The next chunk runs in its own message
+fn schedule_payout_continuation(batch_id: String) {
+    ic_cdk_timers::set_timer(std::time::Duration::ZERO, move || continue_payout_batch(&batch_id));
+}
+
+// Timers do not survive upgrades
+fn resume_payout_batches() {
+    let pending: Vec<String> = PAYOUT_BATCHES.with(|batches| {
+        batches.borrow()
+            .iter()
+            .filter(|(_, batch)| batch.status == PayoutBatchStatus::Processing)
+            .map(|(id, _)| id)
+            .collect()
+    });
+    for batch_id in pending {
+        schedule_payout_continuation(batch_id);
+    }
+}
+
+async fn run_batch_payout(payer: Principal, entries: Vec<PayoutEntry>, currency: Option<String>) -> Result<PayoutBatch, String> {
+    if payer == Principal::anonymous() {
+        return Err("Anonymous users cannot make payments".to_string());
+    }
+    if !USERS.with(|users| users.borrow().contains_key(&payer)) {
+        return Err("Payer not registered".to_string());
+    }
+    validate_payout_size(entries.len())?;
+
+    // One rate for the whole batch
+    let (currency, rate) = match currency.map(|c| c.trim().to_uppercase()).filter(|c| !c.is_empty() && c != "ICP") {
+        None => ("ICP".to_string(), None),
+        Some(currency) => {
+            let currency_info = get_currency_info(&currency)
+                .filter(|info| info.enabled)
+                .ok_or(format!("Unsupported currency: {}", currency))?;
+            let rate = fetch_live_exchange_rate(currency_info.code.clone()).await?;
+            (currency_info.code, Some(rate.rate))
+        }
+    };
+
+    let users: Vec<User> = USERS.with(|users| users.borrow().iter().map(|(_, user)| user).collect());
+    let results: Vec<PayoutResult> = entries.iter()
+        .map(|entry| {
+            price_payout_entry(entry, payer, &users, &currency, rate).unwrap_or_else(|e| PayoutResult {
+                recipient: entry.recipient.clone(),
+                recipient_id: None,
+                amount: Money::zero(&currency),
+                icp_amount: 0,
+                fee: 0,
+                memo: entry.memo.clone(),
+                transaction_id: None,
+                error: Some(e),
+            })
+        })
+        .collect();
+
+    let now = time();
+    let sequence = PAYOUT_BATCHES.with(|batches| batches.borrow().len());
+    let mut batch = PayoutBatch {
+        id: generate_payout_batch_id(payer, sequence),
+        payer,
+        currency,
+        total_icp: results.iter().map(|r| r.icp_amount).sum(),
+        total_fee: results.iter().map(|r| r.fee).sum(),
+        results,
+        status: PayoutBatchStatus::Processing,
+        next_index: 0,
+        created_at: now,
+        completed_at: None,
+    };
+
+    // All or nothing: one bad line rejects the batch
+    if batch.results.iter().any(|r| r.error.is_some()) {
+        batch.status = PayoutBatchStatus::Rejected;
+        return Ok(batch);
+    }
+
+    let payer_balance = get_current_balance(payer);
+    let total_cost = batch.total_icp.saturating_add(batch.total_fee);
+    if get_available_balance(payer) < total_cost {
+        return Err(format!(
+            "Insufficient balance: the batch needs {}",
+            format_icp(total_cost, DEFAULT_LANGUAGE)
+        ));
+    }
+    check_spending_limits(user_tier(payer), payer, batch.total_icp, batch.results.len() as u32)?;
+
+    let sent_log = BalanceChangeLog {
+        id: format!("BAL_PAYMENT_{}_{}", batch.id, now),
+        user_id: payer,
+        change_type: BalanceChangeType::PaymentSent,
+        amount: batch.total_icp,
+        previous_balance: payer_balance,
+        new_balance: payer_balance.saturating_sub(batch.total_icp),
+        timestamp: now,
+        reference_id: batch.id.clone(),
+        description: format!("Batch payout to {} recipients", batch.results.len()),
+    };
+    let fee_log = BalanceChangeLog {
+        id: format!("BAL_FEE_{}_{}", batch.id, now + 1),
+        user_id: payer,
+        change_type: BalanceChangeType::FeeDeducted,
+        amount: batch.total_fee,
+        previous_balance: sent_log.new_balance,
+        new_balance: sent_log.new_balance.saturating_sub(batch.total_fee),
+        timestamp: now + 1,
+        reference_id: batch.id.clone(),
+        description: format!("Transaction fees: {:.8} ICP", batch.total_fee as f64 / 100_000_000.0),
+    };
+    BALANCE_CHANGE_LOGS.with(|logs| {
+        let mut logs = logs.borrow_mut();
+        logs.insert(sent_log.id.clone(), sent_log);
+        logs.insert(fee_log.id.clone(), fee_log);
+    });
+
+    post_payout_chunk(&mut batch);
+    save_payout_batch(&batch);
+    if batch.status == PayoutBatchStatus::Processing {
+        schedule_payout_continuation(batch.id.clone());
+    }
+
+    ic_cdk::println!("💸 Payout batch {}: {} entries, {} credited so far", batch.id, batch.results.len(), batch.next_index);
+    Ok(batch)
+}
+
+// Pays many recipients from the caller's balance in one go. Amounts are ICP
+// unless a fiat `currency` is given. Invalid entries reject the whole batch
+// with per-entry errors; batches over PAYOUTS_PER_MESSAGE finish in
+// follow-up messages.
+#[update]
+#[candid_method(update)]
+async fn batch_payout(entries: Vec<PayoutEntry>, currency: Option<String>) -> Result<PayoutBatch, String> {
+    run_batch_payout(caller(), entries, currency).await
+}
+
+// Same as batch_payout, from "recipient,amount[,memo]" lines
+#[update]
+#[candid_method(update)]
+async fn batch_payout_csv(csv: String, currency: Option<String>) -> Result<PayoutBatch, String> {
+    let entries = parse_payout_csv(&csv)?;
+    run_batch_payout(caller(), entries, currency).await
+}
+
+// Credits the next chunk now rather than waiting for the scheduled one
+#[update]
+#[candid_method(update)]
+fn continue_batch_payout(batch_id: String) -> Result<PayoutBatch, String> {
+    let batch = get_own_payout_batch(&batch_id, caller())?;
+    continue_payout_batch(&batch.id);
+    get_own_payout_batch(&batch_id, caller())
+}
+
+#[query]
+#[candid_method(query)]
+fn get_payout_batch(batch_id: String) -> Result<PayoutBatch, String> {
+    get_own_payout_batch(&batch_id, caller())
+}
+
+#[query]
+#[candid_method(query)]
+fn get_my_payout_batches() -> Vec<PayoutBatch> {
+    let caller = caller();
+    let mut batches: Vec<PayoutBatch> = PAYOUT_BATCHES.with(|batches| {
+        batches.borrow()
+            .iter()
+            .filter(|(_, batch)| batch.payer == caller)
+            .map(|(_, batch)| batch)
+            .collect()
+    });
+    batches.sort_by_key(|batch| std::cmp::Reverse(batch.created_at));
+    batches
+}
+
+// ===================
+// ESCROW
+// ===================
+
+const ESCROW_RELEASES_PER_SWEEP: usize = 50;
+
+fn get_escrow_record(escrow_id: &str) -> Result<Escrow, String> {
+    ESCROWS.with(|escrows| escrows.borrow().get(&escrow_id.to_string()))
+        .ok_or("Escrow not found".to_string())
+}
+
+fn save_escrow(escrow: &Escrow) {
+    ESCROWS.with(|escrows| {
+        escrows.borrow_mut().insert(escrow.id.clone(), escrow.clone());
+    });
+}
+
+fn escrow_id_for(held_tx: &Transaction) -> String {
+    format!("ESC_{}", held_payment_base_id(held_tx).trim_start_matches("TX_"))
+}
+
+fn open_escrow(held_tx: &Transaction, release_seconds: u64) -> Escrow {
+    let escrow = Escrow {
+        id: escrow_id_for(held_tx),
+        qr_id: held_tx.qr_id.clone(),
+        payer: held_tx.from,
+        merchant: held_tx.to,
+        amount: held_tx.fiat_amount,
+        currency: held_tx.fiat_currency.clone(),
+        icp_amount: held_tx.amount,
+        transaction_id: held_tx.id.clone(),
+        status: EscrowStatus::Held,
+        created_at: held_tx.timestamp,
+        release_at: held_tx.timestamp.saturating_add(release_seconds.saturating_mul(1_000_000_000)),
+        dispute: None,
+        settled_at: None,
+    };
+    save_escrow(&escrow);
+    ic_cdk::println!("🔒 Escrow {} holds {}", escrow.id, held_tx.id);
+    escrow
+}
+
+// Moves the held funds as the resolution says and closes the escrow
+fn settle_escrow(escrow: &mut Escrow, resolution: &EscrowResolution) -> Result<(), String> {
+    let (_, payer_part) = resolution_amounts(escrow.icp_amount, resolution)?;
+    let held_tx = TRANSACTIONS.with(|transactions| transactions.borrow().get(&escrow.transaction_id))
+        .ok_or("Held transaction not found")?;
+
+    let now = time();
+    match resolution {
+        EscrowResolution::Release => {
+            let completed_tx = complete_held_payment(&held_tx, now);
+            release_qr_hold(&held_tx, &completed_tx);
+        }
+        EscrowResolution::Refund => {
+            let refunded_tx = refund_held_payment(&held_tx, TransactionStatus::Failed, "escrow refunded", now);
+            refund_qr_hold(&held_tx, &refunded_tx);
+        }
+        EscrowResolution::Split { .. } => {
+            let completed_tx = split_held_payment(&held_tx, payer_part, "escrow split", now);
+            release_qr_hold(&held_tx, &completed_tx);
+        }
+    }
+    escrow.status = resolved_status(resolution);
+    escrow.settled_at = Some(now);
+    save_escrow(escrow);
+    Ok(())
+}
+
+fn release_due_escrows() {
+    let now = time();
+    let due: Vec<Escrow> = ESCROWS.with(|escrows| {
+        escrows.borrow()
+            .iter()
+            .filter(|(_, escrow)| is_due_for_release(escrow, now) && !under_risk_review(&escrow.transaction_id))
+            .take(ESCROW_RELEASES_PER_SWEEP)
+            .map(|(_, escrow)| escrow)
+            .collect()
+    });
+    for mut escrow in due {
+        if let Err(e) = settle_escrow(&mut escrow, &EscrowResolution::Release) {
+            ic_cdk::println!("⚠️ Escrow {} could not be released: {}", escrow.id, e);
+        }
+    }
+}
+
+// The payer confirms delivery
+#[update]
+#[candid_method(update)]
+fn release_escrow(escrow_id: String) -> Result<Escrow, String> {
+    let mut escrow = get_escrow_record(&escrow_id)?;
+    check_release(&escrow, caller())?;
+    if under_risk_review(&escrow.transaction_id) {
+        return Err("Payment is under review and cannot be released yet".to_string());
+    }
+    settle_escrow(&mut escrow, &EscrowResolution::Release)?;
+    Ok(escrow)
+}
+
+// Either party; stops the automatic release until an admin decides
+#[update]
+#[candid_method(update)]
+fn dispute_escrow(escrow_id: String, reason: String) -> Result<Escrow, String> {
+    let mut escrow = get_escrow_record(&escrow_id)?;
+    open_escrow_dispute(&mut escrow, caller(), &reason, time())?;
+    save_escrow(&escrow);
+    Ok(escrow)
+}
+
+#[update]
+#[candid_method(update)]
+fn resolve_escrow_dispute(escrow_id: String, resolution: EscrowResolution, note: Option<String>) -> Result<Escrow, String> {
+    let admin = ensure_admin()?;
+    let mut escrow = get_escrow_record(&escrow_id)?;
+    if escrow.status != EscrowStatus::Disputed {
+        return Err("Escrow is not disputed".to_string());
+    }
+    let note = note.map(|note| validate_dispute_text(&note)).transpose()?;
+
+    if let Some(dispute) = escrow.dispute.as_mut() {
+        dispute.resolution = Some(resolution.clone());
+        dispute.resolved_by = Some(admin);
+        dispute.note = note;
+    }
+    settle_escrow(&mut escrow, &resolution)?;
+    Ok(escrow)
+}
+
+#[query]
+#[candid_method(query)]
+fn get_escrow(escrow_id: String) -> Result<Escrow, String> {
+    let escrow = get_escrow_record(&escrow_id)?;
+    let caller = caller();
+    if !is_escrow_party(&escrow, caller) && !ic_cdk::api::is_controller(&caller) {
+        return Err("Escrow not found".to_string());
+    }
+    Ok(escrow)
+}
+
+// Escrows the caller paid into or is owed
+#[query]
+#[candid_method(query)]
+fn get_my_escrows() -> Vec<Escrow> {
+    let caller = caller();
+    let mut escrows: Vec<Escrow> = ESCROWS.with(|escrows| {
+        escrows.borrow()
+            .iter()
+            .filter(|(_, escrow)| is_escrow_party(escrow, caller))
+            .map(|(_, escrow)| escrow)
+            .collect()
+    });
+    escrows.sort_by_key(|escrow| std::cmp::Reverse(escrow.created_at));
+    escrows
+}
+
+// Open disputes waiting for an admin
+#[query]
+#[candid_method(query)]
+fn get_disputed_escrows() -> Result<Vec<Escrow>, String> {
+    ensure_admin()?;
+    Ok(ESCROWS.with(|escrows| {
+        escrows.borrow()
+            .iter()
+            .filter(|(_, escrow)| escrow.status == EscrowStatus::Disputed)
+            .map(|(_, escrow)| escrow)
+            .collect()
+    }))
+}
+
 // ===================
-// QR CODE MANAGEMENT
+// DISPUTES
 // ===================
 
+fn get_dispute_record(dispute_id: &str) -> Result<Dispute, String> {
+    DISPUTES.with(|disputes| disputes.borrow().get(&dispute_id.to_string()))
+        .ok_or("Dispute not found".to_string())
+}
+
+fn save_dispute(dispute: &Dispute) {
+    DISPUTES.with(|disputes| {
+        disputes.borrow_mut().insert(dispute.id.clone(), dispute.clone());
+    });
+}
+
+// Takes a refund back from the merchant and passes it on to the payer. The
+// payer only gets what the merchant's balance could cover; returns that part.
+fn post_dispute_refund(dispute: &Dispute, refund: u64, now: u64) -> u64 {
+    let merchant_balance = get_current_balance(dispute.merchant);
+    let recovered = refund.min(merchant_balance);
+    if recovered == 0 {
+        return 0;
+    }
+    let payer_balance = get_current_balance(dispute.payer);
+
+    let logs = [
+        BalanceChangeLog {
+            id: format!("BAL_CHARGEBACK_{}_{}", dispute.id, now),
+            user_id: dispute.merchant,
+            change_type: BalanceChangeType::Chargeback,
+            amount: recovered,
+            previous_balance: merchant_balance,
+            new_balance: merchant_balance - recovered,
+            timestamp: now,
+            reference_id: dispute.transaction_id.clone(),
+            description: format!("Chargeback: {}", dispute.id),
+        },
+        BalanceChangeLog {
+            id: format!("BAL_REFUND_{}_{}", dispute.id, now),
+            user_id: dispute.payer,
+            change_type: BalanceChangeType::Refund,
+            amount: recovered,
+            previous_balance: payer_balance,
+            new_balance: payer_balance.saturating_add(recovered),
+            timestamp: now,
+            reference_id: dispute.transaction_id.clone(),
+            description: format!("Dispute refund: {}", dispute.id),
+        },
+    ];
+
+    BALANCE_CHANGE_LOGS.with(|balance_logs| {
+        let mut balance_logs = balance_logs.borrow_mut();
+        for log in logs {
+            balance_logs.insert(log.id.clone(), log);
+        }
+    });
+    recovered
+}
+
 #[update]
 #[candid_method(update)]
-async fn generate_qr(
-    fiat_amount: f64,
-    fiat_currency: String,
-    description: Option<String>,
-) -> Result<QRCode, String> {
+fn open_dispute(transaction_id: String, reason: String, evidence: Option<String>) -> Result<Dispute, String> {
     let caller = caller();
-    if caller == Principal::anonymous() {
-        return Err("Anonymous users cannot generate QR codes".to_string());
+    let now = time();
+    let tx = TRANSACTIONS.with(|transactions| transactions.borrow().get(&transaction_id))
+        .ok_or("Transaction not found")?;
+    check_disputable(&tx, caller, now)?;
+
+    let escrow_id = format!("ESC_{}", payment_base_id(&tx.id).trim_start_matches("TX_"));
+    if ESCROWS.with(|escrows| escrows.borrow().contains_key(&escrow_id)) {
+        return Err("Escrowed payments are disputed through the escrow".to_string());
     }
-
-    // Check if user is registered
-    let user = USERS.with(|users| users.borrow().get(&caller));
-    if user.is_none() {
-        return Err("User not registered. Please register first".to_string());
+    if DISPUTES.with(|disputes| disputes.borrow().contains_key(&dispute_id(&tx.id))) {
+        return Err("This transaction has already been disputed".to_string());
     }
 
-    let qr_code = create_qr_code(caller, fiat_amount, fiat_currency, description).await?;
+    let dispute = build_dispute(&tx, &reason, evidence, now)?;
+    save_dispute(&dispute);
+    ic_cdk::println!("⚖️ Dispute {} opened against {}", dispute.id, tx.id);
+    Ok(dispute)
+}
 
-    QR_CODES.with(|qr_codes| {
-        qr_codes.borrow_mut().insert(qr_code.id.clone(), qr_code.clone());
-    });
+#[update]
+#[candid_method(update)]
+fn respond_to_dispute(dispute_id: String, response: String) -> Result<Dispute, String> {
+    let mut dispute = get_dispute_record(&dispute_id)?;
+    add_merchant_response(&mut dispute, caller(), &response, time())?;
+    save_dispute(&dispute);
+    Ok(dispute)
+}
 
-    ic_cdk::println!("QR code generated: {}", qr_code.id);
-    Ok(qr_code)
+#[update]
+#[candid_method(update)]
+fn withdraw_dispute(dispute_id: String) -> Result<Dispute, String> {
+    let mut dispute = get_dispute_record(&dispute_id)?;
+    withdraw_case(&mut dispute, caller(), time())?;
+    save_dispute(&dispute);
+    Ok(dispute)
+}
+
+#[update]
+#[candid_method(update)]
+fn resolve_dispute(dispute_id: String, decision: DisputeDecision, note: Option<String>) -> Result<Dispute, String> {
+    let admin = ensure_admin()?;
+    let mut dispute = get_dispute_record(&dispute_id)?;
+    let refund = refund_for_decision(&dispute, &decision)?;
+    let note = note.map(|note| validate_dispute_text(&note)).transpose()?;
+
+    let now = time();
+    let recovered = if refund > 0 { post_dispute_refund(&dispute, refund, now) } else { 0 };
+    record_decision(&mut dispute, decision, admin, note, refund, recovered, now);
+    save_dispute(&dispute);
+    Ok(dispute)
 }
 
 #[query]
 #[candid_method(query)]
-fn get_qr(qr_id: String) -> Option<QRCode> {
-    QR_CODES.with(|qr_codes| qr_codes.borrow().get(&qr_id))
+fn get_dispute(dispute_id: String) -> Result<Dispute, String> {
+    let dispute = get_dispute_record(&dispute_id)?;
+    let caller = caller();
+    if !is_dispute_party(&dispute, caller) && !ic_cdk::api::is_controller(&caller) {
+        return Err("Dispute not found".to_string());
+    }
+    Ok(dispute)
 }
 
+// Disputes the caller opened or received
 #[query]
 #[candid_method(query)]
-fn get_qr_display_info_by_id(qr_id: String) -> Option<QRDisplayInfo> {
-    QR_CODES.with(|qr_codes| {
-        qr_codes.borrow().get(&qr_id).map(|qr| get_qr_display_info(&qr))
-    })
+fn get_my_disputes() -> Vec<Dispute> {
+    let caller = caller();
+    let mut disputes: Vec<Dispute> = DISPUTES.with(|disputes| {
+        disputes.borrow()
+            .iter()
+            .filter(|(_, dispute)| is_dispute_party(dispute, caller))
+            .map(|(_, dispute)| dispute)
+            .collect()
+    });
+    disputes.sort_by_key(|dispute| std::cmp::Reverse(dispute.created_at));
+    disputes
 }
 
+// Cases waiting for an admin decision, oldest first
 #[query]
 #[candid_method(query)]
-fn get_user_qr_codes() -> Vec<QRCode> {
-    let caller = caller();
-    QR_CODES.with(|qr_codes| {
-        qr_codes
-            .borrow()
+fn get_open_disputes() -> Result<Vec<Dispute>, String> {
+    ensure_admin()?;
+    let mut disputes: Vec<Dispute> = DISPUTES.with(|disputes| {
+        disputes.borrow()
             .iter()
-            .filter(|(_, qr)| qr.user_id == caller)
-            .map(|(_, qr)| qr.clone())
+            .filter(|(_, dispute)| is_dispute_open(dispute))
+            .map(|(_, dispute)| dispute)
+            .collect()
+    });
+    disputes.sort_by_key(|dispute| dispute.created_at);
+    Ok(disputes)
+}
+
+// ===================
+// SUBSCRIPTIONS
+// ===================
+
+const SUBSCRIPTION_RUN_INTERVAL_SECONDS: u64 = 60;
+const SUBSCRIPTION_CHARGES_PER_RUN: usize = 20;
+
+fn get_plan(plan_id: &str) -> Result<SubscriptionPlan, String> {
+    SUBSCRIPTION_PLANS.with(|plans| plans.borrow().get(&plan_id.to_string()))
+        .ok_or("Plan not found".to_string())
+}
+
+fn get_subscription(subscription_id: &str) -> Result<Subscription, String> {
+    SUBSCRIPTIONS.with(|subscriptions| subscriptions.borrow().get(&subscription_id.to_string()))
+        .ok_or("Subscription not found".to_string())
+}
+
+fn save_subscription(subscription: &Subscription) {
+    SUBSCRIPTIONS.with(|subscriptions| {
+        subscriptions.borrow_mut().insert(subscription.id.clone(), subscription.clone());
+    });
+}
+
+fn subscription_charges(subscription_id: &str) -> Vec<SubscriptionCharge> {
+    let prefix = format!("{}_", subscription_id);
+    SUBSCRIPTION_CHARGES.with(|charges| {
+        charges.borrow()
+            .range(prefix.clone()..)
+            .take_while(|(key, _)| key.starts_with(&prefix))
+            .map(|(_, charge)| charge)
             .collect()
     })
 }
 
+fn record_subscription_charge(subscription_id: &str, plan: &SubscriptionPlan, icp_amount: u64, result: &Result<Transaction, String>) {
+    let sequence = subscription_charges(subscription_id).len();
+    let charge = SubscriptionCharge {
+        id: format!("{}_{:06}", subscription_id, sequence),
+        subscription_id: subscription_id.to_string(),
+        amount: plan.amount,
+        currency: plan.currency.clone(),
+        icp_amount,
+        succeeded: result.is_ok(),
+        transaction_id: result.as_ref().ok().map(|tx| tx.id.clone()),
+        error: result.as_ref().err().cloned(),
+        timestamp: time(),
+    };
+    SUBSCRIPTION_CHARGES.with(|charges| {
+        charges.borrow_mut().insert(charge.id.clone(), charge);
+    });
+}
+
+// One period's payment, through the same balance path as QR payments or
+// pulled from the payer's ICRC-2 allowance
+async fn charge_subscription(subscription: &Subscription, icp_amount: u64, plan: &SubscriptionPlan) -> Result<Transaction, String> {
+    match subscription.funding {
+        SubscriptionFunding::Balance => execute_balance_payment(
+            subscription.payer,
+            subscription.merchant,
+            icp_amount,
+            plan.amount,
+            &plan.currency,
+            PaymentReference {
+                kind: TransactionKind::Subscription,
+                reference_id: subscription.id.clone(),
+                memo: None,
+                transaction_hash: None,
+            },
+        ),
+        SubscriptionFunding::Icrc2Allowance => {
+            let ledger = current_config().ledger_canister_id.ok_or("Wallet payments are not enabled")?;
+            validate_transaction_amount(icp_amount)?;
+
+            let block = icrc::transfer_from(
+                ledger,
+                Account { owner: subscription.payer, subaccount: None },
+                icrc::merchant_account(ic_cdk::id(), subscription.merchant),
+                icp_amount,
+                subscription.id.as_bytes().to_vec(),
+                time(),
+            ).await?;
+
+            let tx = Transaction {
+                id: format!("TX_SUB_{}_{}", subscription.id, block),
+                from: subscription.payer,
+                to: subscription.merchant,
+                amount: icp_amount,
+                fiat_currency: plan.currency.clone(),
+                fiat_amount: plan.amount,
+                icp_amount,
+                timestamp: time(),
+                status: TransactionStatus::Completed,
+                qr_id: subscription.id.clone(),
+                transaction_hash: Some(format!("{}:{}", ledger.to_text(), block)),
+                fee: 0,
+                kind: TransactionKind::Subscription,
+                memo: None,
+            };
+            record_wallet_payment(&tx);
+            Ok(tx)
+        }
+    }
+}
+
+// Prices and charges the current period, then moves the subscription on
+// (next period, or the dunning schedule on failure)
+async fn run_subscription_charge(subscription_id: &str) -> Result<Transaction, String> {
+    // Held until the outcome is saved so a scheduler run and the signup
+    // cannot charge the same period twice
+    let Some(_charging) = InFlight::claim(subscription_id) else {
+        return Err("Subscription is already being charged".to_string());
+    };
+    let subscription = get_subscription(subscription_id)?;
+    let plan = get_plan(&subscription.plan_id)?;
+
+    let priced = match fetch_live_exchange_rate(plan.currency.clone()).await {
+        Ok(rate) => calculate_icp_amount(&plan.amount, rate.rate, money::RoundingMode::Up),
+        Err(e) => Err(e),
+    };
+
+    // Re-read: it may have been paused or cancelled while the rate was fetched
+    let subscription = get_subscription(subscription_id)?;
+    if !is_due(&subscription, time()) {
+        return Err("Subscription is no longer due".to_string());
+    }
+
+    let (icp_amount, result) = match priced {
+        Ok(icp_amount) => (icp_amount, charge_subscription(&subscription, icp_amount, &plan).await),
+        Err(e) => (0, Err(e)),
+    };
+    record_subscription_charge(subscription_id, &plan, icp_amount, &result);
+
+    let mut subscription = get_subscription(subscription_id)?;
+    let now = time();
+    match &result {
+        Ok(tx) => {
+            apply_charge_success(&mut subscription, &plan, now);
+            ic_cdk::println!("🔁 Subscription {} charged: {}", subscription.id, tx.id);
+        }
+        Err(e) => {
+            apply_charge_failure(&mut subscription, e.clone(), now);
+            ic_cdk::println!("⚠️ Subscription {} charge failed ({}): {}", subscription.id, subscription.failed_attempts, e);
+        }
+    }
+    save_subscription(&subscription);
+
+    result
+}
+
+async fn run_due_subscriptions() {
+    let Some(_running) = InFlight::claim(SUBSCRIPTION_RUN_TASK) else { return };
+
+    let now = time();
+    let due: Vec<String> = SUBSCRIPTIONS.with(|subscriptions| {
+        subscriptions.borrow()
+            .iter()
+            .filter(|(_, subscription)| is_due(subscription, now))
+            .take(SUBSCRIPTION_CHARGES_PER_RUN)
+            .map(|(id, _)| id)
+            .collect()
+    });
+
+    for subscription_id in due {
+        let _ = run_subscription_charge(&subscription_id).await;
+    }
+}
+
+fn start_subscription_scheduler() {
+    ic_cdk_timers::set_timer_interval(std::time::Duration::from_secs(SUBSCRIPTION_RUN_INTERVAL_SECONDS), || {
+        ic_cdk::spawn(run_due_subscriptions())
+    });
+}
+
 #[update]
 #[candid_method(update)]
-async fn validate_qr_code(qr_id: String) -> Result<QRDisplayInfo, String> {
-    let qr_code = QR_CODES.with(|qr_codes| qr_codes.borrow().get(&qr_id))
-        .ok_or("QR code not found")?;
+fn create_subscription_plan(
+    name: String,
+    amount: String,
+    currency: String,
+    interval: BillingInterval,
+    interval_count: Option<u32>,
+) -> Result<SubscriptionPlan, String> {
+    let caller = caller();
+    if !USERS.with(|users| users.borrow().contains_key(&caller)) {
+        return Err("User not registered. Please register first".to_string());
+    }
+
+    let currency_info = get_currency_info(&currency)
+        .filter(|info| info.enabled)
+        .ok_or(format!("Unsupported currency: {}", currency))?;
+    let amount = Money::parse(&amount, &currency_info.code)?;
+    let interval_count = interval_count.unwrap_or(1);
+    validate_plan(&name, &amount, interval_count)?;
+
+    let sequence = SUBSCRIPTION_PLANS.with(|plans| plans.borrow().len());
+    let plan = SubscriptionPlan {
+        id: generate_subscription_id("PLAN", caller, sequence),
+        merchant: caller,
+        name: name.trim().to_string(),
+        amount,
+        currency: currency_info.code,
+        interval,
+        interval_count,
+        active: true,
+        created_at: time(),
+    };
 
-    is_qr_code_valid(&qr_code)?;
-    Ok(get_qr_display_info(&qr_code))
+    SUBSCRIPTION_PLANS.with(|plans| {
+        plans.borrow_mut().insert(plan.id.clone(), plan.clone());
+    });
+    Ok(plan)
 }
 
-// ===================
-// TRANSACTION MANAGEMENT
-// ===================
+#[update]
+#[candid_method(update)]
+fn deactivate_subscription_plan(plan_id: String) -> Result<SubscriptionPlan, String> {
+    let mut plan = get_plan(&plan_id)?;
+    if plan.merchant != caller() {
+        return Err("Plan not found".to_string());
+    }
+    plan.active = false;
+    SUBSCRIPTION_PLANS.with(|plans| {
+        plans.borrow_mut().insert(plan.id.clone(), plan.clone());
+    });
+    Ok(plan)
+}
+
+#[query]
+#[candid_method(query)]
+fn get_subscription_plan(plan_id: String) -> Result<SubscriptionPlan, String> {
+    get_plan(&plan_id)
+}
+
+#[query]
+#[candid_method(query)]
+fn get_my_subscription_plans() -> Vec<SubscriptionPlan> {
+    let caller = caller();
+    SUBSCRIPTION_PLANS.with(|plans| {
+        plans.borrow()
+            .iter()
+            .filter(|(_, plan)| plan.merchant == caller)
+            .map(|(_, plan)| plan)
+            .collect()
+    })
+}
 
+// The payer's approval; the first period is charged right away
 #[update]
 #[candid_method(update)]
-async fn process_payment(qr_id: String, transaction_hash: Option<String>) -> Result<Transaction, String> {
+async fn subscribe(plan_id: String, funding: SubscriptionFunding) -> Result<Subscription, String> {
     let caller = caller();
     if caller == Principal::anonymous() {
-        return Err("Anonymous users cannot make payments".to_string());
+        return Err("Anonymous users cannot subscribe".to_string());
     }
-
-    // Check if payer is registered
-    let payer_exists = USERS.with(|users| users.borrow().contains_key(&caller));
-    if !payer_exists {
+    if !USERS.with(|users| users.borrow().contains_key(&caller)) {
         return Err("Payer not registered".to_string());
     }
 
-    // Get and validate QR code
-    let qr_code = QR_CODES.with(|qr_codes| {
-        qr_codes.borrow().get(&qr_id)
-    }).ok_or("QR code not found")?;
+    let plan = get_plan(&plan_id)?;
+    if !plan.active {
+        return Err("Plan is no longer available".to_string());
+    }
+    if plan.merchant == caller {
+        return Err("Cannot subscribe to your own plan".to_string());
+    }
+    let already_subscribed = SUBSCRIPTIONS.with(|subscriptions| {
+        subscriptions.borrow().iter().any(|(_, subscription)| {
+            subscription.plan_id == plan_id
+                && subscription.payer == caller
+                && subscription.status != SubscriptionStatus::Cancelled
+        })
+    });
+    if already_subscribed {
+        return Err("Already subscribed to this plan".to_string());
+    }
 
-    // Check if QR already used via immutable logs
-    if is_qr_already_used(&qr_id) {
-        return Err("QR code has already been used".to_string());
+    let now = time();
+    let sequence = SUBSCRIPTIONS.with(|subscriptions| subscriptions.borrow().len());
+    let subscription = Subscription {
+        id: generate_subscription_id("SUB", caller, sequence),
+        plan_id,
+        merchant: plan.merchant,
+        payer: caller,
+        funding,
+        status: SubscriptionStatus::Active,
+        next_charge_at: now,
+        failed_attempts: 0,
+        charge_count: 0,
+        last_error: None,
+        created_at: now,
+        cancelled_at: None,
+    };
+    save_subscription(&subscription);
+
+    // No dunning for the first period: a failed signup is simply cancelled
+    if let Err(e) = run_subscription_charge(&subscription.id).await {
+        let mut subscription = get_subscription(&subscription.id)?;
+        if subscription.status != SubscriptionStatus::Cancelled {
+            cancel_subscription(&mut subscription, time())?;
+            save_subscription(&subscription);
+        }
+        return Err(e);
     }
 
-    // Validate QR code
-    is_qr_code_valid(&qr_code)?;
+    get_subscription(&subscription.id)
+}
 
-    // Check if payer is different from payee
-    if caller == qr_code.user_id {
-        return Err("Cannot pay to yourself".to_string());
+#[update]
+#[candid_method(update)]
+fn pause_my_subscription(subscription_id: String) -> Result<Subscription, String> {
+    let mut subscription = get_subscription(&subscription_id)?;
+    if !is_party(&subscription, caller()) {
+        return Err("Subscription not found".to_string());
     }
+    pause_subscription(&mut subscription)?;
+    save_subscription(&subscription);
+    Ok(subscription)
+}
 
-    // Check if recipient is registered
-    let recipient_exists = USERS.with(|users| users.borrow().contains_key(&qr_code.user_id));
-    if !recipient_exists {
-        return Err("Recipient not registered".to_string());
+#[update]
+#[candid_method(update)]
+fn resume_my_subscription(subscription_id: String) -> Result<Subscription, String> {
+    let mut subscription = get_subscription(&subscription_id)?;
+    if !is_party(&subscription, caller()) {
+        return Err("Subscription not found".to_string());
+    }
+    resume_subscription(&mut subscription)?;
+    save_subscription(&subscription);
+    Ok(subscription)
+}
+
+#[update]
+#[candid_method(update)]
+fn cancel_my_subscription(subscription_id: String) -> Result<Subscription, String> {
+    let mut subscription = get_subscription(&subscription_id)?;
+    if !is_party(&subscription, caller()) {
+        return Err("Subscription not found".to_string());
+    }
+    cancel_subscription(&mut subscription, time())?;
+    save_subscription(&subscription);
+    Ok(subscription)
+}
+
+// Subscriptions the caller pays for or bills
+#[query]
+#[candid_method(query)]
+fn get_my_subscriptions() -> Vec<Subscription> {
+    let caller = caller();
+    SUBSCRIPTIONS.with(|subscriptions| {
+        subscriptions.borrow()
+            .iter()
+            .filter(|(_, subscription)| is_party(subscription, caller))
+            .map(|(_, subscription)| subscription)
+            .collect()
+    })
+}
+
+#[query]
+#[candid_method(query)]
+fn get_subscription_history(subscription_id: String) -> Result<Vec<SubscriptionCharge>, String> {
+    let subscription = get_subscription(&subscription_id)?;
+    if !is_party(&subscription, caller()) {
+        return Err("Subscription not found".to_string());
     }
+    Ok(subscription_charges(&subscription_id))
+}
+
+// ===================
+// TRANSACTION MANAGEMENT
+// ===================
+
+// What a balance payment is for; copied onto each of its transactions
+struct PaymentReference {
+    kind: TransactionKind,
+    // QR code or subscription id, empty for direct transfers
+    reference_id: String,
+    memo: Option<String>,
+    transaction_hash: Option<String>,
+}
+
+// Moves a payment between internal balances, fee charged to the payer.
+// Shared by QR payments, peer transfers and scheduled subscription charges.
+fn execute_balance_payment(
+    payer: Principal,
+    payee: Principal,
+    payment_icp: u64,
+    payment_fiat: Money,
+    fiat_currency: &str,
+    reference: PaymentReference,
+) -> Result<Transaction, String> {
+    let processing_tx = debit_payer(payer, payee, payment_icp, payment_fiat, fiat_currency, reference)?;
+    Ok(credit_payee(&processing_tx, processing_tx.timestamp + 1))
+}
 
+// Debits the payer (amount and fee) and records the payment as PROCESSING
+fn debit_payer(
+    payer: Principal,
+    payee: Principal,
+    payment_icp: u64,
+    payment_fiat: Money,
+    fiat_currency: &str,
+    reference: PaymentReference,
+) -> Result<Transaction, String> {
     // Validate transaction amount
-    validate_transaction_amount(qr_code.icp_amount)?;
+    validate_transaction_amount(payment_icp)?;
 
     // Get current balances from balance logs
-    let payer_balance = get_current_balance(caller);
-    let recipient_balance = get_current_balance(qr_code.user_id);
+    let payer_balance = get_current_balance(payer);
 
-    let total_cost = qr_code.icp_amount + calculate_transaction_fee(qr_code.icp_amount);
-    if payer_balance < total_cost {
+    // Funds held for open disputes cannot be spent
+    let total_cost = payment_icp + calculate_transaction_fee(payment_icp);
+    if get_available_balance(payer) < total_cost {
         return Err("Insufficient balance".to_string());
     }
+    check_spending_limits(user_tier(payer), payer, payment_icp, 1)?;
 
     // Generate unique transaction ID for this payment flow
-    let base_tx_id = generate_transaction_id(caller, qr_code.user_id, qr_code.icp_amount);
+    let base_tx_id = generate_transaction_id(payer, payee, payment_icp);
     let current_time = time();
 
 
     let pending_tx = Transaction {
         id: format!("{}_PENDING_{}", base_tx_id, current_time),
-        from: caller,
-        to: qr_code.user_id,
-        amount: qr_code.icp_amount,
-        fiat_currency: qr_code.fiat_currency.clone(),
-        fiat_amount: qr_code.fiat_amount,
-        icp_amount: qr_code.icp_amount,
+        from: payer,
+        to: payee,
+        amount: payment_icp,
+        fiat_currency: fiat_currency.to_string(),
+        fiat_amount: payment_fiat,
+        icp_amount: payment_icp,
         timestamp: current_time,
         status: TransactionStatus::Pending,
-        qr_id: qr_code.id.clone(),
-        transaction_hash: transaction_hash.clone(),
-        fee: calculate_transaction_fee(qr_code.icp_amount),
+        qr_id: reference.reference_id.clone(),
+        transaction_hash: reference.transaction_hash.clone(),
+        fee: calculate_transaction_fee(payment_icp),
+        kind: reference.kind,
+        memo: reference.memo.clone(),
     };
 
     TRANSACTIONS.with(|transactions| {
@@ -1397,17 +4062,19 @@ async fn process_payment(qr_id: String, transaction_hash: Option<String>) -> Res
 
     let processing_tx = Transaction {
         id: format!("{}_PROCESSING_{}", base_tx_id, current_time + 1),
-        from: caller,
-        to: qr_code.user_id,
-        amount: qr_code.icp_amount,
-        fiat_currency: qr_code.fiat_currency.clone(),
-        fiat_amount: qr_code.fiat_amount,
-        icp_amount: qr_code.icp_amount,
+        from: payer,
+        to: payee,
+        amount: payment_icp,
+        fiat_currency: fiat_currency.to_string(),
+        fiat_amount: payment_fiat,
+        icp_amount: payment_icp,
         timestamp: current_time + 1,
         status: TransactionStatus::Processing,
-        qr_id: qr_code.id.clone(),
-        transaction_hash: transaction_hash.clone(),
-        fee: calculate_transaction_fee(qr_code.icp_amount),
+        qr_id: reference.reference_id.clone(),
+        transaction_hash: reference.transaction_hash.clone(),
+        fee: calculate_transaction_fee(payment_icp),
+        kind: reference.kind,
+        memo: reference.memo.clone(),
     };
 
     TRANSACTIONS.with(|transactions| {
@@ -1417,30 +4084,28 @@ async fn process_payment(qr_id: String, transaction_hash: Option<String>) -> Res
     ic_cdk::println!("⚙️ Created PROCESSING transaction: {}", processing_tx.id);
 
 
-    let fee_amount = calculate_transaction_fee(qr_code.icp_amount);
+    let fee_amount = calculate_transaction_fee(payment_icp);
     let base_time = time();
     
 
-    let total_deduction = qr_code.icp_amount + fee_amount;
+    let total_deduction = payment_icp + fee_amount;
     let payer_new_balance = payer_balance.saturating_sub(total_deduction);
-    let recipient_new_balance = recipient_balance.saturating_add(qr_code.icp_amount);
     
     ic_cdk::println!("💰 Payment amounts: payment={}, fee={}, total_deduction={}", 
-        qr_code.icp_amount, fee_amount, total_deduction);
-    ic_cdk::println!("💰 Balance transitions: payer {} -> {}, recipient {} -> {}", 
-        payer_balance, payer_new_balance, recipient_balance, recipient_new_balance);
+        payment_icp, fee_amount, total_deduction);
+    ic_cdk::println!("💰 Balance transition: payer {} -> {}", payer_balance, payer_new_balance);
     
 
     let payment_sent_log = BalanceChangeLog {
         id: format!("BAL_PAYMENT_{}_{}", processing_tx.id, base_time),
-        user_id: caller,
+        user_id: payer,
         change_type: BalanceChangeType::PaymentSent,
-        amount: qr_code.icp_amount,
+        amount: payment_icp,
         previous_balance: payer_balance,
-        new_balance: payer_balance.saturating_sub(qr_code.icp_amount),
+        new_balance: payer_balance.saturating_sub(payment_icp),
         timestamp: base_time,
         reference_id: processing_tx.id.clone(),
-        description: format!("Payment sent: {} {}", qr_code.fiat_amount, qr_code.fiat_currency),
+        description: format!("Payment sent: {} {}", payment_fiat, fiat_currency),
     };
     
     BALANCE_CHANGE_LOGS.with(|logs| {
@@ -1453,10 +4118,10 @@ async fn process_payment(qr_id: String, transaction_hash: Option<String>) -> Res
 
     let fee_deducted_log = BalanceChangeLog {
         id: format!("BAL_FEE_{}_{}", processing_tx.id, base_time + 1),
-        user_id: caller,
+        user_id: payer,
         change_type: BalanceChangeType::FeeDeducted,
         amount: fee_amount,
-        previous_balance: payer_balance.saturating_sub(qr_code.icp_amount),
+        previous_balance: payer_balance.saturating_sub(payment_icp),
         new_balance: payer_new_balance,
         timestamp: base_time + 1,
         reference_id: processing_tx.id.clone(),
@@ -1466,21 +4131,73 @@ async fn process_payment(qr_id: String, transaction_hash: Option<String>) -> Res
     BALANCE_CHANGE_LOGS.with(|logs| {
         logs.borrow_mut().insert(fee_deducted_log.id.clone(), fee_deducted_log.clone());
     });
-    
-    ic_cdk::println!("📝 Created FeeDeducted log: {} (amount={}, prev={}, new={})", 
-        fee_deducted_log.id, fee_deducted_log.amount, fee_deducted_log.previous_balance, fee_deducted_log.new_balance);
-    
+    
+    ic_cdk::println!("📝 Created FeeDeducted log: {} (amount={}, prev={}, new={})", 
+        fee_deducted_log.id, fee_deducted_log.amount, fee_deducted_log.previous_balance, fee_deducted_log.new_balance);
+
+    Ok(processing_tx)
+}
+
+// Held funds sit in this system account until they are settled
+fn escrow_account() -> Principal {
+    ic_cdk::id()
+}
+
+fn post_escrow_account_log(change_type: BalanceChangeType, amount: u64, reference_id: &str, now: u64) {
+    let account = escrow_account();
+    let previous_balance = get_current_balance(account);
+    let new_balance = match change_type {
+        BalanceChangeType::PaymentReceived => previous_balance.saturating_add(amount),
+        _ => previous_balance.saturating_sub(amount),
+    };
+    let log = BalanceChangeLog {
+        id: format!("BAL_ESCROW_{:?}_{}_{}", change_type, reference_id, now),
+        user_id: account,
+        change_type,
+        amount,
+        previous_balance,
+        new_balance,
+        timestamp: now,
+        reference_id: reference_id.to_string(),
+        description: "Escrow account".to_string(),
+    };
+    BALANCE_CHANGE_LOGS.with(|logs| {
+        logs.borrow_mut().insert(log.id.clone(), log);
+    });
+}
+
+// Debits the payer and parks the amount in the escrow account. The returned
+// PROCESSING transaction is later completed, refunded or split.
+fn hold_balance_payment(
+    payer: Principal,
+    payee: Principal,
+    payment_icp: u64,
+    payment_fiat: Money,
+    fiat_currency: &str,
+    reference: PaymentReference,
+) -> Result<Transaction, String> {
+    let processing_tx = debit_payer(payer, payee, payment_icp, payment_fiat, fiat_currency, reference)?;
+    post_escrow_account_log(BalanceChangeType::PaymentReceived, processing_tx.amount, &processing_tx.id, processing_tx.timestamp);
+    Ok(processing_tx)
+}
+
+fn held_payment_base_id(processing_tx: &Transaction) -> &str {
+    processing_tx.id.split("_PROCESSING_").next().unwrap_or(&processing_tx.id)
+}
 
+// Credits the payee and records the payment as completed
+fn credit_payee(processing_tx: &Transaction, now: u64) -> Transaction {
+    let recipient_balance = get_current_balance(processing_tx.to);
     let payment_received_log = BalanceChangeLog {
-        id: format!("BAL_RECEIVED_{}_{}", processing_tx.id, base_time + 2),
-        user_id: qr_code.user_id,
+        id: format!("BAL_RECEIVED_{}_{}", processing_tx.id, now),
+        user_id: processing_tx.to,
         change_type: BalanceChangeType::PaymentReceived,
-        amount: qr_code.icp_amount,
+        amount: processing_tx.amount,
         previous_balance: recipient_balance,
-        new_balance: recipient_new_balance,
-        timestamp: base_time + 2,
+        new_balance: recipient_balance.saturating_add(processing_tx.amount),
+        timestamp: now,
         reference_id: processing_tx.id.clone(),
-        description: format!("Payment received: {} {}", qr_code.fiat_amount, qr_code.fiat_currency),
+        description: format!("Payment received: {} {}", processing_tx.fiat_amount, processing_tx.fiat_currency),
     };
     
     BALANCE_CHANGE_LOGS.with(|logs| {
@@ -1492,40 +4209,297 @@ async fn process_payment(qr_id: String, transaction_hash: Option<String>) -> Res
 
 
     let completed_tx = Transaction {
-        id: format!("{}_COMPLETED_{}", base_tx_id, current_time + 2),
-        from: caller,
-        to: qr_code.user_id,
-        amount: qr_code.icp_amount,
-        fiat_currency: qr_code.fiat_currency.clone(),
-        fiat_amount: qr_code.fiat_amount,
-        icp_amount: qr_code.icp_amount,
-        timestamp: current_time + 2,
+        id: format!("{}_COMPLETED_{}", held_payment_base_id(processing_tx), now),
+        timestamp: now,
         status: TransactionStatus::Completed,
-        qr_id: qr_code.id.clone(),
-        transaction_hash: transaction_hash.clone(),
-        fee: calculate_transaction_fee(qr_code.icp_amount),
+        ..processing_tx.clone()
     };
 
     TRANSACTIONS.with(|transactions| {
         transactions.borrow_mut().insert(completed_tx.id.clone(), completed_tx.clone());
     });
 
+    completed_tx
+}
 
-    create_qr_usage_log(
-        qr_id.clone(),
-        qr_code.user_id,
-        caller,
-        completed_tx.id.clone(),
-        QRUsageType::PaymentCompleted,
+// Releases a held payment to the payee
+fn complete_held_payment(processing_tx: &Transaction, now: u64) -> Transaction {
+    post_escrow_account_log(BalanceChangeType::PaymentSent, processing_tx.amount, &processing_tx.id, now);
+    credit_payee(processing_tx, now)
+}
+
+fn post_refund_log(processing_tx: &Transaction, refund: u64, reason: &str, now: u64) {
+    let payer_balance = get_current_balance(processing_tx.from);
+    let refund_log = BalanceChangeLog {
+        id: format!("BAL_REFUND_{}_{}", processing_tx.id, now),
+        user_id: processing_tx.from,
+        change_type: BalanceChangeType::Refund,
+        amount: refund,
+        previous_balance: payer_balance,
+        new_balance: payer_balance.saturating_add(refund),
+        timestamp: now,
+        reference_id: processing_tx.id.clone(),
+        description: format!("Refund: {}", reason),
+    };
+
+    BALANCE_CHANGE_LOGS.with(|logs| {
+        logs.borrow_mut().insert(refund_log.id.clone(), refund_log);
+    });
+}
+
+// Gives a held payment back to the payer, fee included, and closes it with
+// `status`
+fn refund_held_payment(processing_tx: &Transaction, status: TransactionStatus, reason: &str, now: u64) -> Transaction {
+    post_escrow_account_log(BalanceChangeType::PaymentSent, processing_tx.amount, &processing_tx.id, now);
+    post_refund_log(processing_tx, processing_tx.amount.saturating_add(processing_tx.fee), reason, now);
+
+    let refunded_tx = Transaction {
+        id: format!("{}_REFUNDED_{}", held_payment_base_id(processing_tx), now),
+        timestamp: now,
+        status,
+        ..processing_tx.clone()
+    };
+
+    TRANSACTIONS.with(|transactions| {
+        transactions.borrow_mut().insert(refunded_tx.id.clone(), refunded_tx.clone());
+    });
+
+    refunded_tx
+}
+
+// Pays `payer_part` of a held payment back to the payer (fee kept) and the
+// rest to the payee
+fn split_held_payment(processing_tx: &Transaction, payer_part: u64, reason: &str, now: u64) -> Transaction {
+    let merchant_part = processing_tx.amount.saturating_sub(payer_part);
+    post_escrow_account_log(BalanceChangeType::PaymentSent, processing_tx.amount, &processing_tx.id, now);
+    post_refund_log(processing_tx, payer_part, reason, now);
+
+    // Fiat value follows the merchant's share
+    let merchant_fiat = Money::new(
+        (processing_tx.fiat_amount.minor_units as u128 * merchant_part as u128
+            / processing_tx.amount.max(1) as u128) as u64,
+        processing_tx.fiat_amount.scale,
     );
+    let merchant_tx = Transaction {
+        amount: merchant_part,
+        icp_amount: merchant_part,
+        fiat_amount: merchant_fiat,
+        ..processing_tx.clone()
+    };
+    credit_payee(&merchant_tx, now)
+}
+
+#[update]
+#[candid_method(update)]
+async fn process_payment(
+    qr_id: String,
+    transaction_hash: Option<String>,
+    payer_amount: Option<String>,
+    escrow: Option<EscrowTerms>,
+) -> Result<Transaction, String> {
+    let caller = caller();
+    if caller == Principal::anonymous() {
+        return Err("Anonymous users cannot make payments".to_string());
+    }
+
+    pay_qr_code(caller, qr_id, transaction_hash, payer_amount, escrow).await
+}
+
+// Pays a QR code from the payer's balance; also settles the invoice or
+// payment request behind it. With escrow terms the funds are held until
+// released instead of reaching the merchant right away.
+async fn pay_qr_code(
+    caller: Principal,
+    qr_id: String,
+    transaction_hash: Option<String>,
+    payer_amount: Option<String>,
+    escrow: Option<EscrowTerms>,
+) -> Result<Transaction, String> {
+
+    // Check if payer is registered
+    let payer_exists = USERS.with(|users| users.borrow().contains_key(&caller));
+    if !payer_exists {
+        return Err("Payer not registered".to_string());
+    }
+
+    // Get and validate QR code
+    let qr_code = QR_CODES.with(|qr_codes| {
+        qr_codes.borrow().get(&qr_id)
+    }).ok_or("QR code not found")?;
+
+    // Validate QR code; uses are counted from the immutable logs
+    is_qr_code_valid(&qr_code, caller, count_qr_payments(&qr_id))?;
+
+    // Check if payer is different from payee
+    if caller == qr_code.user_id {
+        return Err("Cannot pay to yourself".to_string());
+    }
+
+    // Check if recipient is registered
+    let recipient_exists = USERS.with(|users| users.borrow().contains_key(&qr_code.user_id));
+    if !recipient_exists {
+        return Err("Recipient not registered".to_string());
+    }
+
+    let payment_request = PAYMENT_REQUESTS.with(|requests| requests.borrow().get(&qr_id));
+    let release_seconds = escrow.as_ref().map(resolve_release_seconds).transpose()?;
+    if release_seconds.is_some() && qr_code.kind == QRKind::Split {
+        return Err("Split bills cannot be paid into escrow".to_string());
+    }
+
+    // Static codes take the amount from the payer; dynamic codes carry their own
+    let (payment_fiat, payment_icp) = match (&qr_code.kind, payer_amount) {
+        (QRKind::Dynamic, None) => (qr_code.fiat_amount, qr_code.icp_amount),
+        (QRKind::Dynamic, Some(_)) => return Err("Amount is fixed for this QR code".to_string()),
+        (QRKind::Static, None) => return Err("Amount is required for this QR code".to_string()),
+        (QRKind::Split, payer_amount) => {
+            return contribute_to_split(caller, &qr_code, payer_amount, transaction_hash).await;
+        }
+        (QRKind::Static, Some(amount)) => {
+            let amount = Money::parse(&amount, &qr_code.fiat_currency)?;
+            let icp_amount = quote_static_payment(&qr_code, &amount).await?;
+            (amount, icp_amount)
+        }
+    };
+
+    // Re-check on a fresh read: other payments may have used the code while
+    // the rate was fetched
+    let qr_code = QR_CODES.with(|qr_codes| qr_codes.borrow().get(&qr_id))
+        .ok_or("QR code not found")?;
+    is_qr_code_valid(&qr_code, caller, count_qr_payments(&qr_id))?;
+
+    let reference = PaymentReference {
+        kind: if payment_request.is_some() { TransactionKind::PaymentRequest } else { TransactionKind::QrPayment },
+        reference_id: qr_code.id.clone(),
+        memo: payment_request.and_then(|request| request.note),
+        transaction_hash,
+    };
+    let context = risk_context(RiskAction::Payment, caller, Some(qr_code.user_id), payment_icp);
+    let risk = screen_risk(caller, Some(qr_code.user_id), &context)?;
 
-    ic_cdk::println!("✅ Created COMPLETED transaction: {}", completed_tx.id);
+    // Payments flagged for review are held like escrow until an admin decides
+    let completed_tx = if release_seconds.is_none() && risk.decision != RiskDecision::Review {
+        execute_balance_payment(caller, qr_code.user_id, payment_icp, payment_fiat, &qr_code.fiat_currency, reference)?
+    } else {
+        let held_tx = hold_balance_payment(caller, qr_code.user_id, payment_icp, payment_fiat, &qr_code.fiat_currency, reference)?;
+        if let Some(release_seconds) = release_seconds {
+            open_escrow(&held_tx, release_seconds);
+        }
+        held_tx
+    };
+    let held = (risk.decision == RiskDecision::Review).then(|| completed_tx.id.clone());
+    record_risk_assessment(caller, Some(qr_code.user_id), &context, risk, held);
+
+    // A held payment keeps its use of the code but only counts as paid once
+    // it is released
+    if completed_tx.status == TransactionStatus::Completed {
+        complete_qr_payment(&qr_id, caller, &completed_tx);
+    } else {
+        create_qr_usage_log(qr_id.clone(), qr_code.user_id, caller, completed_tx.id.clone(), QRUsageType::PaymentHeld);
+    }
+
+    ic_cdk::println!("✅ Created {:?} transaction: {}", completed_tx.status, completed_tx.id);
     ic_cdk::println!("Payment processed: {} -> {}", caller.to_text(), qr_code.user_id.to_text());
 
 
     Ok(completed_tx)
 }
 
+// Logs a completed payment through a QR code, closes the code once its last
+// allowed payment is in and settles the invoice or payment request behind it
+fn complete_qr_payment(qr_id: &str, payer: Principal, tx: &Transaction) {
+    let Some(mut qr_code) = QR_CODES.with(|qr_codes| qr_codes.borrow().get(&qr_id.to_string())) else { return };
+    create_qr_usage_log(qr_id.to_string(), qr_code.user_id, payer, tx.id.clone(), QRUsageType::PaymentCompleted);
+
+    let status = status_after_payment(&qr_code, count_qr_payments(qr_id));
+    if status != qr_code.status {
+        qr_code.status = status;
+        QR_CODES.with(|qr_codes| {
+            qr_codes.borrow_mut().insert(qr_id.to_string(), qr_code.clone());
+        });
+    }
+    apply_invoice_payment(&qr_code, &tx.fiat_amount);
+    apply_payment_request_payment(&qr_code, tx);
+}
+
+fn is_held_qr_payment(held_tx: &Transaction) -> bool {
+    matches!(held_tx.kind, TransactionKind::QrPayment | TransactionKind::PaymentRequest) && !held_tx.qr_id.is_empty()
+}
+
+// The held payment reached the merchant, in full or in part
+fn release_qr_hold(held_tx: &Transaction, completed_tx: &Transaction) {
+    if is_held_qr_payment(held_tx) {
+        complete_qr_payment(&held_tx.qr_id, held_tx.from, completed_tx);
+    }
+}
+
+// The held payment went back to the payer; its use of the code is freed
+fn refund_qr_hold(held_tx: &Transaction, refunded_tx: &Transaction) {
+    if is_held_qr_payment(held_tx) {
+        create_qr_usage_log(held_tx.qr_id.clone(), held_tx.to, held_tx.from, refunded_tx.id.clone(), QRUsageType::PaymentRefunded);
+    }
+}
+
+// Direct transfer between users, no QR code involved. Without a currency
+// hint (or with "ICP") the amount is in ICP; otherwise it is a fiat amount
+// converted at the live rate.
+#[update]
+#[candid_method(update)]
+async fn send_to_user(
+    recipient: String,
+    amount: String,
+    currency_hint: Option<String>,
+    memo: Option<String>,
+) -> Result<Transaction, String> {
+    let caller = caller();
+    if caller == Principal::anonymous() {
+        return Err("Anonymous users cannot make payments".to_string());
+    }
+    if !USERS.with(|users| users.borrow().contains_key(&caller)) {
+        return Err("Payer not registered".to_string());
+    }
+
+    let recipient = USERS.with(|users| {
+        resolve_recipient(&recipient, users.borrow().iter().map(|(_, user)| user))
+    })?;
+    if recipient == caller {
+        return Err("Cannot pay to yourself".to_string());
+    }
+    let memo = normalize_memo(memo)?;
+
+    let (payment_fiat, payment_icp, currency) = match currency_hint
+        .map(|c| c.trim().to_uppercase())
+        .filter(|c| !c.is_empty() && c != "ICP")
+    {
+        None => {
+            let icp_amount = parse_icp_amount(&amount)?;
+            (Money::new(icp_amount, 8), icp_amount, "ICP".to_string())
+        }
+        Some(currency) => {
+            let amount = Money::parse(&amount, &currency)?;
+            let icp_amount = quote_fiat_payment(&currency, &amount).await?;
+            (amount, icp_amount, currency)
+        }
+    };
+
+    let completed_tx = execute_balance_payment(
+        caller,
+        recipient,
+        payment_icp,
+        payment_fiat,
+        &currency,
+        PaymentReference {
+            kind: TransactionKind::PeerTransfer,
+            reference_id: String::new(),
+            memo,
+            transaction_hash: None,
+        },
+    )?;
+
+    ic_cdk::println!("Transfer sent: {} -> {} ({})", caller.to_text(), recipient.to_text(), completed_tx.id);
+    Ok(completed_tx)
+}
+
 #[query]
 #[candid_method(query)]
 fn get_all_network_transactions() -> Vec<NetworkTransaction> {
@@ -1580,7 +4554,7 @@ fn get_all_network_transactions() -> Vec<NetworkTransaction> {
     });
     
     // Sort by timestamp (newest first)
-    network_transactions.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+    network_transactions.sort_by_key(|tx| std::cmp::Reverse(tx.timestamp));
     
     ic_cdk::println!("📊 Returning {} network transactions (payments + topups)", network_transactions.len());
     network_transactions
@@ -1616,7 +4590,7 @@ fn get_user_transactions() -> Vec<Transaction> {
             .collect();
         
         // Sort by timestamp (newest first)
-        user_transactions.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+        user_transactions.sort_by_key(|tx| std::cmp::Reverse(tx.timestamp));
         
         ic_cdk::println!("📊 Returning {} user transactions for {}", user_transactions.len(), caller.to_text());
         user_transactions
@@ -1627,16 +4601,17 @@ fn get_user_transactions() -> Vec<Transaction> {
 #[candid_method(query)]
 fn get_user_transaction_summaries() -> Vec<TransactionSummary> {
     let caller = caller();
+    let language = get_user_language(caller);
     TRANSACTIONS.with(|transactions| {
         let mut summaries: Vec<TransactionSummary> = transactions
             .borrow()
             .iter()
             .filter(|(_, tx)| tx.from == caller || tx.to == caller)
-            .map(|(_, tx)| create_transaction_summary(&tx, caller))
+            .map(|(_, tx)| create_transaction_summary(&tx, caller, &language))
             .collect();
         
         // Sort by timestamp (newest first)
-        summaries.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+        summaries.sort_by_key(|summary| std::cmp::Reverse(summary.timestamp));
         
         ic_cdk::println!("📊 Returning {} transaction summaries for {}", summaries.len(), caller.to_text());
         summaries
@@ -1732,9 +4707,9 @@ fn get_user_stats() -> Option<UserStats> {
 #[query]
 #[candid_method(query)]
 fn get_system_stats() -> SystemStats {
-    let total_users = USERS.with(|users| users.borrow().len() as u64);
-    let total_transactions = TRANSACTIONS.with(|tx| tx.borrow().len() as u64);
-    let total_qr_codes = QR_CODES.with(|qr| qr.borrow().len() as u64);
+    let total_users = USERS.with(|users| users.borrow().len());
+    let total_transactions = TRANSACTIONS.with(|tx| tx.borrow().len());
+    let total_qr_codes = QR_CODES.with(|qr| qr.borrow().len());
     
     // Enhanced rate cache info
     let (cached_rates, valid_rates, expired_rates) = EXCHANGE_RATES.with(|rates| {
@@ -1826,6 +4801,332 @@ async fn cleanup_expired_rates() -> u64 {
     cleaned_count
 }
 
+// ===================
+// ICRC WALLET PAYMENTS
+// ===================
+
+const LEDGER_WATCHER_KEY: u8 = 0;
+const LEDGER_POLL_INTERVAL_SECONDS: u64 = 10;
+const LEDGER_BLOCKS_PER_POLL: u64 = 500;
+const MAX_SKIPPED_BLOCK_RANGES: usize = 32;
+
+// Standard payment request for a QR, payable from any ICRC wallet
+fn qr_payment_uri(qr_code: &QRCode) -> Option<String> {
+    let ledger = current_config().ledger_canister_id?;
+    let to = icrc::merchant_account(ic_cdk::id(), qr_code.user_id);
+    // Split contributions are held in balances, which a wallet transfer can't do
+    if qr_code.kind == QRKind::Split {
+        return None;
+    }
+    let amount = (qr_code.kind == QRKind::Dynamic).then_some(qr_code.icp_amount);
+    Some(icrc::build_payment_uri(ledger, &to, amount, qr_code.id.as_bytes()))
+}
+
+#[query]
+#[candid_method(query)]
+fn get_qr_payment_uri(qr_id: String) -> Result<String, String> {
+    let qr_code = QR_CODES.with(|qr_codes| qr_codes.borrow().get(&qr_id))
+        .ok_or("QR code not found")?;
+    qr_payment_uri(&qr_code).ok_or("Wallet payments are not enabled".to_string())
+}
+
+// Image of the payment URI rather than the pay page link
+#[query]
+#[candid_method(query)]
+fn render_qr_payment_uri(qr_id: String, options: QRRenderOptions) -> Result<QRImage, String> {
+    render_qr(&get_qr_payment_uri(qr_id)?, &options)
+}
+
+// Where wallet payments to the caller's QR codes land
+#[query]
+#[candid_method(query)]
+fn get_my_payment_account() -> Account {
+    icrc::merchant_account(ic_cdk::id(), caller())
+}
+
+#[query]
+#[candid_method(query)]
+fn get_ledger_watcher_status() -> Option<LedgerWatcherState> {
+    LEDGER_WATCHER.with(|state| state.borrow().get(&LEDGER_WATCHER_KEY))
+}
+
+#[update]
+#[candid_method(update)]
+async fn poll_ledger_now() -> Result<u64, String> {
+    ensure_admin()?;
+    poll_ledger().await
+}
+
+fn start_ledger_watcher() {
+    ic_cdk_timers::set_timer_interval(std::time::Duration::from_secs(LEDGER_POLL_INTERVAL_SECONDS), || {
+        ic_cdk::spawn(async {
+            if let Err(e) = poll_ledger().await {
+                ic_cdk::println!("⚠️ Ledger poll failed: {}", e);
+            }
+        })
+    });
+}
+
+// Reads new ledger blocks and settles QR payments; returns how many matched
+async fn poll_ledger() -> Result<u64, String> {
+    let Some(ledger) = current_config().ledger_canister_id else {
+        return Ok(0);
+    };
+    let Some(polling) = InFlight::claim(LEDGER_POLL_TASK) else {
+        return Ok(0);
+    };
+
+    let result = poll_ledger_blocks(ledger).await;
+    drop(polling);
+
+    LEDGER_WATCHER.with(|store| {
+        let mut store = store.borrow_mut();
+        if let Some(mut state) = store.get(&LEDGER_WATCHER_KEY) {
+            state.last_polled_at = time();
+            state.last_error = result.as_ref().err().cloned();
+            store.insert(LEDGER_WATCHER_KEY, state);
+        }
+    });
+    result
+}
+
+async fn poll_ledger_blocks(ledger: Principal) -> Result<u64, String> {
+    // A new ledger starts from its current tip; history is not replayed
+    let state = LEDGER_WATCHER.with(|store| store.borrow().get(&LEDGER_WATCHER_KEY))
+        .filter(|state| state.ledger == ledger);
+    let start = state.as_ref().map(|state| state.next_block).unwrap_or(0);
+    let length = if state.is_some() { LEDGER_BLOCKS_PER_POLL } else { 0 };
+
+    let args = vec![GetBlocksArgs { start: start.into(), length: length.into() }];
+    let (result,): (GetBlocksResult,) = ic_cdk::call(ledger, "icrc3_get_blocks", (args,))
+        .await
+        .map_err(|(code, msg)| format!("icrc3_get_blocks failed: {:?} {}", code, msg))?;
+    let log_length = icrc::nat_to_u64(&result.log_length).ok_or("Invalid ledger log length")?;
+
+    let Some(mut state) = state else {
+        LEDGER_WATCHER.with(|store| {
+            store.borrow_mut().insert(LEDGER_WATCHER_KEY, LedgerWatcherState {
+                ledger,
+                next_block: log_length,
+                last_polled_at: time(),
+                last_error: None,
+                matched_payments: 0,
+                skipped_ranges: vec![],
+            });
+        });
+        ic_cdk::println!("👀 Watching ledger {} from block {}", ledger.to_text(), log_length);
+        return Ok(0);
+    };
+
+    let mut blocks = result.blocks;
+    for archived in &result.archived_blocks {
+        match fetch_archived_blocks(archived).await {
+            Ok(mut archived_blocks) => blocks.append(&mut archived_blocks),
+            // The range is recorded as skipped below
+            Err(e) => ic_cdk::println!("⚠️ Archived ledger blocks unavailable: {}", e),
+        }
+    }
+    let mut blocks: Vec<(u64, icrc::Icrc3Value)> = blocks.into_iter()
+        .filter_map(|block| Some((icrc::nat_to_u64(&block.id)?, block.block)))
+        .filter(|(index, _)| *index >= start)
+        .collect();
+    blocks.sort_by_key(|(index, _)| *index);
+    blocks.dedup_by_key(|(index, _)| *index);
+    let received: Vec<u64> = blocks.iter().map(|(index, _)| *index).collect();
+    let next_block = icrc::poll_end(start, LEDGER_BLOCKS_PER_POLL, &received, &result.archived_blocks, log_length);
+
+    let mut matched = 0;
+    for (index, block) in &blocks {
+        let Some(transfer) = icrc::transfer_from_block(*index, block) else { continue };
+        if icrc::match_qr_transfer(&transfer, ic_cdk::id()).is_none() {
+            continue;
+        }
+        match settle_ledger_transfer(&transfer, ledger) {
+            Ok(tx) => {
+                matched += 1;
+                ic_cdk::println!("✅ Ledger block {} settled QR {} ({})", index, tx.qr_id, tx.id);
+            }
+            Err(e) => {
+                ic_cdk::println!("⚠️ Ledger block {} not settled: {}", index, e);
+                record_unmatched_transfer(&transfer, ledger, e);
+            }
+        }
+    }
+
+    for range in icrc::missing_block_ranges(start, next_block, &received) {
+        ic_cdk::println!("⚠️ Skipping unreadable ledger blocks {}..{}", range.start, range.end);
+        state.skipped_ranges.push(range);
+    }
+    let excess = state.skipped_ranges.len().saturating_sub(MAX_SKIPPED_BLOCK_RANGES);
+    state.skipped_ranges.drain(..excess);
+
+    state.next_block = next_block;
+    state.matched_payments += matched;
+    LEDGER_WATCHER.with(|store| {
+        store.borrow_mut().insert(LEDGER_WATCHER_KEY, state);
+    });
+
+    Ok(matched)
+}
+
+async fn fetch_archived_blocks(archived: &ArchivedBlocks) -> Result<Vec<BlockWithId>, String> {
+    let callback = &archived.callback.0;
+    let (result,): (GetBlocksResult,) = ic_cdk::call(callback.principal, &callback.method, (archived.args.clone(),))
+        .await
+        .map_err(|(code, msg)| format!("{} {} failed: {:?} {}", callback.principal.to_text(), callback.method, code, msg))?;
+    Ok(result.blocks)
+}
+
+// Block indices are only unique per ledger
+fn ledger_transfer_id(ledger: Principal, block_index: u64) -> String {
+    format!("TX_ICRC_{}_{}", ledger.to_text().split('-').next().unwrap_or_default(), block_index)
+}
+
+// A QR transfer that could not settle: the payer gets the amount on their
+// balance and admins can see why
+fn record_unmatched_transfer(transfer: &LedgerTransfer, ledger: Principal, reason: String) {
+    let Some((merchant, qr_id)) = icrc::match_qr_transfer(transfer, ic_cdk::id()) else { return };
+    let now = time();
+    let unmatched = UnmatchedLedgerTransfer {
+        id: ledger_transfer_id(ledger, transfer.block_index),
+        ledger,
+        block_index: transfer.block_index,
+        payer: transfer.from.owner,
+        merchant,
+        qr_id,
+        amount: transfer.amount,
+        reason,
+        recorded_at: now,
+    };
+
+    let previous_balance = get_current_balance(unmatched.payer);
+    let refund_log = BalanceChangeLog {
+        id: format!("BAL_REFUND_{}_{}", unmatched.id, now),
+        user_id: unmatched.payer,
+        change_type: BalanceChangeType::Refund,
+        amount: unmatched.amount,
+        previous_balance,
+        new_balance: previous_balance.saturating_add(unmatched.amount),
+        timestamp: now,
+        reference_id: unmatched.id.clone(),
+        description: format!("Wallet payment for QR {} not applied: {}", unmatched.qr_id, unmatched.reason),
+    };
+    BALANCE_CHANGE_LOGS.with(|logs| {
+        logs.borrow_mut().insert(refund_log.id.clone(), refund_log);
+    });
+    UNMATCHED_LEDGER_TRANSFERS.with(|transfers| {
+        transfers.borrow_mut().insert(unmatched.id.clone(), unmatched);
+    });
+}
+
+// Newest first
+#[query]
+#[candid_method(query)]
+fn get_unmatched_ledger_transfers() -> Result<Vec<UnmatchedLedgerTransfer>, String> {
+    ensure_admin()?;
+    let mut transfers: Vec<UnmatchedLedgerTransfer> = UNMATCHED_LEDGER_TRANSFERS.with(|transfers| {
+        transfers.borrow().iter().map(|(_, transfer)| transfer).collect()
+    });
+    transfers.sort_by_key(|transfer| std::cmp::Reverse(transfer.recorded_at));
+    Ok(transfers)
+}
+
+// Stores a completed ledger payment and credits the funds, which now sit
+// in the payee's subaccount, to their internal balance
+fn record_wallet_payment(tx: &Transaction) {
+    TRANSACTIONS.with(|transactions| {
+        transactions.borrow_mut().insert(tx.id.clone(), tx.clone());
+    });
+
+    let previous_balance = get_current_balance(tx.to);
+    let received_log = BalanceChangeLog {
+        id: format!("BAL_RECEIVED_{}_{}", tx.id, tx.timestamp),
+        user_id: tx.to,
+        change_type: BalanceChangeType::PaymentReceived,
+        amount: tx.amount,
+        previous_balance,
+        new_balance: previous_balance.saturating_add(tx.amount),
+        timestamp: tx.timestamp,
+        reference_id: tx.id.clone(),
+        description: format!("Wallet payment received: {} {}", tx.fiat_amount, tx.fiat_currency),
+    };
+    BALANCE_CHANGE_LOGS.with(|logs| {
+        logs.borrow_mut().insert(received_log.id.clone(), received_log);
+    });
+}
+
+// Records a wallet transfer carrying a QR memo as a completed payment
+fn settle_ledger_transfer(transfer: &LedgerTransfer, ledger: Principal) -> Result<Transaction, String> {
+    let (merchant, qr_id) = icrc::match_qr_transfer(transfer, ic_cdk::id())
+        .ok_or("Not a QR payment")?;
+    let mut qr_code = QR_CODES.with(|qr_codes| qr_codes.borrow().get(&qr_id))
+        .ok_or(format!("QR code {} not found", qr_id))?;
+    if qr_code.user_id != merchant {
+        return Err("Paid into another merchant's account".to_string());
+    }
+
+    let payer = transfer.from.owner;
+    is_qr_code_valid(&qr_code, payer, count_qr_payments(&qr_id))?;
+
+    let fiat_amount = match qr_code.kind {
+        QRKind::Dynamic => {
+            if transfer.amount < qr_code.icp_amount {
+                return Err(format!("Underpaid: {} of {} e8s", transfer.amount, qr_code.icp_amount));
+            }
+            qr_code.fiat_amount
+        }
+        // The payer picked the amount; value it at the last known rate
+        QRKind::Static => {
+            validate_transaction_amount(transfer.amount)?;
+            lookup_known_rate(&qr_code.fiat_currency)
+                .and_then(|rate| {
+                    money::e8s_to_fiat(
+                        transfer.amount,
+                        rate.rate,
+                        money::currency_minor_units(&qr_code.fiat_currency),
+                        money::RoundingMode::Down,
+                    ).ok()
+                })
+                .unwrap_or_else(|| Money::zero(&qr_code.fiat_currency))
+        }
+        QRKind::Split => return Err("Split bills can only be paid from a balance".to_string()),
+    };
+
+    let now = time();
+    let tx = Transaction {
+        id: ledger_transfer_id(ledger, transfer.block_index),
+        from: payer,
+        to: merchant,
+        amount: transfer.amount,
+        fiat_currency: qr_code.fiat_currency.clone(),
+        fiat_amount,
+        icp_amount: transfer.amount,
+        timestamp: now,
+        status: TransactionStatus::Completed,
+        qr_id: qr_id.clone(),
+        transaction_hash: Some(format!("{}:{}", ledger.to_text(), transfer.block_index)),
+        // The ledger fee was paid by the sender
+        fee: 0,
+        kind: TransactionKind::WalletPayment,
+        memo: None,
+    };
+
+    record_wallet_payment(&tx);
+
+    create_qr_usage_log(qr_id.clone(), merchant, payer, tx.id.clone(), QRUsageType::PaymentCompleted);
+
+    let status = status_after_payment(&qr_code, count_qr_payments(&qr_id));
+    if status != qr_code.status {
+        qr_code.status = status;
+        QR_CODES.with(|qr_codes| {
+            qr_codes.borrow_mut().insert(qr_id, qr_code.clone());
+        });
+    }
+    apply_invoice_payment(&qr_code, &fiat_amount);
+
+    Ok(tx)
+}
+
 // ===================
 // HTTP TRANSFORM FUNCTION
 // ===================
@@ -1840,12 +5141,69 @@ fn transform_response(raw: TransformArgs) -> HttpResponse {
     }
 }
 
+// ===================
+// HTTP GATEWAY
+// ===================
+
+#[query]
+#[candid_method(query)]
+fn http_request(request: HttpRequest) -> http::HttpResponse {
+    if request.method != "GET" && request.method != "HEAD" {
+        return http::error_response(405, "Method not allowed");
+    }
+
+    let language = DEFAULT_LANGUAGE;
+    match http::parse_route(&request.url) {
+        Route::Pay(qr_id) => match QR_CODES.with(|qr_codes| qr_codes.borrow().get(&qr_id)) {
+            Some(qr_code) => {
+                let info = get_qr_display_info(&qr_code, language, None, count_qr_payments(&qr_id));
+                http::html_response(http::pay_page(&info, &format!("/qr/{}.svg", qr_id)))
+            }
+            None => http::error_response(404, "QR code not found"),
+        },
+        Route::Qris(topup_id) => match TOPUP_TRANSACTIONS.with(|topups| topups.borrow().get(&topup_id)) {
+            Some(topup) => match http::qris_page(&topup, language) {
+                Ok(html) => http::html_response(html),
+                Err(e) => http::error_response(404, &e),
+            },
+            None => http::error_response(404, "Top-up not found"),
+        },
+        Route::QrImage(qr_id, format) => {
+            if QR_CODES.with(|qr_codes| !qr_codes.borrow().contains_key(&qr_id)) {
+                return http::error_response(404, "QR code not found");
+            }
+
+            let options = QRRenderOptions {
+                format,
+                error_correction: None,
+                size_px: None,
+                margin_modules: None,
+                logo: None,
+            };
+            match render_qr(&generate_qr_data_url(&qr_id, &public_base_url()), &options) {
+                Ok(image) => http::image_response(image),
+                Err(e) => http::error_response(500, &e),
+            }
+        }
+        Route::Receipt(tx_id) => match TRANSACTIONS.with(|transactions| transactions.borrow().get(&tx_id)) {
+            Some(tx) => http::html_response(http::receipt_page(&tx, language)),
+            None => http::error_response(404, "Transaction not found"),
+        },
+        Route::NotFound => http::error_response(404, "Page not found"),
+    }
+}
+
 // ===================
 // CANISTER LIFECYCLE
 // ===================
 
 #[init]
-fn init() {
+fn init(args: Option<CanisterArgs>) {
+    apply_canister_args(args);
+    seed_currency_registry();
+    start_ledger_watcher();
+    start_subscription_scheduler();
+    start_sweeper();
     ic_cdk::println!("🚀 ICP Payment Gateway Canister initialized");
     ic_cdk::println!("📊 System ready for payments and QR code generation");
 }
@@ -1856,10 +5214,51 @@ fn pre_upgrade() {
 }
 
 #[post_upgrade]
-fn post_upgrade() {
+fn post_upgrade(args: Option<CanisterArgs>) {
+    apply_canister_args(args);
+    seed_currency_registry();
+    migrate_legacy_records();
+    start_ledger_watcher();
+    start_subscription_scheduler();
+    start_sweeper();
+    resume_payout_batches();
     ic_cdk::println!("✅ Canister upgrade completed");
 }
 
+// Records written by the previous release still decode (see the legacy
+// layouts in types.rs); re-inserting them stores the current layout.
+fn migrate_legacy_records() {
+    let qr_codes = QR_CODES.with(|qr_codes| {
+        let mut qr_codes = qr_codes.borrow_mut();
+        let all: Vec<QRCode> = qr_codes.iter().map(|(_, qr)| qr).collect();
+        for qr in &all {
+            qr_codes.insert(qr.id.clone(), qr.clone());
+        }
+        all.len()
+    });
+
+    let transactions = TRANSACTIONS.with(|transactions| {
+        let mut transactions = transactions.borrow_mut();
+        let all: Vec<Transaction> = transactions.iter().map(|(_, tx)| tx).collect();
+        for tx in &all {
+            transactions.insert(tx.id.clone(), tx.clone());
+        }
+        all.len()
+    });
+
+    let topups = TOPUP_TRANSACTIONS.with(|topups| {
+        let mut topups = topups.borrow_mut();
+        let all: Vec<TopUpTransaction> = topups.iter().map(|(_, topup)| topup).collect();
+        for topup in &all {
+            topups.insert(topup.id.clone(), topup.clone());
+        }
+        all.len()
+    });
+
+    ic_cdk::println!("💱 Legacy record migration: {} QR codes, {} transactions, {} top-ups re-encoded",
+        qr_codes, transactions, topups);
+}
+
 #[heartbeat]
 fn heartbeat() {
 
@@ -2069,12 +5468,12 @@ fn get_network_stats() -> NetworkStats {
                 let currency_stat = currency_map.entry(tx.fiat_currency.clone()).or_insert(CurrencyStatInfo {
                     currency: tx.fiat_currency.clone(),
                     usage_count: 0,
-                    total_fiat_volume: 0.0,
+                    total_fiat_volume: Money::zero(&tx.fiat_currency),
                     total_icp_volume: 0,
                 });
                 
                 currency_stat.usage_count += 1;
-                currency_stat.total_fiat_volume += tx.fiat_amount;
+                currency_stat.total_fiat_volume = currency_stat.total_fiat_volume.saturating_add(&tx.fiat_amount);
                 currency_stat.total_icp_volume += tx.amount;
             }
         }
@@ -2095,20 +5494,26 @@ fn get_network_stats() -> NetworkStats {
     });
     
     // Get user count
-    let total_users = USERS.with(|users| users.borrow().len() as u64);
+    let total_users = USERS.with(|users| users.borrow().len());
     
-    // Calculate active countries from currency stats
+    // Countries come from the currency registry; one currency can span many
     let active_currencies = currency_stats.len() as u64;
-    let active_countries = active_currencies; // For MVP, 1 currency = 1 country
     
-    // Create currency countries mapping
     let currency_countries: Vec<CurrencyCountryInfo> = currency_stats.iter().map(|stat| {
         CurrencyCountryInfo {
             currency: stat.currency.clone(),
             transaction_count: stat.usage_count,
+            countries: get_currency_info(&stat.currency)
+                .map(|info| info.countries)
+                .unwrap_or_default(),
         }
     }).collect();
     
+    let active_countries = currency_countries.iter()
+        .flat_map(|info| info.countries.iter())
+        .collect::<std::collections::HashSet<_>>()
+        .len() as u64;
+    
     NetworkStats {
         active_countries,
         active_currencies,
@@ -2146,7 +5551,7 @@ pub struct NetworkStats {
 pub struct CurrencyStatInfo {
     pub currency: String,
     pub usage_count: u64,
-    pub total_fiat_volume: f64,
+    pub total_fiat_volume: Money,
     pub total_icp_volume: u64,
 }
 
@@ -2154,6 +5559,7 @@ pub struct CurrencyStatInfo {
 pub struct CurrencyCountryInfo {
     pub currency: String,
     pub transaction_count: u64,
+    pub countries: Vec<String>,
 }
 
 #[derive(Clone, Debug)]
@@ -2178,7 +5584,7 @@ fn get_all_transactions() -> Vec<Transaction> {
             .collect();
         
         // Sort by timestamp (newest first)
-        all_transactions.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+        all_transactions.sort_by_key(|tx| std::cmp::Reverse(tx.timestamp));
         
         ic_cdk::println!("📊 Returning {} transactions", all_transactions.len());
         all_transactions
@@ -2204,7 +5610,7 @@ async fn create_user_session(ip_address: String, user_agent: String) -> Result<U
         return Err("Anonymous users cannot create sessions".to_string());
     }
 
-    let session_id = format!("SES_{}_{}", caller.to_text()[..8].to_string(), time());
+    let session_id = format!("SES_{}_{}", &caller.to_text()[..8], time());
     let session = UserSession {
         user_id: caller,
         session_id: session_id.clone(),
@@ -0,0 +1,253 @@
+use crate::formatting::{format_icp, DEFAULT_LANGUAGE};
+use crate::types::*;
+use std::collections::HashMap;
+
+const HOUR_NANOS: u64 = 60 * 60 * 1_000_000_000;
+const DAY_NANOS: u64 = 24 * HOUR_NANOS;
+pub const MONTH_NANOS: u64 = 30 * DAY_NANOS;
+
+const ALL_TIERS: [KycTier; 3] = [KycTier::None, KycTier::Basic, KycTier::Full];
+
+// Outgoing payments over the limit windows
+#[derive(Debug, Default, PartialEq)]
+pub struct OutgoingUsage {
+    pub daily: u64,
+    pub weekly: u64,
+    pub monthly: u64,
+    pub last_hour_count: u32,
+}
+
+// What is left of each sent payment once the refunds posted against it (same
+// reference) are taken off; fully refunded payments drop out
+pub fn net_of_refunds<'a>(sent: &'a [BalanceChangeLog], refunds: &[BalanceChangeLog]) -> Vec<(&'a BalanceChangeLog, u64)> {
+    let mut refunded: HashMap<&str, u64> = HashMap::new();
+    for refund in refunds {
+        let total = refunded.entry(refund.reference_id.as_str()).or_default();
+        *total = total.saturating_add(refund.amount);
+    }
+    sent.iter()
+        .filter_map(|log| {
+            let net = log.amount.saturating_sub(refunded.get(log.reference_id.as_str()).copied().unwrap_or(0));
+            (net > 0).then_some((log, net))
+        })
+        .collect()
+}
+
+// `sent` is (timestamp, amount, payments) per debit, where a batch payout
+// carries one payment per recipient; older entries are ignored
+pub fn outgoing_usage(sent: impl Iterator<Item = (u64, u64, u32)>, now: u64) -> OutgoingUsage {
+    let mut usage = OutgoingUsage::default();
+    for (timestamp, amount, payments) in sent {
+        let age = now.saturating_sub(timestamp);
+        if age < MONTH_NANOS {
+            usage.monthly = usage.monthly.saturating_add(amount);
+        }
+        if age < 7 * DAY_NANOS {
+            usage.weekly = usage.weekly.saturating_add(amount);
+        }
+        if age < DAY_NANOS {
+            usage.daily = usage.daily.saturating_add(amount);
+        }
+        if age < HOUR_NANOS {
+            usage.last_hour_count = usage.last_hour_count.saturating_add(payments);
+        }
+    }
+    usage
+}
+
+// Falls back to the built-in limits if the config lacks the tier
+pub fn limits_for_tier(tier_limits: &[TierLimits], tier: KycTier) -> TierLimits {
+    let find = |limits: &TierLimits| limits.tier == tier;
+    tier_limits.iter()
+        .find(|limits| find(limits))
+        .cloned()
+        .or_else(|| default_tier_limits().into_iter().find(find))
+        .expect("every tier has built-in limits")
+}
+
+pub fn validate_tier_limits(tier_limits: &[TierLimits]) -> Result<(), String> {
+    for tier in ALL_TIERS {
+        if tier_limits.iter().filter(|limits| limits.tier == tier).count() != 1 {
+            return Err(format!("Limits must be set exactly once for the {:?} tier", tier));
+        }
+    }
+    for limits in tier_limits {
+        if limits.daily_outgoing_e8s == 0
+            || limits.daily_outgoing_e8s > limits.weekly_outgoing_e8s
+            || limits.weekly_outgoing_e8s > limits.monthly_outgoing_e8s
+        {
+            return Err(format!("{:?} tier caps must satisfy 0 < daily <= weekly <= monthly", limits.tier));
+        }
+        if limits.max_transactions_per_hour == 0 {
+            return Err(format!("{:?} tier must allow at least one transaction per hour", limits.tier));
+        }
+        if limits.monthly_topup_e8s.is_some_and(|cap| cap < limits.max_topup_e8s) {
+            return Err(format!("{:?} tier's monthly top-up cap is below its single top-up cap", limits.tier));
+        }
+    }
+    Ok(())
+}
+
+// `payments` is how many payments `amount` covers
+pub fn check_outgoing(limits: &TierLimits, usage: &OutgoingUsage, amount: u64, payments: u32) -> Result<(), String> {
+    if usage.last_hour_count.saturating_add(payments) > limits.max_transactions_per_hour {
+        return Err(format!(
+            "Limit reached: at most {} payments per hour",
+            limits.max_transactions_per_hour
+        ));
+    }
+    for (window, used, cap) in [
+        ("daily", usage.daily, limits.daily_outgoing_e8s),
+        ("weekly", usage.weekly, limits.weekly_outgoing_e8s),
+        ("monthly", usage.monthly, limits.monthly_outgoing_e8s),
+    ] {
+        if used.saturating_add(amount) > cap {
+            return Err(format!(
+                "This payment exceeds your {} limit ({} left)",
+                window,
+                format_icp(cap.saturating_sub(used), DEFAULT_LANGUAGE)
+            ));
+        }
+    }
+    Ok(())
+}
+
+// `monthly_topups` is what the user topped up over the last 30 days
+pub fn check_topup(limits: &TierLimits, amount: u64, monthly_topups: u64) -> Result<(), String> {
+    if amount > limits.max_topup_e8s {
+        return Err(format!(
+            "Top-ups are limited to {} for your verification tier",
+            format_icp(limits.max_topup_e8s, DEFAULT_LANGUAGE)
+        ));
+    }
+    if let Some(cap) = limits.monthly_topup_e8s {
+        if monthly_topups.saturating_add(amount) > cap {
+            return Err(format!(
+                "This top-up exceeds your monthly limit ({} left); verify your identity to raise it",
+                format_icp(cap.saturating_sub(monthly_topups), DEFAULT_LANGUAGE)
+            ));
+        }
+    }
+    Ok(())
+}
+
+pub fn remaining_limits(limits: TierLimits, usage: &OutgoingUsage, monthly_topups: u64) -> UserLimits {
+    UserLimits {
+        tier: limits.tier,
+        daily_remaining_e8s: limits.daily_outgoing_e8s.saturating_sub(usage.daily),
+        weekly_remaining_e8s: limits.weekly_outgoing_e8s.saturating_sub(usage.weekly),
+        monthly_remaining_e8s: limits.monthly_outgoing_e8s.saturating_sub(usage.monthly),
+        transactions_remaining_this_hour: limits.max_transactions_per_hour.saturating_sub(usage.last_hour_count),
+        monthly_topup_remaining_e8s: limits.monthly_topup_e8s.map(|cap| cap.saturating_sub(monthly_topups)),
+        limits,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ICP: u64 = 100_000_000;
+
+    #[test]
+    fn test_outgoing_usage_windows() {
+        let now = 40 * DAY_NANOS;
+        let sent = [
+            (now - 10, ICP, 1),
+            (now - 2 * HOUR_NANOS, 2 * ICP, 1),
+            (now - 3 * DAY_NANOS, 4 * ICP, 1),
+            (now - 20 * DAY_NANOS, 8 * ICP, 1),
+            (now - 35 * DAY_NANOS, 16 * ICP, 1),
+        ];
+        let usage = outgoing_usage(sent.into_iter(), now);
+        assert_eq!(usage, OutgoingUsage { daily: 3 * ICP, weekly: 7 * ICP, monthly: 15 * ICP, last_hour_count: 1 });
+
+        // A batch payout debits once but counts every recipient
+        let batch = outgoing_usage([(now - 10, 5 * ICP, 40)].into_iter(), now);
+        assert_eq!(batch.last_hour_count, 40);
+    }
+
+    #[test]
+    fn test_refunded_payments_free_their_share() {
+        let user = candid::Principal::from_slice(&[1]);
+        let log = |reference: &str, change_type: BalanceChangeType, amount: u64| BalanceChangeLog {
+            id: format!("BAL_{:?}_{}", change_type, reference),
+            user_id: user,
+            change_type,
+            amount,
+            previous_balance: 0,
+            new_balance: 0,
+            timestamp: 0,
+            reference_id: reference.to_string(),
+            description: String::new(),
+        };
+        let sent = [
+            log("TX_A", BalanceChangeType::PaymentSent, 10 * ICP),
+            log("TX_B", BalanceChangeType::PaymentSent, 20 * ICP),
+            log("TX_C", BalanceChangeType::PaymentSent, 40 * ICP),
+        ];
+        let refunds = [
+            // Escrow refund, fee included
+            log("TX_A", BalanceChangeType::Refund, 10 * ICP + 10_000),
+            // Escrow split: a quarter back to the payer
+            log("TX_C", BalanceChangeType::Refund, 10 * ICP),
+            // Not tied to a payment
+            log("TX_ICRC_1", BalanceChangeType::Refund, ICP),
+        ];
+
+        let net: Vec<(String, u64)> = net_of_refunds(&sent, &refunds)
+            .into_iter()
+            .map(|(log, amount)| (log.reference_id.clone(), amount))
+            .collect();
+        assert_eq!(net, vec![("TX_B".to_string(), 20 * ICP), ("TX_C".to_string(), 30 * ICP)]);
+    }
+
+    #[test]
+    fn test_check_outgoing() {
+        let limits = limits_for_tier(&default_tier_limits(), KycTier::None);
+        let usage = OutgoingUsage { daily: 45 * ICP, weekly: 45 * ICP, monthly: 45 * ICP, last_hour_count: 3 };
+        assert!(check_outgoing(&limits, &usage, 5 * ICP, 1).is_ok());
+        assert!(check_outgoing(&limits, &usage, 5 * ICP + 1, 1).is_err());
+
+        let busy = OutgoingUsage { last_hour_count: limits.max_transactions_per_hour, ..OutgoingUsage::default() };
+        assert!(check_outgoing(&limits, &busy, 1, 1).is_err());
+        // A batch needs room for all of its payments
+        let quiet = OutgoingUsage::default();
+        assert!(check_outgoing(&limits, &quiet, 1, limits.max_transactions_per_hour).is_ok());
+        assert!(check_outgoing(&limits, &quiet, 1, limits.max_transactions_per_hour + 1).is_err());
+
+        let remaining = remaining_limits(limits.clone(), &usage, 0);
+        assert_eq!(remaining.daily_remaining_e8s, 5 * ICP);
+        assert_eq!(remaining.transactions_remaining_this_hour, limits.max_transactions_per_hour - 3);
+    }
+
+    #[test]
+    fn test_check_topup() {
+        let limits = limits_for_tier(&default_tier_limits(), KycTier::None);
+        let monthly_cap = limits.monthly_topup_e8s.unwrap();
+        assert!(check_topup(&limits, limits.max_topup_e8s, 0).is_ok());
+        assert!(check_topup(&limits, limits.max_topup_e8s + 1, 0).is_err());
+        assert!(check_topup(&limits, ICP, monthly_cap - ICP).is_ok());
+        assert!(check_topup(&limits, ICP, monthly_cap - ICP + 1).is_err());
+        assert_eq!(remaining_limits(limits, &OutgoingUsage::default(), ICP).monthly_topup_remaining_e8s, Some(monthly_cap - ICP));
+
+        // Fully verified users have no monthly cap
+        let full = limits_for_tier(&default_tier_limits(), KycTier::Full);
+        assert!(check_topup(&full, full.max_topup_e8s, u64::MAX / 2).is_ok());
+    }
+
+    #[test]
+    fn test_validate_tier_limits() {
+        let mut tiers = default_tier_limits();
+        assert!(validate_tier_limits(&tiers).is_ok());
+
+        tiers[1].weekly_outgoing_e8s = tiers[1].daily_outgoing_e8s - 1;
+        assert!(validate_tier_limits(&tiers).is_err());
+
+        let mut missing = default_tier_limits();
+        missing.pop();
+        assert!(validate_tier_limits(&missing).is_err());
+        // The built-in limits cover a tier missing from the config
+        assert_eq!(limits_for_tier(&missing, KycTier::Full).tier, KycTier::Full);
+    }
+}
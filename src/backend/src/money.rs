@@ -0,0 +1,351 @@
+use candid::{CandidType, Deserialize};
+use serde::Serialize;
+use std::fmt;
+
+pub const E8S_PER_ICP: u128 = 100_000_000;
+
+// Exchange rates arrive as f64; they are pinned to this many decimal places
+// before any arithmetic so conversions are reproducible.
+const RATE_DECIMALS: u32 = 8;
+
+// Fixed-point fiat amount. The currency code always travels next to it
+// (e.g. `fiat_currency`), `scale` is the number of decimal places.
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct Money {
+    pub minor_units: u64,
+    pub scale: u8,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RoundingMode {
+    Down,
+    Up,
+    HalfUp,
+    HalfEven,
+}
+
+// Minor units from the currency registry, falling back to ISO 4217
+pub fn currency_minor_units(currency: &str) -> u8 {
+    crate::get_currency_info(currency)
+        .map(|info| info.minor_units)
+        .unwrap_or_else(|| iso_minor_units(currency))
+}
+
+pub fn iso_minor_units(currency: &str) -> u8 {
+    match currency.to_uppercase().as_str() {
+        "JPY" | "VND" | "KRW" => 0,
+        "BHD" | "KWD" | "OMR" => 3,
+        _ => 2,
+    }
+}
+
+impl Money {
+    pub fn new(minor_units: u64, scale: u8) -> Self {
+        Money { minor_units, scale }
+    }
+
+    pub fn zero(currency: &str) -> Self {
+        Money::new(0, currency_minor_units(currency))
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.minor_units == 0
+    }
+
+    // Parse a decimal string such as "100.50" using the currency's minor units.
+    // More fractional digits than the currency allows is an error, not a rounding.
+    pub fn parse(amount: &str, currency: &str) -> Result<Money, String> {
+        Money::parse_scaled(amount, currency_minor_units(currency), currency)
+    }
+
+    pub fn parse_scaled(amount: &str, scale: u8, currency: &str) -> Result<Money, String> {
+        let amount = amount.trim();
+
+        if amount.is_empty() {
+            return Err("Amount is required".to_string());
+        }
+
+        let (whole, fraction) = match amount.split_once('.') {
+            Some((w, f)) => (w, f),
+            None => (amount, ""),
+        };
+
+        if whole.is_empty() && fraction.is_empty() {
+            return Err(format!("Invalid amount: {}", amount));
+        }
+
+        if !whole.chars().all(|c| c.is_ascii_digit()) || !fraction.chars().all(|c| c.is_ascii_digit()) {
+            return Err(format!("Invalid amount: {}", amount));
+        }
+
+        if fraction.len() > scale as usize {
+            return Err(format!(
+                "{} supports at most {} decimal places",
+                currency.to_uppercase(),
+                scale
+            ));
+        }
+
+        let whole_units: u64 = if whole.is_empty() {
+            0
+        } else {
+            whole.parse().map_err(|_| format!("Amount too large: {}", amount))?
+        };
+
+        let padded_fraction = format!("{:0<width$}", fraction, width = scale as usize);
+        let fraction_units: u64 = if padded_fraction.is_empty() {
+            0
+        } else {
+            padded_fraction.parse().map_err(|_| format!("Invalid amount: {}", amount))?
+        };
+
+        let minor_units = whole_units
+            .checked_mul(10u64.pow(scale as u32))
+            .and_then(|units| units.checked_add(fraction_units))
+            .ok_or(format!("Amount too large: {}", amount))?;
+
+        Ok(Money::new(minor_units, scale))
+    }
+
+    // Only used to carry over amounts that were stored as f64
+    pub fn from_f64(amount: f64, scale: u8, mode: RoundingMode) -> Money {
+        if !amount.is_finite() || amount <= 0.0 {
+            return Money::new(0, scale);
+        }
+
+        let scaled = amount * 10f64.powi(scale as i32);
+        let rounded = match mode {
+            RoundingMode::Down => scaled.floor(),
+            RoundingMode::Up => scaled.ceil(),
+            RoundingMode::HalfUp => scaled.round(),
+            RoundingMode::HalfEven => {
+                let floor = scaled.floor();
+                let diff = scaled - floor;
+                if diff > 0.5 || (diff == 0.5 && floor % 2.0 != 0.0) {
+                    floor + 1.0
+                } else {
+                    floor
+                }
+            }
+        };
+
+        Money::new(rounded.min(u64::MAX as f64) as u64, scale)
+    }
+
+    // Re-express the amount with a different number of decimal places
+    pub fn rescale(&self, scale: u8, mode: RoundingMode) -> Money {
+        if scale >= self.scale {
+            let factor = 10u128.pow((scale - self.scale) as u32);
+            let units = (self.minor_units as u128).saturating_mul(factor);
+            Money::new(units.min(u64::MAX as u128) as u64, scale)
+        } else {
+            let factor = 10u128.pow((self.scale - scale) as u32);
+            let units = div_round(self.minor_units as u128, factor, mode);
+            Money::new(units as u64, scale)
+        }
+    }
+
+    pub fn checked_add(&self, other: &Money) -> Option<Money> {
+        let scale = self.scale.max(other.scale);
+        let a = self.rescale(scale, RoundingMode::HalfEven);
+        let b = other.rescale(scale, RoundingMode::HalfEven);
+        a.minor_units.checked_add(b.minor_units).map(|units| Money::new(units, scale))
+    }
+
+    pub fn saturating_add(&self, other: &Money) -> Money {
+        let scale = self.scale.max(other.scale);
+        self.checked_add(other).unwrap_or(Money::new(u64::MAX, scale))
+    }
+}
+
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.scale == 0 {
+            return write!(f, "{}", self.minor_units);
+        }
+
+        let factor = 10u64.pow(self.scale as u32);
+        write!(
+            f,
+            "{}.{:0width$}",
+            self.minor_units / factor,
+            self.minor_units % factor,
+            width = self.scale as usize
+        )
+    }
+}
+
+pub fn div_round(numerator: u128, denominator: u128, mode: RoundingMode) -> u128 {
+    let quotient = numerator / denominator;
+    let remainder = numerator % denominator;
+
+    if remainder == 0 {
+        return quotient;
+    }
+
+    let round_up = match mode {
+        RoundingMode::Down => false,
+        RoundingMode::Up => true,
+        RoundingMode::HalfUp => remainder * 2 >= denominator,
+        RoundingMode::HalfEven => {
+            let twice = remainder * 2;
+            twice > denominator || (twice == denominator && quotient % 2 == 1)
+        }
+    };
+
+    if round_up {
+        quotient + 1
+    } else {
+        quotient
+    }
+}
+
+// Pin an f64 ICP->fiat rate to RATE_DECIMALS places
+pub fn rate_to_fixed(exchange_rate: f64) -> Result<u128, String> {
+    if !exchange_rate.is_finite() || exchange_rate <= 0.0 {
+        return Err("Exchange rate must be greater than 0".to_string());
+    }
+
+    let fixed = (exchange_rate * 10f64.powi(RATE_DECIMALS as i32)).round();
+    if fixed < 1.0 {
+        return Err("Exchange rate too small".to_string());
+    }
+
+    Ok(fixed as u128)
+}
+
+// fiat / rate, in e8s
+pub fn fiat_to_e8s(amount: &Money, exchange_rate: f64, mode: RoundingMode) -> Result<u64, String> {
+    let rate_fixed = rate_to_fixed(exchange_rate)?;
+
+    let numerator = (amount.minor_units as u128)
+        .checked_mul(E8S_PER_ICP)
+        .and_then(|n| n.checked_mul(10u128.pow(RATE_DECIMALS)))
+        .ok_or("Amount too large")?;
+    let denominator = rate_fixed
+        .checked_mul(10u128.pow(amount.scale as u32))
+        .ok_or("Amount too large")?;
+
+    let e8s = div_round(numerator, denominator, mode);
+    u64::try_from(e8s).map_err(|_| "Amount too large".to_string())
+}
+
+// e8s * rate, expressed with the given scale
+pub fn e8s_to_fiat(e8s: u64, exchange_rate: f64, scale: u8, mode: RoundingMode) -> Result<Money, String> {
+    let rate_fixed = rate_to_fixed(exchange_rate)?;
+
+    let numerator = (e8s as u128)
+        .checked_mul(rate_fixed)
+        .and_then(|n| n.checked_mul(10u128.pow(scale as u32)))
+        .ok_or("Amount too large")?;
+    let denominator = E8S_PER_ICP * 10u128.pow(RATE_DECIMALS);
+
+    let units = div_round(numerator, denominator, mode);
+    u64::try_from(units)
+        .map(|units| Money::new(units, scale))
+        .map_err(|_| "Amount too large".to_string())
+}
+
+// amount / from_rate * to_rate, i.e. fiat -> ICP -> fiat without an e8s round trip
+pub fn cross_convert(
+    amount: &Money,
+    from_rate: f64,
+    to_rate: f64,
+    to_scale: u8,
+    mode: RoundingMode,
+) -> Result<Money, String> {
+    let from_fixed = rate_to_fixed(from_rate)?;
+    let to_fixed = rate_to_fixed(to_rate)?;
+
+    let numerator = (amount.minor_units as u128)
+        .checked_mul(to_fixed)
+        .and_then(|n| n.checked_mul(10u128.pow(to_scale as u32)))
+        .ok_or("Amount too large")?;
+    let denominator = from_fixed
+        .checked_mul(10u128.pow(amount.scale as u32))
+        .ok_or("Amount too large")?;
+
+    let units = div_round(numerator, denominator, mode);
+    u64::try_from(units)
+        .map(|units| Money::new(units, to_scale))
+        .map_err(|_| "Amount too large".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_money() {
+        assert_eq!(Money::parse("100.50", "USD").unwrap(), Money::new(10050, 2));
+        assert_eq!(Money::parse("100.5", "USD").unwrap(), Money::new(10050, 2));
+        assert_eq!(Money::parse("100", "JPY").unwrap(), Money::new(100, 0));
+        assert_eq!(Money::parse(".75", "EUR").unwrap(), Money::new(75, 2));
+        assert!(Money::parse("100.5", "JPY").is_err());
+        assert!(Money::parse("1.234", "USD").is_err());
+        assert!(Money::parse("-1", "USD").is_err());
+        assert!(Money::parse("1e5", "USD").is_err());
+        assert!(Money::parse("", "USD").is_err());
+    }
+
+    #[test]
+    fn test_money_display() {
+        assert_eq!(Money::new(10050, 2).to_string(), "100.50");
+        assert_eq!(Money::new(5, 2).to_string(), "0.05");
+        assert_eq!(Money::new(101, 0).to_string(), "101");
+    }
+
+    #[test]
+    fn test_from_f64_rounding() {
+        assert_eq!(Money::from_f64(0.1 + 0.2, 2, RoundingMode::HalfUp), Money::new(30, 2));
+        assert_eq!(Money::from_f64(100.5, 0, RoundingMode::HalfEven), Money::new(100, 0));
+        assert_eq!(Money::from_f64(101.5, 0, RoundingMode::HalfEven), Money::new(102, 0));
+        assert_eq!(Money::from_f64(100.5, 0, RoundingMode::HalfUp), Money::new(101, 0));
+        assert_eq!(Money::from_f64(-3.0, 2, RoundingMode::HalfUp), Money::new(0, 2));
+    }
+
+    #[test]
+    fn test_div_round_modes() {
+        assert_eq!(div_round(7, 2, RoundingMode::Down), 3);
+        assert_eq!(div_round(7, 2, RoundingMode::Up), 4);
+        assert_eq!(div_round(7, 2, RoundingMode::HalfUp), 4);
+        assert_eq!(div_round(7, 2, RoundingMode::HalfEven), 4);
+        assert_eq!(div_round(5, 2, RoundingMode::HalfEven), 2);
+        assert_eq!(div_round(10, 3, RoundingMode::HalfUp), 3);
+    }
+
+    #[test]
+    fn test_fiat_to_e8s() {
+        let amount = Money::parse("100", "USD").unwrap();
+        assert_eq!(fiat_to_e8s(&amount, 5.0, RoundingMode::Down).unwrap(), 2_000_000_000);
+
+        // 10 / 3 ICP does not divide evenly in e8s
+        let amount = Money::parse("10", "USD").unwrap();
+        assert_eq!(fiat_to_e8s(&amount, 3.0, RoundingMode::Down).unwrap(), 333_333_333);
+        assert_eq!(fiat_to_e8s(&amount, 3.0, RoundingMode::Up).unwrap(), 333_333_334);
+    }
+
+    #[test]
+    fn test_e8s_to_fiat() {
+        assert_eq!(e8s_to_fiat(2_000_000_000, 5.0, 2, RoundingMode::HalfUp).unwrap(), Money::new(10000, 2));
+        assert_eq!(e8s_to_fiat(333_333_333, 3.0, 2, RoundingMode::HalfUp).unwrap(), Money::new(1000, 2));
+    }
+
+    #[test]
+    fn test_cross_convert() {
+        // 1 ICP = 5 USD = 80,000 IDR
+        let usd = Money::parse("10", "USD").unwrap();
+        let idr = cross_convert(&usd, 5.0, 80_000.0, 0, RoundingMode::HalfUp).unwrap();
+        assert_eq!(idr, Money::new(160_000, 0));
+
+        let back = cross_convert(&idr, 80_000.0, 5.0, 2, RoundingMode::HalfUp).unwrap();
+        assert_eq!(back, usd);
+    }
+
+    #[test]
+    fn test_totals_do_not_drift() {
+        let cent = Money::parse("0.10", "USD").unwrap();
+        let total = (0..10).fold(Money::zero("USD"), |acc, _| acc.saturating_add(&cent));
+        assert_eq!(total, Money::parse("1.00", "USD").unwrap());
+    }
+}
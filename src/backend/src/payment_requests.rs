@@ -0,0 +1,139 @@
+use crate::types::*;
+use candid::Principal;
+
+// A request shares its id with the single-use QR code reserved for the payer,
+// so paying that code (from the request or the payment page) settles it
+pub fn build_payment_request(qr_code: &QRCode, payer: Principal, note: Option<String>) -> PaymentRequest {
+    PaymentRequest {
+        id: qr_code.id.clone(),
+        requester: qr_code.user_id,
+        payer,
+        amount: qr_code.fiat_amount,
+        currency: qr_code.fiat_currency.clone(),
+        icp_amount: qr_code.icp_amount,
+        note,
+        status: PaymentRequestStatus::Pending,
+        created_at: qr_code.created_at,
+        expires_at: qr_code.expire_time,
+        responded_at: None,
+        transaction_id: None,
+    }
+}
+
+// Pending requests past their expiry read as expired without a write
+pub fn request_status(request: &PaymentRequest, now: u64) -> PaymentRequestStatus {
+    if request.status == PaymentRequestStatus::Pending && now >= request.expires_at {
+        PaymentRequestStatus::Expired
+    } else {
+        request.status.clone()
+    }
+}
+
+pub fn refresh_request_status(request: &mut PaymentRequest, now: u64) {
+    request.status = request_status(request, now);
+}
+
+pub fn ensure_pending(request: &PaymentRequest, now: u64) -> Result<(), String> {
+    match request_status(request, now) {
+        PaymentRequestStatus::Pending => Ok(()),
+        status => Err(format!("Payment request is no longer pending ({:?})", status)),
+    }
+}
+
+pub fn decline_request(request: &mut PaymentRequest, caller: Principal, now: u64) -> Result<(), String> {
+    if request.payer != caller {
+        return Err("Payment request not found".to_string());
+    }
+    ensure_pending(request, now)?;
+    request.status = PaymentRequestStatus::Declined;
+    request.responded_at = Some(now);
+    Ok(())
+}
+
+pub fn cancel_request(request: &mut PaymentRequest, caller: Principal, now: u64) -> Result<(), String> {
+    if request.requester != caller {
+        return Err("Payment request not found".to_string());
+    }
+    ensure_pending(request, now)?;
+    request.status = PaymentRequestStatus::Cancelled;
+    request.responded_at = Some(now);
+    Ok(())
+}
+
+// A request closed while its payment was held stays closed
+pub fn mark_request_paid(request: &mut PaymentRequest, transaction_id: String, now: u64) {
+    if request.status != PaymentRequestStatus::Pending {
+        return;
+    }
+    request.status = PaymentRequestStatus::Accepted;
+    request.responded_at = Some(now);
+    request.transaction_id = Some(transaction_id);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::money::Money;
+
+    fn request(expires_at: u64) -> PaymentRequest {
+        PaymentRequest {
+            id: "REQ".to_string(),
+            requester: Principal::from_slice(&[1]),
+            payer: Principal::from_slice(&[2]),
+            amount: Money::new(1_500, 2),
+            currency: "USD".to_string(),
+            icp_amount: 150_000_000,
+            note: Some("Dinner".to_string()),
+            status: PaymentRequestStatus::Pending,
+            created_at: 0,
+            expires_at,
+            responded_at: None,
+            transaction_id: None,
+        }
+    }
+
+    #[test]
+    fn test_request_expiry() {
+        let mut req = request(100);
+        assert_eq!(request_status(&req, 99), PaymentRequestStatus::Pending);
+        assert_eq!(request_status(&req, 100), PaymentRequestStatus::Expired);
+        assert!(ensure_pending(&req, 100).is_err());
+
+        refresh_request_status(&mut req, 100);
+        assert_eq!(req.status, PaymentRequestStatus::Expired);
+    }
+
+    #[test]
+    fn test_decline_and_cancel() {
+        let requester = Principal::from_slice(&[1]);
+        let payer = Principal::from_slice(&[2]);
+
+        let mut req = request(100);
+        assert!(decline_request(&mut req, requester, 10).is_err());
+        decline_request(&mut req, payer, 10).unwrap();
+        assert_eq!(req.status, PaymentRequestStatus::Declined);
+        assert_eq!(req.responded_at, Some(10));
+        assert!(cancel_request(&mut req, requester, 11).is_err());
+
+        let mut req = request(100);
+        assert!(cancel_request(&mut req, payer, 10).is_err());
+        cancel_request(&mut req, requester, 10).unwrap();
+        assert_eq!(req.status, PaymentRequestStatus::Cancelled);
+    }
+
+    #[test]
+    fn test_mark_paid() {
+        let mut req = request(100);
+        mark_request_paid(&mut req, "TX_1".to_string(), 50);
+        assert_eq!(req.status, PaymentRequestStatus::Accepted);
+        assert_eq!(req.transaction_id.as_deref(), Some("TX_1"));
+        // Paid requests never expire
+        assert_eq!(request_status(&req, 1_000), PaymentRequestStatus::Accepted);
+
+        let mut req = request(100);
+        cancel_request(&mut req, Principal::from_slice(&[1]), 10).unwrap();
+        mark_request_paid(&mut req, "TX_2".to_string(), 50);
+        assert_eq!(req.status, PaymentRequestStatus::Cancelled);
+        assert_eq!(req.transaction_id, None);
+    }
+}
@@ -0,0 +1,127 @@
+use crate::types::*;
+use candid::Principal;
+use ic_cdk::api::time;
+use sha2::{Digest, Sha256};
+
+pub const MAX_PAYOUT_ENTRIES: usize = 500;
+
+// Recipients credited per message; larger batches continue in follow-up
+// messages so each one stays well under the instruction limit
+pub const PAYOUTS_PER_MESSAGE: u32 = 50;
+
+pub fn generate_payout_batch_id(payer: Principal, sequence: u64) -> String {
+    let combined = format!("{}-{}-{}", payer.to_text(), time(), sequence);
+
+    let mut hasher = Sha256::new();
+    hasher.update(combined.as_bytes());
+    let hash = hasher.finalize();
+
+    format!("PAYOUT_{:X}", hash)[..23].to_string()
+}
+
+// "recipient,amount[,memo]" per line. Blank lines, `#` comments and a
+// leading header row are skipped; the memo may itself contain commas.
+pub fn parse_payout_csv(csv: &str) -> Result<Vec<PayoutEntry>, String> {
+    let mut entries = Vec::new();
+    for (number, line) in csv.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut fields = line.splitn(3, ',').map(str::trim);
+        let recipient = fields.next().unwrap_or_default();
+        let amount = fields.next().unwrap_or_default();
+        let memo = fields.next().filter(|memo| !memo.is_empty());
+
+        if entries.is_empty() && recipient.eq_ignore_ascii_case("recipient") {
+            continue;
+        }
+        if recipient.is_empty() || amount.is_empty() {
+            return Err(format!("Line {}: expected recipient,amount[,memo]", number + 1));
+        }
+
+        entries.push(PayoutEntry {
+            recipient: recipient.to_string(),
+            amount: amount.to_string(),
+            memo: memo.map(str::to_string),
+        });
+    }
+    Ok(entries)
+}
+
+pub fn validate_payout_size(entries: usize) -> Result<(), String> {
+    if entries == 0 {
+        return Err("A payout batch needs at least one entry".to_string());
+    }
+    if entries > MAX_PAYOUT_ENTRIES {
+        return Err(format!("A payout batch can have at most {} entries", MAX_PAYOUT_ENTRIES));
+    }
+    Ok(())
+}
+
+// Entries credited by the next message
+pub fn next_payout_chunk(batch: &PayoutBatch) -> std::ops::Range<usize> {
+    let start = (batch.next_index as usize).min(batch.results.len());
+    let end = (start + PAYOUTS_PER_MESSAGE as usize).min(batch.results.len());
+    start..end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::money::Money;
+
+    #[test]
+    fn test_parse_payout_csv() {
+        let csv = "recipient,amount,memo\n\
+                   # staff\n\
+                   alice@example.com, 1.5, March salary, bonus\n\
+                   \n\
+                   @bob,0.25\n";
+        let entries = parse_payout_csv(csv).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].recipient, "alice@example.com");
+        assert_eq!(entries[0].amount, "1.5");
+        assert_eq!(entries[0].memo.as_deref(), Some("March salary, bonus"));
+        assert_eq!(entries[1].memo, None);
+
+        assert!(parse_payout_csv("alice\n").is_err());
+        assert!(parse_payout_csv("alice,\n").is_err());
+    }
+
+    #[test]
+    fn test_payout_chunks() {
+        assert!(validate_payout_size(0).is_err());
+        assert!(validate_payout_size(MAX_PAYOUT_ENTRIES).is_ok());
+        assert!(validate_payout_size(MAX_PAYOUT_ENTRIES + 1).is_err());
+
+        let result = PayoutResult {
+            recipient: "alice".to_string(),
+            recipient_id: None,
+            amount: Money::new(100, 8),
+            icp_amount: 100,
+            fee: 10_000,
+            memo: None,
+            transaction_id: None,
+            error: None,
+        };
+        let mut batch = PayoutBatch {
+            id: "PAYOUT_1".to_string(),
+            payer: Principal::from_slice(&[1]),
+            currency: "ICP".to_string(),
+            results: vec![result; 120],
+            total_icp: 0,
+            total_fee: 0,
+            status: PayoutBatchStatus::Processing,
+            next_index: 0,
+            created_at: 0,
+            completed_at: None,
+        };
+        assert_eq!(next_payout_chunk(&batch), 0..50);
+        batch.next_index = 100;
+        assert_eq!(next_payout_chunk(&batch), 100..120);
+        batch.next_index = 120;
+        assert!(next_payout_chunk(&batch).is_empty());
+    }
+}
@@ -1,5 +1,8 @@
 use crate::types::*;
 use crate::rates::*;
+use crate::money::{Money, RoundingMode};
+use crate::currencies::check_payment_amount;
+use crate::formatting::{format_icp, format_money};
 use candid::Principal;
 use ic_cdk::api::time;
 use sha2::{Digest, Sha256};
@@ -20,62 +23,227 @@ pub fn generate_qr_id() -> String {
 
 pub async fn create_qr_code(
     user_id: Principal,
-    fiat_amount: f64,
+    fiat_amount: Money,
     fiat_currency: String,
     description: Option<String>,
+    expiry_seconds: u64,
+    max_uses: Option<u32>,
+    allowed_payer: Option<Principal>,
 ) -> Result<QRCode, String> {
 
-    if fiat_amount <= 0.0 {
+    if fiat_amount.is_zero() {
         return Err("Amount must be greater than 0".to_string());
     }
 
-    if !is_supported_currency(&fiat_currency) {
-        return Err(format!("Unsupported currency: {}", fiat_currency));
-    }
+    let currency_info = crate::get_currency_info(&fiat_currency)
+        .filter(|info| info.enabled)
+        .ok_or(format!("Unsupported currency: {}", fiat_currency))?;
+
+    check_payment_amount(&currency_info, &fiat_amount)?;
 
 
     let exchange_rate = fetch_live_exchange_rate(fiat_currency.clone()).await?;
     
 
-    let icp_amount = calculate_icp_amount(fiat_amount, exchange_rate.rate)?;
+    // Round up so the merchant never receives less than the fiat price
+    let icp_amount = calculate_icp_amount(&fiat_amount, exchange_rate.rate, RoundingMode::Up)?;
 
     let qr_id = generate_qr_id();
     let current_time = time();
     
-
-    let expire_time = current_time + (30 * 60 * 1_000_000_000);
+    let expire_time = current_time.saturating_add(expiry_seconds.saturating_mul(1_000_000_000));
 
     let qr_code = QRCode {
         id: qr_id, 
         user_id,
+        kind: QRKind::Dynamic,
         fiat_amount,
         fiat_currency: fiat_currency.to_uppercase(),
         icp_amount,
         expire_time,
         created_at: current_time,
-        is_used: false,
+        status: QRStatus::Active,
         description,
+        max_uses,
+        allowed_payer,
+        invoice_id: None,
     };
 
     Ok(qr_code)
 }
 
-pub fn is_qr_code_valid(qr_code: &QRCode) -> Result<(), String> {
-    let current_time = time();
-    
-    if qr_code.is_used {
-        return Err("QR code has already been used".to_string());
+// Static codes have no amount of their own; the payer's amount is priced
+// at payment time.
+pub fn create_static_qr_code(
+    user_id: Principal,
+    fiat_currency: String,
+    description: Option<String>,
+) -> Result<QRCode, String> {
+    if !is_supported_currency(&fiat_currency) {
+        return Err(format!("Unsupported currency: {}", fiat_currency));
     }
-    
-    if current_time > qr_code.expire_time {
-        return Err("QR code has expired".to_string());
+
+    Ok(QRCode {
+        id: generate_qr_id(),
+        user_id,
+        kind: QRKind::Static,
+        fiat_amount: Money::zero(&fiat_currency),
+        fiat_currency: fiat_currency.to_uppercase(),
+        icp_amount: 0,
+        expire_time: u64::MAX,
+        created_at: time(),
+        status: QRStatus::Active,
+        description,
+        max_uses: None,
+        allowed_payer: None,
+        invoice_id: None,
+    })
+}
+
+// Price a payer-entered amount against a static QR code
+pub async fn quote_static_payment(qr_code: &QRCode, payer_amount: &Money) -> Result<u64, String> {
+    quote_fiat_payment(&qr_code.fiat_currency, payer_amount).await
+}
+
+// ICP needed for a payer-entered fiat amount, at the live rate
+pub async fn quote_fiat_payment(currency: &str, amount: &Money) -> Result<u64, String> {
+    if amount.is_zero() {
+        return Err("Amount must be greater than 0".to_string());
     }
-    
+
+    let currency_info = crate::get_currency_info(currency)
+        .filter(|info| info.enabled)
+        .ok_or(format!("Unsupported currency: {}", currency))?;
+
+    check_payment_amount(&currency_info, amount)?;
+
+    let exchange_rate = fetch_live_exchange_rate(currency_info.code.clone()).await?;
+    calculate_icp_amount(amount, exchange_rate.rate, RoundingMode::Up)
+}
+
+pub fn validate_qr_policy(policy: &QRPolicy) -> Result<(), String> {
+    if policy.min_expiry_seconds == 0 || policy.min_expiry_seconds > policy.max_expiry_seconds {
+        return Err("Expiry bounds must satisfy 0 < min <= max".to_string());
+    }
+
+    if policy.default_expiry_seconds < policy.min_expiry_seconds
+        || policy.default_expiry_seconds > policy.max_expiry_seconds
+    {
+        return Err("Default expiry must be within the expiry bounds".to_string());
+    }
+
+    if policy.max_uses_limit == 0 {
+        return Err("Max uses limit must be at least 1".to_string());
+    }
+
     Ok(())
 }
 
-pub fn mark_qr_as_used(qr_code: &mut QRCode) {
-    qr_code.is_used = true;
+// Merchant-requested expiry, or the policy default, checked against the bounds
+pub fn resolve_expiry_seconds(policy: &QRPolicy, requested: Option<u64>) -> Result<u64, String> {
+    let seconds = requested.unwrap_or(policy.default_expiry_seconds);
+    if seconds < policy.min_expiry_seconds || seconds > policy.max_expiry_seconds {
+        return Err(format!(
+            "Expiry must be between {} and {} seconds",
+            policy.min_expiry_seconds, policy.max_expiry_seconds
+        ));
+    }
+    Ok(seconds)
+}
+
+pub fn validate_max_uses(policy: &QRPolicy, max_uses: Option<u32>) -> Result<(), String> {
+    match max_uses {
+        Some(0) => Err("Max uses must be at least 1".to_string()),
+        Some(uses) if uses > policy.max_uses_limit => {
+            Err(format!("Max uses cannot exceed {}", policy.max_uses_limit))
+        }
+        _ => Ok(()),
+    }
+}
+
+// Effective usage limit; None means unlimited
+pub fn max_uses(qr_code: &QRCode) -> Option<u32> {
+    match (qr_code.max_uses, &qr_code.kind) {
+        (Some(uses), _) => Some(uses),
+        (None, QRKind::Dynamic) => Some(1),
+        (None, QRKind::Static) => None,
+        // Split codes close once the bill is covered
+        (None, QRKind::Split) => None,
+    }
+}
+
+pub fn remaining_uses(qr_code: &QRCode, completed_uses: u64) -> Option<u32> {
+    max_uses(qr_code).map(|uses| (uses as u64).saturating_sub(completed_uses) as u32)
+}
+
+pub fn is_qr_code_valid(qr_code: &QRCode, payer: Principal, completed_uses: u64) -> Result<(), String> {
+    check_qr_payable(qr_code, payer, completed_uses, time())
+}
+
+fn check_qr_payable(
+    qr_code: &QRCode,
+    payer: Principal,
+    completed_uses: u64,
+    current_time: u64,
+) -> Result<(), String> {
+    match qr_status(qr_code, current_time) {
+        QRStatus::Active => {}
+        QRStatus::Cancelled => return Err("QR code has been cancelled".to_string()),
+        QRStatus::Expired => return Err("QR code has expired".to_string()),
+        QRStatus::Paid => return Err("QR code has already been used".to_string()),
+        QRStatus::Exhausted => return Err("QR code has reached its usage limit".to_string()),
+    }
+
+    if qr_code.allowed_payer.is_some_and(|allowed| allowed != payer) {
+        return Err("This QR code is reserved for another payer".to_string());
+    }
+
+    if remaining_uses(qr_code, completed_uses) == Some(0) {
+        return Err(match max_uses(qr_code) {
+            Some(1) => "QR code has already been used".to_string(),
+            _ => "QR code has reached its usage limit".to_string(),
+        });
+    }
+
+    Ok(())
+}
+
+// Status as seen at `current_time`; static codes never expire
+pub fn qr_status(qr_code: &QRCode, current_time: u64) -> QRStatus {
+    match qr_code.status {
+        QRStatus::Active if qr_code.kind != QRKind::Static && current_time > qr_code.expire_time => {
+            QRStatus::Expired
+        }
+        ref status => status.clone(),
+    }
+}
+
+// Stored status once `completed_uses` payments have gone through
+// Only an active code moves on; a code cancelled while a payment was held
+// stays cancelled when that payment is released
+pub fn status_after_payment(qr_code: &QRCode, completed_uses: u64) -> QRStatus {
+    if qr_code.status != QRStatus::Active {
+        return qr_code.status.clone();
+    }
+    match (remaining_uses(qr_code, completed_uses), max_uses(qr_code)) {
+        (Some(0), Some(1)) => QRStatus::Paid,
+        (Some(0), _) => QRStatus::Exhausted,
+        _ => QRStatus::Active,
+    }
+}
+
+pub fn cancel_qr_code(qr_code: &mut QRCode, caller: Principal, current_time: u64) -> Result<(), String> {
+    if qr_code.user_id != caller {
+        return Err("Only the QR code owner can cancel it".to_string());
+    }
+
+    let status = qr_status(qr_code, current_time);
+    if status != QRStatus::Active {
+        return Err(format!("QR code is no longer active ({:?})", status));
+    }
+
+    qr_code.status = QRStatus::Cancelled;
+    Ok(())
 }
 
 // Generate QR code data URL (for frontend display)
@@ -83,10 +251,18 @@ pub fn generate_qr_data_url(qr_id: &str, frontend_url: &str) -> String {
     format!("{}/pay/{}", frontend_url, qr_id)
 }
 
-// Get QR code display info
-pub fn get_qr_display_info(qr_code: &QRCode) -> QRDisplayInfo {
+// Get QR code display info, formatted for the viewer's language
+pub fn get_qr_display_info(
+    qr_code: &QRCode,
+    language: &str,
+    payer_equivalent: Option<CurrencyConversion>,
+    completed_uses: u64,
+) -> QRDisplayInfo {
     let current_time = time();
-    let time_remaining = if current_time < qr_code.expire_time {
+    let status = qr_status(qr_code, current_time);
+    let time_remaining = if qr_code.kind == QRKind::Static {
+        None
+    } else if current_time < qr_code.expire_time {
         Some((qr_code.expire_time - current_time) / 1_000_000_000) 
     } else {
         None
@@ -94,15 +270,22 @@ pub fn get_qr_display_info(qr_code: &QRCode) -> QRDisplayInfo {
 
     QRDisplayInfo {
         id: qr_code.id.clone(),
+        kind: qr_code.kind.clone(),
         fiat_amount: qr_code.fiat_amount,
         fiat_currency: qr_code.fiat_currency.clone(),
         icp_amount: qr_code.icp_amount,
-        formatted_fiat: format_currency_amount(qr_code.fiat_amount, &qr_code.fiat_currency),
-        formatted_icp: format!("{:.8} ICP", qr_code.icp_amount as f64 / 100_000_000.0),
+        formatted_fiat: format_money(&qr_code.fiat_amount, &qr_code.fiat_currency, language),
+        formatted_icp: format_icp(qr_code.icp_amount, language),
         time_remaining_seconds: time_remaining,
-        is_expired: current_time > qr_code.expire_time,
-        is_used: qr_code.is_used,
+        is_expired: status == QRStatus::Expired,
+        status,
+        remaining_uses: remaining_uses(qr_code, completed_uses),
         description: qr_code.description.clone(),
+        formatted_payer_equivalent: payer_equivalent.as_ref().map(|conversion| {
+            format_money(&conversion.converted_amount, &conversion.to_currency, language)
+        }),
+        payer_equivalent,
+        payment_uri: crate::qr_payment_uri(qr_code),
     }
 }
 
@@ -110,15 +293,126 @@ pub fn get_qr_display_info(qr_code: &QRCode) -> QRDisplayInfo {
 #[derive(candid::CandidType, serde::Serialize, serde::Deserialize, Clone, Debug)]
 pub struct QRDisplayInfo {
     pub id: String,
-    pub fiat_amount: f64,
+    pub kind: QRKind,
+    pub fiat_amount: Money,
     pub fiat_currency: String,
     pub icp_amount: u64,
     pub formatted_fiat: String,
     pub formatted_icp: String,
     pub time_remaining_seconds: Option<u64>,
     pub is_expired: bool,
-    pub is_used: bool,
+    pub status: QRStatus,
+    // None when the code can be paid an unlimited number of times
+    pub remaining_uses: Option<u32>,
     pub description: Option<String>,
+    // The amount in the viewer's preferred currency, when it differs
+    pub payer_equivalent: Option<CurrencyConversion>,
+    pub formatted_payer_equivalent: Option<String>,
+    // ICRC-22 style URI for paying from any ICRC wallet
+    pub payment_uri: Option<String>,
+}
+
+// Several logs can be written in one message (split bills, ledger polls)
+// and `time()` does not move within it, so the id also covers what was logged
+pub fn qr_usage_log_id(qr_id: &str, transaction_id: &str, usage_type: &QRUsageType, now: u64) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(format!("{}|{}|{:?}", qr_id, transaction_id, usage_type).as_bytes());
+    format!("QRU_{}_{}", now, &format!("{:X}", hasher.finalize())[..16])
+}
+
+pub fn build_qr_usage_log(
+    qr_id: String,
+    user_id: Principal,
+    used_by: Principal,
+    transaction_id: String,
+    usage_type: QRUsageType,
+    now: u64,
+) -> QRUsageLog {
+    QRUsageLog {
+        id: qr_usage_log_id(&qr_id, &transaction_id, &usage_type, now),
+        qr_id,
+        user_id,
+        used_by,
+        transaction_id,
+        timestamp: now,
+        usage_type,
+    }
+}
+
+// Held, completed and refunded records of one payment share the id they were
+// created under
+fn held_or_settled_base(transaction_id: &str) -> &str {
+    ["_PROCESSING_", "_COMPLETED_", "_REFUNDED_"]
+        .iter()
+        .find_map(|marker| transaction_id.split_once(marker))
+        .map_or(transaction_id, |(base, _)| base)
+}
+
+// Uses taken from a code by the logs of its payments: completed ones plus
+// held ones that were neither released nor refunded yet
+pub fn count_qr_uses(usage_logs: &[QRUsageLog]) -> u64 {
+    let mut completed = 0;
+    let mut held = Vec::new();
+    let mut settled = Vec::new();
+    for log in usage_logs {
+        match log.usage_type {
+            QRUsageType::PaymentCompleted => {
+                completed += 1;
+                settled.push(held_or_settled_base(&log.transaction_id));
+            }
+            QRUsageType::PaymentRefunded => settled.push(held_or_settled_base(&log.transaction_id)),
+            QRUsageType::PaymentHeld => held.push(held_or_settled_base(&log.transaction_id)),
+            _ => {}
+        }
+    }
+    completed + held.iter().filter(|base| !settled.contains(base)).count() as u64
+}
+
+// Roll usage logs and their completed transactions up into per-QR stats
+pub fn summarize_qr_usage(
+    qr_code: &QRCode,
+    usage_logs: &[QRUsageLog],
+    transactions: &[Transaction],
+) -> QRUsageStats {
+    let mut payers: Vec<Principal> = Vec::new();
+    let mut stats = QRUsageStats {
+        qr_id: qr_code.id.clone(),
+        kind: qr_code.kind.clone(),
+        fiat_currency: qr_code.fiat_currency.clone(),
+        payment_count: 0,
+        failed_count: 0,
+        unique_payers: 0,
+        total_fiat_volume: Money::zero(&qr_code.fiat_currency),
+        total_icp_volume: 0,
+        last_payment_at: None,
+    };
+
+    for log in usage_logs {
+        match log.usage_type {
+            QRUsageType::PaymentCompleted => {
+                stats.payment_count += 1;
+                if !payers.contains(&log.used_by) {
+                    payers.push(log.used_by);
+                }
+                stats.last_payment_at = stats.last_payment_at.max(Some(log.timestamp));
+            }
+            QRUsageType::PaymentFailed => stats.failed_count += 1,
+            QRUsageType::PaymentExpired
+            | QRUsageType::Cancelled
+            | QRUsageType::ContributionHeld
+            | QRUsageType::ContributionRefunded
+            | QRUsageType::PaymentHeld
+            | QRUsageType::PaymentRefunded => {}
+        }
+    }
+
+    for tx in transactions.iter().filter(|tx| tx.status == TransactionStatus::Completed) {
+        stats.total_fiat_volume = stats.total_fiat_volume.saturating_add(&tx.fiat_amount);
+        stats.total_icp_volume = stats.total_icp_volume.saturating_add(tx.icp_amount);
+    }
+
+    stats.unique_payers = payers.len() as u64;
+    stats
 }
 
 // Validate QR code format
@@ -162,4 +456,240 @@ mod tests {
         let url = generate_qr_data_url("ABC123", "https://example.com");
         assert_eq!(url, "https://example.com/pay/ABC123");
     }
+
+    #[test]
+    fn test_summarize_qr_usage() {
+        let merchant = Principal::from_slice(&[1]);
+        let alice = Principal::from_slice(&[2]);
+        let bob = Principal::from_slice(&[3]);
+
+        let qr_code = QRCode {
+            id: "1234567890ABCDEF".to_string(),
+            user_id: merchant,
+            kind: QRKind::Static,
+            fiat_amount: Money::new(0, 2),
+            fiat_currency: "USD".to_string(),
+            icp_amount: 0,
+            expire_time: u64::MAX,
+            created_at: 0,
+            status: QRStatus::Active,
+            description: None,
+            max_uses: None,
+            allowed_payer: None,
+            invoice_id: None,
+        };
+
+        let log = |id: &str, used_by: Principal, timestamp: u64, usage_type: QRUsageType| QRUsageLog {
+            id: id.to_string(),
+            qr_id: qr_code.id.clone(),
+            user_id: merchant,
+            used_by,
+            transaction_id: format!("TX_{}", id),
+            timestamp,
+            usage_type,
+        };
+        let tx = |id: &str, from: Principal, fiat: u64, icp: u64| Transaction {
+            id: format!("TX_{}", id),
+            from,
+            to: merchant,
+            amount: icp,
+            fiat_currency: "USD".to_string(),
+            fiat_amount: Money::new(fiat, 2),
+            icp_amount: icp,
+            timestamp: 0,
+            status: TransactionStatus::Completed,
+            qr_id: qr_code.id.clone(),
+            transaction_hash: None,
+            fee: 10_000,
+            kind: TransactionKind::QrPayment,
+            memo: None,
+        };
+
+        let logs = vec![
+            log("A", alice, 10, QRUsageType::PaymentCompleted),
+            log("B", alice, 30, QRUsageType::PaymentCompleted),
+            log("C", bob, 20, QRUsageType::PaymentCompleted),
+            log("D", bob, 40, QRUsageType::PaymentFailed),
+        ];
+        let transactions = vec![
+            tx("A", alice, 1_050, 100_000_000),
+            tx("B", alice, 250, 25_000_000),
+            tx("C", bob, 700, 70_000_000),
+        ];
+
+        let stats = summarize_qr_usage(&qr_code, &logs, &transactions);
+        assert_eq!(stats.payment_count, 3);
+        assert_eq!(stats.failed_count, 1);
+        assert_eq!(stats.unique_payers, 2);
+        assert_eq!(stats.total_fiat_volume, Money::new(2_000, 2));
+        assert_eq!(stats.total_icp_volume, 195_000_000);
+        assert_eq!(stats.last_payment_at, Some(30));
+    }
+
+    fn dynamic_qr(max_uses: Option<u32>, allowed_payer: Option<Principal>) -> QRCode {
+        QRCode {
+            id: "1234567890ABCDEF".to_string(),
+            user_id: Principal::from_slice(&[1]),
+            kind: QRKind::Dynamic,
+            fiat_amount: Money::new(1_000, 2),
+            fiat_currency: "USD".to_string(),
+            icp_amount: 100_000_000,
+            expire_time: 1_000,
+            created_at: 0,
+            status: QRStatus::Active,
+            description: None,
+            max_uses,
+            allowed_payer,
+            invoice_id: None,
+        }
+    }
+
+    #[test]
+    fn test_resolve_expiry_seconds() {
+        let policy = QRPolicy::default();
+        assert_eq!(resolve_expiry_seconds(&policy, None), Ok(30 * 60));
+        assert_eq!(resolve_expiry_seconds(&policy, Some(3600)), Ok(3600));
+        assert!(resolve_expiry_seconds(&policy, Some(10)).is_err());
+        assert!(resolve_expiry_seconds(&policy, Some(30 * 24 * 60 * 60)).is_err());
+    }
+
+    #[test]
+    fn test_validate_max_uses() {
+        let policy = QRPolicy::default();
+        assert!(validate_max_uses(&policy, None).is_ok());
+        assert!(validate_max_uses(&policy, Some(5)).is_ok());
+        assert!(validate_max_uses(&policy, Some(0)).is_err());
+        assert!(validate_max_uses(&policy, Some(policy.max_uses_limit + 1)).is_err());
+    }
+
+    #[test]
+    fn test_qr_usage_limits() {
+        let payer = Principal::from_slice(&[2]);
+
+        let single = dynamic_qr(None, None);
+        assert_eq!(remaining_uses(&single, 0), Some(1));
+        assert!(check_qr_payable(&single, payer, 0, 500).is_ok());
+        assert_eq!(
+            check_qr_payable(&single, payer, 1, 500),
+            Err("QR code has already been used".to_string())
+        );
+        assert!(check_qr_payable(&single, payer, 0, 1_001).is_err());
+
+        let multi = dynamic_qr(Some(3), None);
+        assert_eq!(remaining_uses(&multi, 2), Some(1));
+        assert!(check_qr_payable(&multi, payer, 2, 500).is_ok());
+        assert!(check_qr_payable(&multi, payer, 3, 500).is_err());
+
+        let mut unlimited = dynamic_qr(None, None);
+        unlimited.kind = QRKind::Static;
+        assert_eq!(remaining_uses(&unlimited, 1_000), None);
+        assert!(check_qr_payable(&unlimited, payer, 1_000, u64::MAX).is_ok());
+    }
+
+    #[test]
+    fn test_qr_allowed_payer() {
+        let payer = Principal::from_slice(&[2]);
+        let other = Principal::from_slice(&[3]);
+        let qr_code = dynamic_qr(None, Some(payer));
+
+        assert!(check_qr_payable(&qr_code, payer, 0, 500).is_ok());
+        assert!(check_qr_payable(&qr_code, other, 0, 500).is_err());
+    }
+
+    #[test]
+    fn test_qr_status_lifecycle() {
+        let payer = Principal::from_slice(&[2]);
+        let mut qr_code = dynamic_qr(Some(2), None);
+
+        assert_eq!(qr_status(&qr_code, 500), QRStatus::Active);
+        assert_eq!(qr_status(&qr_code, 1_001), QRStatus::Expired);
+        assert_eq!(status_after_payment(&qr_code, 1), QRStatus::Active);
+        assert_eq!(status_after_payment(&qr_code, 2), QRStatus::Exhausted);
+        assert_eq!(status_after_payment(&dynamic_qr(None, None), 1), QRStatus::Paid);
+
+        qr_code.status = QRStatus::Exhausted;
+        assert_eq!(
+            check_qr_payable(&qr_code, payer, 0, 500),
+            Err("QR code has reached its usage limit".to_string())
+        );
+    }
+
+    #[test]
+    fn test_cancel_qr_code() {
+        let owner = Principal::from_slice(&[1]);
+        let payer = Principal::from_slice(&[2]);
+        let mut qr_code = dynamic_qr(None, None);
+
+        assert!(cancel_qr_code(&mut qr_code, payer, 500).is_err());
+        assert!(cancel_qr_code(&mut qr_code, owner, 1_001).is_err());
+
+        assert!(cancel_qr_code(&mut qr_code, owner, 500).is_ok());
+        assert_eq!(qr_code.status, QRStatus::Cancelled);
+        assert_eq!(
+            check_qr_payable(&qr_code, payer, 0, 500),
+            Err("QR code has been cancelled".to_string())
+        );
+        assert!(cancel_qr_code(&mut qr_code, owner, 500).is_err());
+        // A held payment released after the cancellation doesn't reopen it
+        assert_eq!(status_after_payment(&qr_code, 1), QRStatus::Cancelled);
+    }
+
+    #[test]
+    fn test_usage_log_ids_within_one_message() {
+        let merchant = Principal::from_slice(&[1]);
+        let payer = Principal::from_slice(&[2]);
+        // Two wallet payments settled by the same ledger poll
+        let first = build_qr_usage_log("QR".to_string(), merchant, payer, "TX_ICRC_1".to_string(), QRUsageType::PaymentCompleted, 7);
+        let second = build_qr_usage_log("QR".to_string(), merchant, payer, "TX_ICRC_2".to_string(), QRUsageType::PaymentCompleted, 7);
+        assert_ne!(first.id, second.id);
+        assert!(first.id.starts_with("QRU_7_"));
+
+        let failed = build_qr_usage_log("QR".to_string(), merchant, payer, "TX_ICRC_1".to_string(), QRUsageType::PaymentFailed, 7);
+        assert_ne!(first.id, failed.id);
+    }
+
+    #[test]
+    fn test_held_payments_count_until_settled() {
+        let merchant = Principal::from_slice(&[1]);
+        let payer = Principal::from_slice(&[2]);
+        let log = |transaction_id: &str, usage_type: QRUsageType| {
+            build_qr_usage_log("QR".to_string(), merchant, payer, transaction_id.to_string(), usage_type, 7)
+        };
+
+        let mut logs = vec![
+            log("TX_A_COMPLETED_1", QRUsageType::PaymentCompleted),
+            log("TX_B_PROCESSING_2", QRUsageType::PaymentHeld),
+            log("TX_C_PROCESSING_3", QRUsageType::PaymentHeld),
+        ];
+        assert_eq!(count_qr_uses(&logs), 3);
+
+        // Releasing a hold logs it as completed once, not twice
+        logs.push(log("TX_B_COMPLETED_4", QRUsageType::PaymentCompleted));
+        assert_eq!(count_qr_uses(&logs), 3);
+
+        // A refunded hold gives its use back
+        logs.push(log("TX_C_REFUNDED_5", QRUsageType::PaymentRefunded));
+        assert_eq!(count_qr_uses(&logs), 2);
+    }
+
+    #[test]
+    fn test_rejected_review_hold_is_never_paid() {
+        let payer = Principal::from_slice(&[2]);
+        let other = Principal::from_slice(&[3]);
+        let qr_code = dynamic_qr(Some(1), None);
+        let log = |transaction_id: &str, usage_type: QRUsageType| {
+            build_qr_usage_log(qr_code.id.clone(), qr_code.user_id, payer, transaction_id.to_string(), usage_type, 7)
+        };
+
+        // Held for review: the single use is taken but nothing is paid yet
+        let mut logs = vec![log("TX_A_PROCESSING_1", QRUsageType::PaymentHeld)];
+        assert!(check_qr_payable(&qr_code, other, count_qr_uses(&logs), 500).is_err());
+        assert!(!logs.iter().any(|log| log.usage_type == QRUsageType::PaymentCompleted));
+
+        // Rejected: the payment is refunded and the code can be paid again
+        logs.push(log("TX_A_REFUNDED_2", QRUsageType::PaymentRefunded));
+        assert_eq!(count_qr_uses(&logs), 0);
+        assert_eq!(status_after_payment(&qr_code, count_qr_uses(&logs)), QRStatus::Active);
+        assert!(check_qr_payable(&qr_code, other, count_qr_uses(&logs), 500).is_ok());
+    }
 }
@@ -0,0 +1,384 @@
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use candid::{CandidType, Deserialize};
+use qrcodegen::{QrCode, QrCodeEcc};
+use serde::Serialize;
+
+const DEFAULT_SIZE_PX: u32 = 512;
+const MAX_SIZE_PX: u32 = 2048;
+// Quiet zone the QR spec asks for, in modules
+const DEFAULT_MARGIN: u32 = 4;
+const MAX_MARGIN: u32 = 16;
+const DEFAULT_LOGO_PERCENT: u8 = 20;
+// Beyond this the logo hides more than error correction level H can recover
+const MAX_LOGO_PERCENT: u8 = 30;
+const MAX_LOGO_BYTES: usize = 256 * 1024;
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum QRImageFormat {
+    Svg,
+    Png,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum QRErrorCorrection {
+    Low,
+    Medium,
+    Quartile,
+    High,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct QRLogo {
+    // PNG only; embedded as-is in SVG output, composited into PNG output
+    pub png_bytes: Vec<u8>,
+    // Logo width as a percentage of the symbol width
+    pub size_percent: Option<u8>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct QRRenderOptions {
+    pub format: QRImageFormat,
+    // Defaults to Medium, or High when a logo is overlaid
+    pub error_correction: Option<QRErrorCorrection>,
+    pub size_px: Option<u32>,
+    pub margin_modules: Option<u32>,
+    pub logo: Option<QRLogo>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct QRImage {
+    pub content_type: String,
+    pub bytes: Vec<u8>,
+    pub width_px: u32,
+}
+
+// Module layout shared by both output formats
+struct Layout {
+    qr: QrCode,
+    margin: u32,
+    scale: u32,
+    logo_modules: u32,
+}
+
+impl Layout {
+    fn total_modules(&self) -> u32 {
+        self.qr.size() as u32 + 2 * self.margin
+    }
+
+    fn width_px(&self) -> u32 {
+        self.total_modules() * self.scale
+    }
+
+    // Top-left module and width of the centred logo box
+    fn logo_box(&self) -> (u32, u32) {
+        ((self.total_modules() - self.logo_modules) / 2, self.logo_modules)
+    }
+
+    fn is_dark(&self, x: u32, y: u32) -> bool {
+        let (x, y) = (x as i32 - self.margin as i32, y as i32 - self.margin as i32);
+        self.qr.get_module(x, y)
+    }
+}
+
+fn to_ecc(level: QRErrorCorrection) -> QrCodeEcc {
+    match level {
+        QRErrorCorrection::Low => QrCodeEcc::Low,
+        QRErrorCorrection::Medium => QrCodeEcc::Medium,
+        QRErrorCorrection::Quartile => QrCodeEcc::Quartile,
+        QRErrorCorrection::High => QrCodeEcc::High,
+    }
+}
+
+fn layout(data: &str, options: &QRRenderOptions) -> Result<Layout, String> {
+    if data.is_empty() {
+        return Err("Nothing to encode".to_string());
+    }
+
+    let level = match (options.error_correction, &options.logo) {
+        (Some(level), None) => level,
+        (Some(QRErrorCorrection::High), Some(_)) | (None, Some(_)) => QRErrorCorrection::High,
+        (Some(_), Some(_)) => {
+            return Err("A logo overlay requires High error correction".to_string());
+        }
+        (None, None) => QRErrorCorrection::Medium,
+    };
+
+    let margin = options.margin_modules.unwrap_or(DEFAULT_MARGIN);
+    if margin > MAX_MARGIN {
+        return Err(format!("Margin cannot exceed {} modules", MAX_MARGIN));
+    }
+
+    let size_px = options.size_px.unwrap_or(DEFAULT_SIZE_PX);
+    if size_px == 0 || size_px > MAX_SIZE_PX {
+        return Err(format!("Size must be between 1 and {} pixels", MAX_SIZE_PX));
+    }
+
+    let qr = QrCode::encode_text(data, to_ecc(level))
+        .map_err(|_| "Data is too long for a QR code".to_string())?;
+
+    let logo_modules = match &options.logo {
+        Some(logo) => {
+            let percent = logo.size_percent.unwrap_or(DEFAULT_LOGO_PERCENT);
+            if percent == 0 || percent > MAX_LOGO_PERCENT {
+                return Err(format!("Logo size must be 1-{}% of the code", MAX_LOGO_PERCENT));
+            }
+            // Keep the box centred on the module grid
+            let modules = (qr.size() as u32 * percent as u32 / 100).max(1);
+            modules + (qr.size() as u32 - modules) % 2
+        }
+        None => 0,
+    };
+
+    // Whole pixels per module so edges stay sharp; the image may come out
+    // slightly smaller than requested
+    let total_modules = qr.size() as u32 + 2 * margin;
+    let scale = (size_px / total_modules).max(1);
+
+    Ok(Layout { qr, margin, scale, logo_modules })
+}
+
+fn validate_logo(logo: &QRLogo) -> Result<(), String> {
+    if logo.png_bytes.len() > MAX_LOGO_BYTES {
+        return Err(format!("Logo cannot exceed {} KB", MAX_LOGO_BYTES / 1024));
+    }
+    if !logo.png_bytes.starts_with(&PNG_SIGNATURE) {
+        return Err("Logo must be a PNG image".to_string());
+    }
+    Ok(())
+}
+
+pub fn render_qr(data: &str, options: &QRRenderOptions) -> Result<QRImage, String> {
+    if let Some(logo) = &options.logo {
+        validate_logo(logo)?;
+    }
+
+    let layout = layout(data, options)?;
+    match options.format {
+        QRImageFormat::Svg => Ok(QRImage {
+            content_type: "image/svg+xml".to_string(),
+            bytes: render_svg(&layout, options.logo.as_ref()).into_bytes(),
+            width_px: layout.width_px(),
+        }),
+        QRImageFormat::Png => Ok(QRImage {
+            content_type: "image/png".to_string(),
+            bytes: render_png(&layout, options.logo.as_ref())?,
+            width_px: layout.width_px(),
+        }),
+    }
+}
+
+fn render_svg(layout: &Layout, logo: Option<&QRLogo>) -> String {
+    let total = layout.total_modules();
+    let width = layout.width_px();
+
+    let mut path = String::new();
+    for y in 0..total {
+        for x in 0..total {
+            if layout.is_dark(x, y) {
+                path.push_str(&format!("M{},{}h1v1h-1z", x, y));
+            }
+        }
+    }
+
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" version=\"1.1\" width=\"{w}\" height=\"{w}\" \
+         viewBox=\"0 0 {t} {t}\" shape-rendering=\"crispEdges\">\
+         <rect width=\"100%\" height=\"100%\" fill=\"#FFFFFF\"/>\
+         <path d=\"{p}\" fill=\"#000000\"/>",
+        w = width,
+        t = total,
+        p = path
+    );
+
+    if let Some(logo) = logo {
+        let (start, size) = layout.logo_box();
+        svg.push_str(&format!(
+            "<rect x=\"{s}\" y=\"{s}\" width=\"{n}\" height=\"{n}\" fill=\"#FFFFFF\"/>\
+             <image x=\"{s}\" y=\"{s}\" width=\"{n}\" height=\"{n}\" \
+             href=\"data:image/png;base64,{data}\"/>",
+            s = start,
+            n = size,
+            data = STANDARD.encode(&logo.png_bytes)
+        ));
+    }
+
+    svg.push_str("</svg>");
+    svg
+}
+
+// RGBA pixels of a PNG logo, whatever its colour type
+fn decode_logo(png_bytes: &[u8]) -> Result<(u32, u32, Vec<u8>), String> {
+    let mut decoder = png::Decoder::new(png_bytes);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().map_err(|e| format!("Invalid logo: {}", e))?;
+
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer).map_err(|e| format!("Invalid logo: {}", e))?;
+    let pixels = &buffer[..info.buffer_size()];
+
+    let rgba = match info.color_type {
+        png::ColorType::Rgba => pixels.to_vec(),
+        png::ColorType::Rgb => pixels.chunks(3).flat_map(|p| [p[0], p[1], p[2], 255]).collect(),
+        png::ColorType::GrayscaleAlpha => pixels.chunks(2).flat_map(|p| [p[0], p[0], p[0], p[1]]).collect(),
+        png::ColorType::Grayscale => pixels.iter().flat_map(|&g| [g, g, g, 255]).collect(),
+        png::ColorType::Indexed => return Err("Unsupported logo colour type".to_string()),
+    };
+
+    Ok((info.width, info.height, rgba))
+}
+
+fn blend(background: u8, foreground: u8, alpha: u8) -> u8 {
+    ((foreground as u32 * alpha as u32 + background as u32 * (255 - alpha as u32)) / 255) as u8
+}
+
+fn render_png(layout: &Layout, logo: Option<&QRLogo>) -> Result<Vec<u8>, String> {
+    let width = layout.width_px();
+    let mut rgba = vec![255u8; (width * width * 4) as usize];
+
+    for y in 0..width {
+        for x in 0..width {
+            if layout.is_dark(x / layout.scale, y / layout.scale) {
+                let offset = ((y * width + x) * 4) as usize;
+                rgba[offset..offset + 3].copy_from_slice(&[0, 0, 0]);
+            }
+        }
+    }
+
+    if let Some(logo) = logo {
+        let (logo_width, logo_height, logo_pixels) = decode_logo(&logo.png_bytes)?;
+        let (start, size) = layout.logo_box();
+        let (start, size) = (start * layout.scale, size * layout.scale);
+
+        // Clear the box, then scale the logo into it (nearest neighbour)
+        for y in 0..size {
+            for x in 0..size {
+                let offset = (((start + y) * width + start + x) * 4) as usize;
+                let src_x = x * logo_width / size;
+                let src_y = y * logo_height / size;
+                let src = ((src_y * logo_width + src_x) * 4) as usize;
+                let alpha = logo_pixels[src + 3];
+                for channel in 0..3 {
+                    rgba[offset + channel] = blend(255, logo_pixels[src + channel], alpha);
+                }
+            }
+        }
+    }
+
+    let mut bytes = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut bytes, width, width);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().map_err(|e| format!("PNG encoding failed: {}", e))?;
+        writer.write_image_data(&rgba).map_err(|e| format!("PNG encoding failed: {}", e))?;
+    }
+
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn svg_options() -> QRRenderOptions {
+        QRRenderOptions {
+            format: QRImageFormat::Svg,
+            error_correction: None,
+            size_px: None,
+            margin_modules: None,
+            logo: None,
+        }
+    }
+
+    fn decode_png(bytes: &[u8]) -> (u32, Vec<u8>) {
+        let (width, _, pixels) = decode_logo(bytes).unwrap();
+        (width, pixels)
+    }
+
+    fn pixel(pixels: &[u8], width: u32, x: u32, y: u32) -> [u8; 3] {
+        let offset = ((y * width + x) * 4) as usize;
+        [pixels[offset], pixels[offset + 1], pixels[offset + 2]]
+    }
+
+    fn red_logo() -> Vec<u8> {
+        let mut bytes = Vec::new();
+        {
+            let mut encoder = png::Encoder::new(&mut bytes, 2, 2);
+            encoder.set_color(png::ColorType::Rgb);
+            encoder.set_depth(png::BitDepth::Eight);
+            let mut writer = encoder.write_header().unwrap();
+            writer.write_image_data(&[255, 0, 0].repeat(4)).unwrap();
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_render_svg() {
+        let image = render_qr("https://example.com/pay/1234567890ABCDEF", &svg_options()).unwrap();
+        let svg = String::from_utf8(image.bytes).unwrap();
+
+        assert_eq!(image.content_type, "image/svg+xml");
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.ends_with("</svg>"));
+        // Top-left finder pattern starts right after the quiet zone
+        assert!(svg.contains("M4,4h1v1h-1z"));
+        assert!(!svg.contains("<image"));
+    }
+
+    #[test]
+    fn test_render_png() {
+        let options = QRRenderOptions {
+            format: QRImageFormat::Png,
+            size_px: Some(300),
+            ..svg_options()
+        };
+        let data = "https://example.com/pay/1234567890ABCDEF";
+        let image = render_qr(data, &options).unwrap();
+        assert_eq!(image.content_type, "image/png");
+        assert!(image.width_px <= 300);
+
+        let (width, pixels) = decode_png(&image.bytes);
+        assert_eq!(width, image.width_px);
+
+        let scale = layout(data, &options).unwrap().scale;
+        assert_eq!(pixel(&pixels, width, 0, 0), [255, 255, 255]);
+        assert_eq!(pixel(&pixels, width, 4 * scale, 4 * scale), [0, 0, 0]);
+    }
+
+    #[test]
+    fn test_render_png_with_logo() {
+        let options = QRRenderOptions {
+            format: QRImageFormat::Png,
+            logo: Some(QRLogo { png_bytes: red_logo(), size_percent: None }),
+            ..svg_options()
+        };
+        let image = render_qr("https://example.com/pay/1234567890ABCDEF", &options).unwrap();
+        let (width, pixels) = decode_png(&image.bytes);
+
+        assert_eq!(pixel(&pixels, width, width / 2, width / 2), [255, 0, 0]);
+    }
+
+    #[test]
+    fn test_render_options_validation() {
+        let data = "https://example.com/pay/1234567890ABCDEF";
+
+        let too_big = QRRenderOptions { size_px: Some(MAX_SIZE_PX + 1), ..svg_options() };
+        assert!(render_qr(data, &too_big).is_err());
+
+        let not_png = QRRenderOptions {
+            logo: Some(QRLogo { png_bytes: b"GIF89a".to_vec(), size_percent: None }),
+            ..svg_options()
+        };
+        assert!(render_qr(data, &not_png).is_err());
+
+        let low_with_logo = QRRenderOptions {
+            error_correction: Some(QRErrorCorrection::Low),
+            logo: Some(QRLogo { png_bytes: red_logo(), size_percent: None }),
+            ..svg_options()
+        };
+        assert!(render_qr(data, &low_with_logo).is_err());
+
+        assert!(render_qr("", &svg_options()).is_err());
+    }
+}
@@ -0,0 +1,141 @@
+use crate::types::*;
+
+const NANOS_PER_SECOND: u64 = 1_000_000_000;
+
+// Resolution at which samples are stored; coarser buckets are aggregated on read
+pub const BASE_BUCKET_SECONDS: u64 = 5 * 60;
+
+// Upper bound on candles returned by one history query
+pub const MAX_HISTORY_POINTS: usize = 2000;
+
+pub fn bucket_seconds(bucket: RateBucket) -> u64 {
+    match bucket {
+        RateBucket::FiveMinutes => BASE_BUCKET_SECONDS,
+        RateBucket::Hour => 60 * 60,
+        RateBucket::Day => 24 * 60 * 60,
+        RateBucket::Week => 7 * 24 * 60 * 60,
+    }
+}
+
+pub fn bucket_start(timestamp: u64, seconds: u64) -> u64 {
+    let width = seconds * NANOS_PER_SECOND;
+    timestamp - (timestamp % width)
+}
+
+// Zero-padded so keys for one currency sort by time
+pub fn rate_history_key(currency: &str, bucket_start: u64) -> String {
+    format!("{}:{:020}", currency.to_uppercase(), bucket_start)
+}
+
+pub fn new_candle(currency: &str, rate: f64, timestamp: u64) -> RateCandle {
+    RateCandle {
+        currency: currency.to_uppercase(),
+        bucket_start: bucket_start(timestamp, BASE_BUCKET_SECONDS),
+        bucket_seconds: BASE_BUCKET_SECONDS,
+        open: rate,
+        high: rate,
+        low: rate,
+        close: rate,
+        sample_count: 1,
+        last_updated: timestamp,
+    }
+}
+
+pub fn apply_sample(candle: &mut RateCandle, rate: f64, timestamp: u64) {
+    candle.high = candle.high.max(rate);
+    candle.low = candle.low.min(rate);
+    if timestamp >= candle.last_updated {
+        candle.close = rate;
+        candle.last_updated = timestamp;
+    }
+    candle.sample_count = candle.sample_count.saturating_add(1);
+}
+
+// Merge time-ordered base candles into buckets of `seconds` width
+pub fn aggregate_candles(candles: &[RateCandle], seconds: u64) -> Vec<RateCandle> {
+    let mut aggregated: Vec<RateCandle> = Vec::new();
+
+    for candle in candles {
+        let start = bucket_start(candle.bucket_start, seconds);
+
+        match aggregated.last_mut() {
+            Some(current) if current.bucket_start == start => {
+                current.high = current.high.max(candle.high);
+                current.low = current.low.min(candle.low);
+                current.close = candle.close;
+                current.last_updated = candle.last_updated;
+                current.sample_count = current.sample_count.saturating_add(candle.sample_count);
+            }
+            _ => aggregated.push(RateCandle {
+                bucket_start: start,
+                bucket_seconds: seconds,
+                ..candle.clone()
+            }),
+        }
+    }
+
+    aggregated
+}
+
+pub fn candle_to_exchange_rate(candle: &RateCandle) -> ExchangeRate {
+    ExchangeRate {
+        currency: candle.currency.clone(),
+        rate: candle.close,
+        timestamp: candle.last_updated,
+        source: "rate_history".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINUTE: u64 = 60 * NANOS_PER_SECOND;
+
+    #[test]
+    fn test_bucket_start() {
+        assert_eq!(bucket_start(0, BASE_BUCKET_SECONDS), 0);
+        assert_eq!(bucket_start(4 * MINUTE, BASE_BUCKET_SECONDS), 0);
+        assert_eq!(bucket_start(5 * MINUTE, BASE_BUCKET_SECONDS), 5 * MINUTE);
+        assert_eq!(bucket_start(61 * MINUTE, 3600), 60 * MINUTE);
+    }
+
+    #[test]
+    fn test_rate_history_key_ordering() {
+        let a = rate_history_key("usd", 9 * MINUTE);
+        let b = rate_history_key("USD", 10 * MINUTE);
+        assert!(a < b);
+        assert!(a.starts_with("USD:"));
+    }
+
+    #[test]
+    fn test_apply_sample() {
+        let mut candle = new_candle("USD", 5.0, MINUTE);
+        apply_sample(&mut candle, 6.0, 2 * MINUTE);
+        apply_sample(&mut candle, 4.5, 3 * MINUTE);
+
+        assert_eq!(candle.open, 5.0);
+        assert_eq!(candle.high, 6.0);
+        assert_eq!(candle.low, 4.5);
+        assert_eq!(candle.close, 4.5);
+        assert_eq!(candle.sample_count, 3);
+    }
+
+    #[test]
+    fn test_aggregate_candles() {
+        let candles = vec![
+            new_candle("USD", 5.0, MINUTE),
+            new_candle("USD", 7.0, 6 * MINUTE),
+            new_candle("USD", 6.0, 61 * MINUTE),
+        ];
+
+        let hourly = aggregate_candles(&candles, 3600);
+        assert_eq!(hourly.len(), 2);
+        assert_eq!(hourly[0].open, 5.0);
+        assert_eq!(hourly[0].high, 7.0);
+        assert_eq!(hourly[0].close, 7.0);
+        assert_eq!(hourly[0].sample_count, 2);
+        assert_eq!(hourly[1].bucket_start, 60 * MINUTE);
+        assert_eq!(hourly[1].close, 6.0);
+    }
+}
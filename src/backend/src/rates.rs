@@ -1,13 +1,12 @@
 use crate::types::*;
+use crate::money::{cross_convert, currency_minor_units, fiat_to_e8s, Money, RoundingMode};
 use ic_cdk::api::management_canister::http_request::{
     http_request, CanisterHttpRequestArgument, HttpHeader, HttpMethod, HttpResponse, TransformArgs,
 };
 use serde_json;
 
-// Rate limiting configuration
-const MAX_RETRIES: u32 = 3;
+// Retry count and cache duration come from CanisterConfig
 const RETRY_DELAY_SECONDS: u64 = 2;
-const RATE_CACHE_DURATION_SECONDS: u64 = 300; 
 
 // Enhanced exchange rate fetching with cache-only fallback
 pub async fn fetch_exchange_rate_with_retry(currency: String) -> Result<ExchangeRate, String> {
@@ -22,7 +21,8 @@ pub async fn fetch_exchange_rate_with_retry_internal(currency: String, cached_ra
         return Err(format!("Unsupported currency: {}", currency_upper));
     }
     
-    for attempt in 1..=MAX_RETRIES {
+    let max_retries = crate::current_config().rate_fetch_max_retries;
+    for attempt in 1..=max_retries {
         ic_cdk::println!("🔄 Attempt {} to fetch {} rate", attempt, currency_upper);
         
         match fetch_live_exchange_rate(currency_upper.clone()).await {
@@ -53,7 +53,7 @@ pub async fn fetch_exchange_rate_with_retry_internal(currency: String, cached_ra
                 }
                 
                 
-                if attempt < MAX_RETRIES {
+                if attempt < max_retries {
                     let delay = RETRY_DELAY_SECONDS * (attempt as u64);
                     ic_cdk::println!("⏳ Waiting {} seconds before retry...", delay);
                     
@@ -82,7 +82,7 @@ pub async fn fetch_exchange_rate_with_retry_internal(currency: String, cached_ra
         return Ok(rate_with_disclaimer);
     }
     
-    Err(format!("Failed to fetch {} rate after {} attempts and no cached rate available", currency_upper, MAX_RETRIES))
+    Err(format!("Failed to fetch {} rate after {} attempts and no cached rate available", currency_upper, max_retries))
 }
 
 // Get cached rate if it's still valid
@@ -157,6 +157,8 @@ pub async fn fetch_live_exchange_rate(currency: String) -> Result<ExchangeRate,
                         source: "coingecko".to_string(),
                     };
 
+                    crate::record_rate_sample(&exchange_rate);
+
                     Ok(exchange_rate)
                 }
                 429 => {
@@ -180,11 +182,11 @@ pub async fn fetch_live_exchange_rate(currency: String) -> Result<ExchangeRate,
     }
 }
 
-// Check if cached rate is very recent (within 5 minutes for immediate use)
+// Check if cached rate is recent enough for immediate use
 fn is_cache_very_recent(exchange_rate: &ExchangeRate) -> bool {
     let current_time = ic_cdk::api::time();
     let rate_age = current_time.saturating_sub(exchange_rate.timestamp);
-    let max_recent_age = RATE_CACHE_DURATION_SECONDS * 1_000_000_000; 
+    let max_recent_age = crate::current_config().rate_cache_seconds * 1_000_000_000; 
     
     rate_age < max_recent_age
 }
@@ -200,7 +202,7 @@ pub fn get_cache_age_minutes(exchange_rate: &ExchangeRate) -> u64 {
 pub fn is_rate_cache_valid(exchange_rate: &ExchangeRate) -> bool {
     let current_time = ic_cdk::api::time();
     let rate_age = current_time.saturating_sub(exchange_rate.timestamp);
-    let max_age = RATE_CACHE_DURATION_SECONDS * 1_000_000_000; 
+    let max_age = crate::current_config().rate_cache_seconds * 1_000_000_000; 
     
     rate_age < max_age
 }
@@ -232,8 +234,8 @@ pub fn transform_response(raw: TransformArgs) -> HttpResponse {
     }
 }
 
-pub fn calculate_icp_amount(fiat_amount: f64, exchange_rate: f64) -> Result<u64, String> {
-    if fiat_amount <= 0.0 {
+pub fn calculate_icp_amount(fiat_amount: &Money, exchange_rate: f64, rounding: RoundingMode) -> Result<u64, String> {
+    if fiat_amount.is_zero() {
         return Err("Fiat amount must be greater than 0".to_string());
     }
     
@@ -241,8 +243,7 @@ pub fn calculate_icp_amount(fiat_amount: f64, exchange_rate: f64) -> Result<u64,
         return Err("Exchange rate must be greater than 0".to_string());
     }
 
-    let icp_amount = fiat_amount / exchange_rate;
-    let e8s_amount = (icp_amount * 100_000_000.0) as u64;
+    let e8s_amount = fiat_to_e8s(fiat_amount, exchange_rate, rounding)?;
     
     if e8s_amount == 0 {
         return Err("Amount too small, results in 0 e8s".to_string());
@@ -252,36 +253,33 @@ pub fn calculate_icp_amount(fiat_amount: f64, exchange_rate: f64) -> Result<u64,
 }
 
 pub fn get_supported_currencies() -> Vec<String> {
-    vec![
-        "usd".to_string(),
-        "eur".to_string(),
-        "gbp".to_string(),
-        "jpy".to_string(),
-        "idr".to_string(),
-        "sgd".to_string(),
-        "myr".to_string(),
-        "php".to_string(),
-        "thb".to_string(),
-        "vnd".to_string(),
-    ]
+    crate::get_enabled_currencies()
+        .iter()
+        .map(|info| info.code.to_lowercase())
+        .collect()
 }
 
 pub fn is_supported_currency(currency: &str) -> bool {
-    get_supported_currencies().contains(&currency.to_lowercase())
+    crate::get_currency_info(currency).is_some_and(|info| info.enabled)
 }
 
-pub fn format_currency_amount(amount: f64, currency: &str) -> String {
-    match currency.to_uppercase().as_str() {
-        "USD" | "EUR" | "GBP" | "SGD" => format!("{:.2}", amount),
-        "JPY" => format!("{:.0}", amount),
-        "IDR" | "VND" => format!("{:.0}", amount),
-        _ => format!("{:.2}", amount),
-    }
-}
+// Convert between two fiat currencies through their ICP rates
+pub fn convert_amount(
+    amount: &Money,
+    from_rate: &ExchangeRate,
+    to_rate: &ExchangeRate,
+) -> Result<CurrencyConversion, String> {
+    let to_scale = currency_minor_units(&to_rate.currency);
+    let converted_amount = cross_convert(amount, from_rate.rate, to_rate.rate, to_scale, RoundingMode::HalfUp)?;
 
-// Function to get supported currencies list for API
-pub fn get_supported_currencies_list() -> Vec<String> {
-    get_supported_currencies()
+    Ok(CurrencyConversion {
+        amount: *amount,
+        from_currency: from_rate.currency.clone(),
+        converted_amount,
+        to_currency: to_rate.currency.clone(),
+        cross_rate: to_rate.rate / from_rate.rate,
+        rates_used: vec![from_rate.clone(), to_rate.clone()],
+    })
 }
 
 #[cfg(test)]
@@ -290,6 +288,7 @@ mod tests {
 
     #[test]
     fn test_currency_support() {
+        crate::seed_currency_registry();
         assert!(is_supported_currency("USD"));
         assert!(is_supported_currency("usd"));
         assert!(is_supported_currency("EUR"));
@@ -298,16 +297,11 @@ mod tests {
 
     #[test]
     fn test_icp_amount_calculation() {
-        let result = calculate_icp_amount(100.0, 5.0);
+        let amount = Money::parse("100", "USD").unwrap();
+        let result = calculate_icp_amount(&amount, 5.0, RoundingMode::Up);
         assert!(result.is_ok());
         assert_eq!(result.unwrap(), 2_000_000_000); 
-    }
-
-    #[test]
-    fn test_currency_formatting() {
-        assert_eq!(format_currency_amount(100.5, "USD"), "100.50");
-        assert_eq!(format_currency_amount(100.5, "JPY"), "101");
-        assert_eq!(format_currency_amount(100000.0, "IDR"), "100000");
+        assert!(calculate_icp_amount(&Money::zero("USD"), 5.0, RoundingMode::Up).is_err());
     }
 
     #[test]
@@ -0,0 +1,230 @@
+use crate::formatting::{format_icp, DEFAULT_LANGUAGE};
+use crate::types::*;
+use candid::Principal;
+
+const ICP: u64 = 100_000_000;
+const DAY_NANOS: u64 = 24 * 60 * 60 * 1_000_000_000;
+const MAX_REVIEW_NOTE_LEN: usize = 1000;
+
+// Scores at or above these are held for review or refused
+pub const REVIEW_SCORE: u32 = 40;
+pub const DENY_SCORE: u32 = 80;
+
+// Rule thresholds
+const NEW_ACCOUNT_BURST: u32 = 3;
+const NEW_ACCOUNT_LARGE_E8S: u64 = 10 * ICP;
+const SAME_PAIR_BURST: u32 = 5;
+const MIN_HISTORY: usize = 3;
+const FIRST_TIME_LARGE_E8S: u64 = 100 * ICP;
+
+// What the rules see of one payment or top-up. Counts cover the last hour,
+// `history` the user's previous amounts for the same action.
+#[derive(Debug, Default)]
+pub struct RiskContext {
+    pub action: RiskAction,
+    pub amount: u64,
+    pub account_age_nanos: u64,
+    pub recent_count: u32,
+    pub history: Vec<u64>,
+    // Payments only: completed payments payer -> payee and payee -> payer
+    pub pair_count: u32,
+    pub reverse_count: u32,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct RiskEvaluation {
+    pub score: u32,
+    pub decision: RiskDecision,
+    pub reasons: Vec<String>,
+}
+
+fn action_name(action: RiskAction) -> &'static str {
+    match action {
+        RiskAction::Payment => "payment",
+        RiskAction::CardTopup => "card top-up",
+        RiskAction::QrisTopup => "QRIS top-up",
+    }
+}
+
+pub fn evaluate_risk(ctx: &RiskContext) -> RiskEvaluation {
+    let name = action_name(ctx.action);
+    let mut score = 0;
+    let mut reasons = Vec::new();
+    let mut flag = |points: u32, reason: String| {
+        score += points;
+        reasons.push(reason);
+    };
+
+    // New-account velocity
+    if ctx.account_age_nanos < DAY_NANOS {
+        if ctx.recent_count >= NEW_ACCOUNT_BURST {
+            flag(30, format!("Account is less than a day old and made {} {}s in the last hour", ctx.recent_count, name));
+        }
+        if ctx.amount > NEW_ACCOUNT_LARGE_E8S {
+            flag(20, format!("Large {} from an account less than a day old", name));
+        }
+    }
+
+    // QR payment loops between the same two principals
+    if ctx.pair_count >= SAME_PAIR_BURST {
+        flag(30, format!("{} payments to the same recipient in the last hour", ctx.pair_count));
+    }
+    if ctx.pair_count > 0 && ctx.reverse_count > 0 {
+        flag(40, "Money is moving back and forth between the same two accounts".to_string());
+    }
+
+    // Amount against the user's own history
+    if ctx.history.len() >= MIN_HISTORY {
+        let average = ctx.history.iter().sum::<u64>() / ctx.history.len() as u64;
+        let multiple = ctx.amount / average.max(1);
+        if multiple >= 10 {
+            flag(45, format!("Amount is {}x the usual {} of {}", multiple, name, format_icp(average, DEFAULT_LANGUAGE)));
+        } else if multiple >= 5 {
+            flag(25, format!("Amount is {}x the usual {} of {}", multiple, name, format_icp(average, DEFAULT_LANGUAGE)));
+        }
+    } else if ctx.history.is_empty() && ctx.amount > FIRST_TIME_LARGE_E8S {
+        flag(20, format!("First {} is unusually large", name));
+    }
+
+    let decision = if score >= DENY_SCORE {
+        RiskDecision::Deny
+    } else if score >= REVIEW_SCORE {
+        RiskDecision::Review
+    } else {
+        RiskDecision::Allow
+    };
+    RiskEvaluation { score, decision, reasons }
+}
+
+// `reference_id` is the held payment or top-up; only held items wait for
+// an admin
+pub fn build_assessment(
+    id: String,
+    user_id: Principal,
+    counterparty: Option<Principal>,
+    ctx: &RiskContext,
+    evaluation: RiskEvaluation,
+    reference_id: Option<String>,
+    now: u64,
+) -> RiskAssessment {
+    let held = evaluation.decision == RiskDecision::Review && reference_id.is_some();
+    RiskAssessment {
+        id,
+        user_id,
+        action: ctx.action,
+        counterparty,
+        amount: ctx.amount,
+        score: evaluation.score,
+        decision: evaluation.decision,
+        reasons: evaluation.reasons,
+        reference_id,
+        review_status: held.then_some(RiskReviewStatus::Pending),
+        reviewed_by: None,
+        review_note: None,
+        created_at: now,
+        reviewed_at: None,
+    }
+}
+
+pub fn is_awaiting_review(assessment: &RiskAssessment) -> bool {
+    assessment.review_status == Some(RiskReviewStatus::Pending)
+}
+
+pub fn resolve_review(
+    assessment: &mut RiskAssessment,
+    approve: bool,
+    admin: Principal,
+    note: Option<String>,
+    now: u64,
+) -> Result<(), String> {
+    if !is_awaiting_review(assessment) {
+        return Err("This item is not waiting for review".to_string());
+    }
+    let note = note.map(|note| note.trim().to_string()).filter(|note| !note.is_empty());
+    if note.as_ref().is_some_and(|note| note.chars().count() > MAX_REVIEW_NOTE_LEN) {
+        return Err(format!("Notes must be at most {} characters", MAX_REVIEW_NOTE_LEN));
+    }
+    assessment.review_status = Some(if approve { RiskReviewStatus::Approved } else { RiskReviewStatus::Rejected });
+    assessment.reviewed_by = Some(admin);
+    assessment.review_note = note;
+    assessment.reviewed_at = Some(now);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn established(amount: u64) -> RiskContext {
+        RiskContext {
+            action: RiskAction::Payment,
+            amount,
+            account_age_nanos: 90 * DAY_NANOS,
+            history: vec![ICP, ICP, 2 * ICP],
+            ..RiskContext::default()
+        }
+    }
+
+    #[test]
+    fn test_ordinary_payment_is_allowed() {
+        let evaluation = evaluate_risk(&established(2 * ICP));
+        assert_eq!(evaluation, RiskEvaluation { score: 0, decision: RiskDecision::Allow, reasons: vec![] });
+    }
+
+    #[test]
+    fn test_unusual_amount() {
+        // Average is 1.33 ICP
+        assert_eq!(evaluate_risk(&established(7 * ICP)).score, 25);
+        let evaluation = evaluate_risk(&established(14 * ICP));
+        assert_eq!(evaluation.decision, RiskDecision::Review);
+        assert!(evaluation.reasons[0].starts_with("Amount is 10x"));
+    }
+
+    #[test]
+    fn test_new_account_velocity() {
+        let ctx = RiskContext {
+            action: RiskAction::CardTopup,
+            amount: 15 * ICP,
+            account_age_nanos: DAY_NANOS / 2,
+            recent_count: 3,
+            ..RiskContext::default()
+        };
+        let evaluation = evaluate_risk(&ctx);
+        assert_eq!(evaluation.score, 50);
+        assert_eq!(evaluation.decision, RiskDecision::Review);
+        assert_eq!(evaluation.reasons.len(), 2);
+    }
+
+    #[test]
+    fn test_payment_loops() {
+        let looping = RiskContext { pair_count: 5, reverse_count: 2, ..established(ICP) };
+        assert_eq!(evaluate_risk(&looping).decision, RiskDecision::Review);
+
+        let new_account = RiskContext { account_age_nanos: 0, amount: 20 * ICP, history: vec![], ..looping };
+        // Loops (70) + large from a new account (20)
+        assert_eq!(evaluate_risk(&new_account).decision, RiskDecision::Deny);
+    }
+
+    #[test]
+    fn test_review_queue() {
+        let ctx = established(14 * ICP);
+        let admin = Principal::from_slice(&[9]);
+        let mut held = build_assessment(
+            "RISK_1".to_string(),
+            Principal::from_slice(&[1]),
+            None,
+            &ctx,
+            evaluate_risk(&ctx),
+            Some("TX_1_PROCESSING_1".to_string()),
+            10,
+        );
+        assert!(is_awaiting_review(&held));
+        resolve_review(&mut held, false, admin, Some("Stolen card".to_string()), 20).unwrap();
+        assert_eq!(held.review_status, Some(RiskReviewStatus::Rejected));
+        assert!(resolve_review(&mut held, true, admin, None, 30).is_err());
+
+        // Nothing held, nothing to review
+        let logged = build_assessment("RISK_2".to_string(), Principal::from_slice(&[1]), None, &ctx, evaluate_risk(&ctx), None, 10);
+        assert!(!is_awaiting_review(&logged));
+    }
+}
@@ -0,0 +1,236 @@
+use crate::types::*;
+use crate::money::Money;
+use crate::qr::build_qr_usage_log;
+use candid::Principal;
+
+// Caps contributions per bill so settlement loops stay small
+pub const MAX_SPLIT_CONTRIBUTIONS: usize = 50;
+
+pub fn validate_split_mode(mode: &SplitMode, target: &Money, merchant: Principal) -> Result<(), String> {
+    match mode {
+        SplitMode::Equal { participants } => {
+            if *participants < 2 || *participants as usize > MAX_SPLIT_CONTRIBUTIONS {
+                return Err(format!("Participants must be between 2 and {}", MAX_SPLIT_CONTRIBUTIONS));
+            }
+            if target.minor_units < *participants as u64 {
+                return Err("Amount is too small to split that many ways".to_string());
+            }
+        }
+        SplitMode::Custom(shares) => {
+            if shares.len() < 2 || shares.len() > MAX_SPLIT_CONTRIBUTIONS {
+                return Err(format!("A split needs between 2 and {} shares", MAX_SPLIT_CONTRIBUTIONS));
+            }
+            let mut total = Money::new(0, target.scale);
+            for (i, share) in shares.iter().enumerate() {
+                if share.amount.is_zero() {
+                    return Err("Shares must be greater than 0".to_string());
+                }
+                if share.amount.scale != target.scale {
+                    return Err("Shares must use the bill's currency".to_string());
+                }
+                if share.payer == merchant {
+                    return Err("The merchant cannot hold a share".to_string());
+                }
+                if shares[..i].iter().any(|other| other.payer == share.payer) {
+                    return Err("Each payer can only hold one share".to_string());
+                }
+                total = total.checked_add(&share.amount).ok_or("Shares are too large")?;
+            }
+            if total != *target {
+                return Err(format!("Shares add up to {} but the bill is {}", total, target));
+            }
+        }
+        SplitMode::Open => {}
+    }
+    Ok(())
+}
+
+pub fn collected(bill: &SplitBill) -> Money {
+    bill.contributions.iter()
+        .fold(Money::new(0, bill.target.scale), |sum, c| sum.saturating_add(&c.amount))
+}
+
+pub fn remaining(bill: &SplitBill) -> Money {
+    Money::new(
+        bill.target.minor_units.saturating_sub(collected(bill).minor_units),
+        bill.target.scale,
+    )
+}
+
+pub fn is_covered(bill: &SplitBill) -> bool {
+    remaining(bill).is_zero()
+}
+
+fn has_contributed(bill: &SplitBill, payer: Principal) -> bool {
+    bill.contributions.iter().any(|c| c.payer == payer)
+}
+
+// What `payer` owes next. Shares are fixed by the split; open bills take
+// the payer's amount, capped at what is left.
+pub fn next_contribution(bill: &SplitBill, payer: Principal, requested: Option<Money>) -> Result<Money, String> {
+    if bill.status != SplitStatus::Collecting {
+        return Err(format!("Split bill is no longer collecting ({:?})", bill.status));
+    }
+    if payer == bill.merchant {
+        return Err("Cannot pay to yourself".to_string());
+    }
+    if bill.contributions.len() >= MAX_SPLIT_CONTRIBUTIONS {
+        return Err("Split bill has reached its contribution limit".to_string());
+    }
+
+    let amount = match &bill.mode {
+        SplitMode::Equal { participants } => {
+            if has_contributed(bill, payer) {
+                return Err("You have already paid your share".to_string());
+            }
+            let share = bill.target.minor_units / *participants as u64;
+            // The last share absorbs the rounding remainder
+            if bill.contributions.len() + 1 >= *participants as usize {
+                remaining(bill)
+            } else {
+                Money::new(share, bill.target.scale)
+            }
+        }
+        SplitMode::Custom(shares) => {
+            let share = shares.iter().find(|share| share.payer == payer)
+                .ok_or("You do not have a share in this bill")?;
+            if has_contributed(bill, payer) {
+                return Err("You have already paid your share".to_string());
+            }
+            share.amount
+        }
+        SplitMode::Open => {
+            let amount = requested.ok_or("Amount is required for this QR code")?;
+            if amount.is_zero() {
+                return Err("Amount must be greater than 0".to_string());
+            }
+            if amount.minor_units > remaining(bill).minor_units {
+                return Err(format!("Only {} is left to pay", remaining(bill)));
+            }
+            return Ok(amount);
+        }
+    };
+
+    if requested.is_some_and(|requested| requested != amount) {
+        return Err(format!("Your share is {}", amount));
+    }
+    Ok(amount)
+}
+
+// One usage log per contribution settled together; `settled` pairs each
+// payer with the transaction that closed their part
+pub fn settlement_usage_logs(
+    bill: &SplitBill,
+    settled: &[(Principal, String)],
+    usage_type: QRUsageType,
+    now: u64,
+) -> Vec<QRUsageLog> {
+    settled.iter()
+        .map(|(payer, transaction_id)| {
+            build_qr_usage_log(bill.id.clone(), bill.merchant, *payer, transaction_id.clone(), usage_type.clone(), now)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn principal(id: u8) -> Principal {
+        Principal::from_slice(&[id])
+    }
+
+    fn bill(mode: SplitMode) -> SplitBill {
+        SplitBill {
+            id: "SPLIT".to_string(),
+            merchant: principal(1),
+            currency: "USD".to_string(),
+            target: Money::new(10_000, 2),
+            mode,
+            contributions: Vec::new(),
+            status: SplitStatus::Collecting,
+            created_at: 0,
+            expires_at: 100,
+            settled_at: None,
+        }
+    }
+
+    fn contribute(bill: &mut SplitBill, payer: Principal, amount: Money) {
+        bill.contributions.push(SplitContribution {
+            payer,
+            amount,
+            icp_amount: 0,
+            transaction_id: String::new(),
+            timestamp: 0,
+        });
+    }
+
+    #[test]
+    fn test_validate_split_mode() {
+        let target = Money::new(10_000, 2);
+        let share = |id, amount| SplitShare { payer: principal(id), amount: Money::new(amount, 2) };
+
+        assert!(validate_split_mode(&SplitMode::Equal { participants: 3 }, &target, principal(1)).is_ok());
+        assert!(validate_split_mode(&SplitMode::Equal { participants: 1 }, &target, principal(1)).is_err());
+        assert!(validate_split_mode(&SplitMode::Custom(vec![share(2, 4_000), share(3, 6_000)]), &target, principal(1)).is_ok());
+        assert!(validate_split_mode(&SplitMode::Custom(vec![share(2, 4_000), share(3, 5_000)]), &target, principal(1)).is_err());
+        assert!(validate_split_mode(&SplitMode::Custom(vec![share(2, 4_000), share(2, 6_000)]), &target, principal(1)).is_err());
+        assert!(validate_split_mode(&SplitMode::Custom(vec![share(1, 4_000), share(3, 6_000)]), &target, principal(1)).is_err());
+        assert!(validate_split_mode(&SplitMode::Open, &target, principal(1)).is_ok());
+    }
+
+    #[test]
+    fn test_equal_shares_absorb_remainder() {
+        let mut bill = bill(SplitMode::Equal { participants: 3 });
+
+        assert_eq!(next_contribution(&bill, principal(2), None), Ok(Money::new(3_333, 2)));
+        contribute(&mut bill, principal(2), Money::new(3_333, 2));
+        assert!(next_contribution(&bill, principal(2), None).is_err());
+
+        contribute(&mut bill, principal(3), Money::new(3_333, 2));
+        assert_eq!(next_contribution(&bill, principal(4), None), Ok(Money::new(3_334, 2)));
+        assert!(next_contribution(&bill, principal(4), Some(Money::new(3_333, 2))).is_err());
+
+        contribute(&mut bill, principal(4), Money::new(3_334, 2));
+        assert!(is_covered(&bill));
+    }
+
+    #[test]
+    fn test_custom_and_open_contributions() {
+        let custom = bill(SplitMode::Custom(vec![
+            SplitShare { payer: principal(2), amount: Money::new(4_000, 2) },
+            SplitShare { payer: principal(3), amount: Money::new(6_000, 2) },
+        ]));
+        assert_eq!(next_contribution(&custom, principal(3), None), Ok(Money::new(6_000, 2)));
+        assert!(next_contribution(&custom, principal(4), None).is_err());
+
+        let mut open = bill(SplitMode::Open);
+        assert!(next_contribution(&open, principal(2), None).is_err());
+        contribute(&mut open, principal(2), Money::new(7_500, 2));
+        assert_eq!(remaining(&open), Money::new(2_500, 2));
+        assert!(next_contribution(&open, principal(2), Some(Money::new(3_000, 2))).is_err());
+        assert_eq!(next_contribution(&open, principal(2), Some(Money::new(2_500, 2))), Ok(Money::new(2_500, 2)));
+        assert!(next_contribution(&open, principal(1), Some(Money::new(100, 2))).is_err());
+    }
+
+    #[test]
+    fn test_settling_contributions_logs_each_one() {
+        let mut bill = bill(SplitMode::Open);
+        contribute(&mut bill, principal(2), Money::new(4_000, 2));
+        contribute(&mut bill, principal(3), Money::new(6_000, 2));
+        let settled = vec![
+            (principal(2), "TX_A_COMPLETED_50".to_string()),
+            (principal(3), "TX_B_COMPLETED_50".to_string()),
+        ];
+
+        // Both are released in the same message, at the same time
+        let logs = settlement_usage_logs(&bill, &settled, QRUsageType::PaymentCompleted, 50);
+        let stored: std::collections::BTreeMap<String, QRUsageLog> =
+            logs.into_iter().map(|log| (log.id.clone(), log)).collect();
+        assert_eq!(stored.len(), 2);
+        assert!(stored.values().all(|log| log.qr_id == "SPLIT" && log.user_id == principal(1)));
+
+        let refunds = settlement_usage_logs(&bill, &settled, QRUsageType::ContributionRefunded, 50);
+        assert!(refunds.iter().all(|log| !stored.contains_key(&log.id)));
+    }
+}
@@ -0,0 +1,214 @@
+use crate::types::*;
+use crate::money::Money;
+use candid::Principal;
+use ic_cdk::api::time;
+use sha2::{Digest, Sha256};
+
+const NANOS_PER_DAY: u64 = 24 * 60 * 60 * 1_000_000_000;
+const MAX_INTERVAL_COUNT: u32 = 36;
+const MAX_PLAN_NAME_LEN: usize = 100;
+
+// Delay before each retry of a failed charge; once exhausted the
+// subscription is cancelled
+const DUNNING_RETRY_SECONDS: [u64; 3] = [60 * 60, 24 * 60 * 60, 3 * 24 * 60 * 60];
+
+// Short enough to travel as an ICRC-1 memo (max 32 bytes)
+pub fn generate_subscription_id(prefix: &str, owner: Principal, sequence: u64) -> String {
+    let combined = format!("{}-{}-{}-{}", prefix, owner.to_text(), time(), sequence);
+
+    let mut hasher = Sha256::new();
+    hasher.update(combined.as_bytes());
+    let hash = hasher.finalize();
+
+    format!("{}_{:X}", prefix, hash)[..prefix.len() + 17].to_string()
+}
+
+pub fn period_nanos(interval: BillingInterval, interval_count: u32) -> u64 {
+    let days = match interval {
+        BillingInterval::Day => 1,
+        BillingInterval::Week => 7,
+        BillingInterval::Month => 30,
+        BillingInterval::Year => 365,
+    };
+    days * interval_count as u64 * NANOS_PER_DAY
+}
+
+pub fn validate_plan(name: &str, amount: &Money, interval_count: u32) -> Result<(), String> {
+    if name.trim().is_empty() || name.len() > MAX_PLAN_NAME_LEN {
+        return Err(format!("Plan names must be 1-{} characters", MAX_PLAN_NAME_LEN));
+    }
+    if amount.is_zero() {
+        return Err("Plan amount must be greater than 0".to_string());
+    }
+    if interval_count == 0 || interval_count > MAX_INTERVAL_COUNT {
+        return Err(format!("Interval count must be between 1 and {}", MAX_INTERVAL_COUNT));
+    }
+    Ok(())
+}
+
+pub fn is_party(subscription: &Subscription, caller: Principal) -> bool {
+    subscription.payer == caller || subscription.merchant == caller
+}
+
+pub fn is_due(subscription: &Subscription, now: u64) -> bool {
+    matches!(subscription.status, SubscriptionStatus::Active | SubscriptionStatus::PastDue)
+        && subscription.next_charge_at <= now
+}
+
+// A paid period; the next one starts where this one ended
+pub fn apply_charge_success(subscription: &mut Subscription, plan: &SubscriptionPlan, now: u64) {
+    let period = period_nanos(plan.interval, plan.interval_count);
+    // After a long pause or outage, bill from now instead of catching up
+    subscription.next_charge_at = subscription.next_charge_at.saturating_add(period).max(now.saturating_add(1));
+    // A pause or cancel that raced the charge stays in place
+    if subscription.status == SubscriptionStatus::PastDue {
+        subscription.status = SubscriptionStatus::Active;
+    }
+    subscription.failed_attempts = 0;
+    subscription.charge_count += 1;
+    subscription.last_error = None;
+}
+
+pub fn apply_charge_failure(subscription: &mut Subscription, error: String, now: u64) {
+    match DUNNING_RETRY_SECONDS.get(subscription.failed_attempts as usize) {
+        Some(delay) => {
+            subscription.status = SubscriptionStatus::PastDue;
+            subscription.next_charge_at = now.saturating_add(delay * 1_000_000_000);
+        }
+        None => {
+            subscription.status = SubscriptionStatus::Cancelled;
+            subscription.cancelled_at = Some(now);
+        }
+    }
+    subscription.failed_attempts += 1;
+    subscription.last_error = Some(error);
+}
+
+pub fn pause_subscription(subscription: &mut Subscription) -> Result<(), String> {
+    match subscription.status {
+        SubscriptionStatus::Active | SubscriptionStatus::PastDue => {
+            subscription.status = SubscriptionStatus::Paused;
+            Ok(())
+        }
+        SubscriptionStatus::Paused => Err("Subscription is already paused".to_string()),
+        SubscriptionStatus::Cancelled => Err("Subscription is cancelled".to_string()),
+    }
+}
+
+// An overdue period is charged on the scheduler's next run
+pub fn resume_subscription(subscription: &mut Subscription) -> Result<(), String> {
+    if subscription.status != SubscriptionStatus::Paused {
+        return Err("Only paused subscriptions can be resumed".to_string());
+    }
+    subscription.status = if subscription.failed_attempts > 0 {
+        SubscriptionStatus::PastDue
+    } else {
+        SubscriptionStatus::Active
+    };
+    Ok(())
+}
+
+pub fn cancel_subscription(subscription: &mut Subscription, now: u64) -> Result<(), String> {
+    if subscription.status == SubscriptionStatus::Cancelled {
+        return Err("Subscription is already cancelled".to_string());
+    }
+    subscription.status = SubscriptionStatus::Cancelled;
+    subscription.cancelled_at = Some(now);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plan() -> SubscriptionPlan {
+        SubscriptionPlan {
+            id: "PLAN_1".to_string(),
+            merchant: Principal::from_slice(&[1]),
+            name: "Gym".to_string(),
+            amount: Money::new(2_500, 2),
+            currency: "USD".to_string(),
+            interval: BillingInterval::Month,
+            interval_count: 1,
+            active: true,
+            created_at: 0,
+        }
+    }
+
+    fn subscription(next_charge_at: u64) -> Subscription {
+        Subscription {
+            id: "SUB_1".to_string(),
+            plan_id: "PLAN_1".to_string(),
+            merchant: Principal::from_slice(&[1]),
+            payer: Principal::from_slice(&[2]),
+            funding: SubscriptionFunding::Balance,
+            status: SubscriptionStatus::Active,
+            next_charge_at,
+            failed_attempts: 0,
+            charge_count: 0,
+            last_error: None,
+            created_at: 0,
+            cancelled_at: None,
+        }
+    }
+
+    #[test]
+    fn test_validate_plan() {
+        assert!(validate_plan("Gym", &Money::new(2_500, 2), 1).is_ok());
+        assert!(validate_plan(" ", &Money::new(2_500, 2), 1).is_err());
+        assert!(validate_plan("Gym", &Money::new(0, 2), 1).is_err());
+        assert!(validate_plan("Gym", &Money::new(2_500, 2), 0).is_err());
+        assert_eq!(period_nanos(BillingInterval::Week, 2), 14 * NANOS_PER_DAY);
+    }
+
+    #[test]
+    fn test_charge_success_advances_period() {
+        let mut sub = subscription(100);
+        apply_charge_success(&mut sub, &plan(), 100);
+        assert_eq!(sub.next_charge_at, 100 + 30 * NANOS_PER_DAY);
+        assert_eq!(sub.charge_count, 1);
+
+        // Far behind schedule: the next period starts from now
+        let mut late = subscription(100);
+        let now = 100 * NANOS_PER_DAY;
+        apply_charge_success(&mut late, &plan(), now);
+        assert_eq!(late.next_charge_at, now + 1);
+    }
+
+    #[test]
+    fn test_dunning_then_cancel() {
+        let mut sub = subscription(0);
+        for (attempt, delay) in DUNNING_RETRY_SECONDS.iter().enumerate() {
+            apply_charge_failure(&mut sub, "Insufficient balance".to_string(), 10);
+            assert_eq!(sub.status, SubscriptionStatus::PastDue);
+            assert_eq!(sub.failed_attempts, attempt as u32 + 1);
+            assert_eq!(sub.next_charge_at, 10 + delay * 1_000_000_000);
+        }
+
+        apply_charge_failure(&mut sub, "Insufficient balance".to_string(), 20);
+        assert_eq!(sub.status, SubscriptionStatus::Cancelled);
+        assert!(!is_due(&sub, u64::MAX));
+
+        let mut recovered = subscription(0);
+        apply_charge_failure(&mut recovered, "Insufficient balance".to_string(), 10);
+        apply_charge_success(&mut recovered, &plan(), 20);
+        assert_eq!(recovered.status, SubscriptionStatus::Active);
+        assert_eq!(recovered.failed_attempts, 0);
+    }
+
+    #[test]
+    fn test_pause_resume_cancel() {
+        let mut sub = subscription(0);
+        pause_subscription(&mut sub).unwrap();
+        assert!(!is_due(&sub, 10));
+        assert!(pause_subscription(&mut sub).is_err());
+
+        resume_subscription(&mut sub).unwrap();
+        assert!(is_due(&sub, 10));
+
+        cancel_subscription(&mut sub, 5).unwrap();
+        assert_eq!(sub.cancelled_at, Some(5));
+        assert!(resume_subscription(&mut sub).is_err());
+        assert!(cancel_subscription(&mut sub, 6).is_err());
+    }
+}
@@ -1,16 +1,49 @@
 use crate::types::*;
 use crate::rates::*;
+use crate::money::{currency_minor_units, Money, RoundingMode};
+use crate::currencies::iso_numeric_code;
+use crate::emv::{encode_payload, AdditionalData, EmvPayload, MerchantAccountInfo, PointOfInitiation};
 use candid::Principal;
 use ic_cdk::api::time;
 use sha2::{Digest, Sha256};
 
-fn get_base_url() -> &'static str {
-    // DEVELOPMENT MODE
-    "http://localhost:3000"
-    // CANISTER MODE (uncomment line below, comment line above)
-    // "http://127.0.0.1:4943" example : http://uzt4z-lp777-77774-qaabq-cai.localhost:4943/
-    // PRODUCTION MODE (uncomment line below, comment others)
-    // "https://your-production-domain.com" coming soon
+const QRIS_GLOBAL_ID: &str = "ID.CO.QRIS.WWW";
+// Quasi-cash: buying ICP with fiat
+const QRIS_MERCHANT_CATEGORY: &str = "6051";
+
+// Dynamic QRIS payload carrying the top-up amount
+fn build_qris_payload(
+    topup_id: &str,
+    merchant_id: &str,
+    fiat_amount: &Money,
+    currency: &str,
+) -> Result<String, String> {
+    let currency_numeric = iso_numeric_code(currency)
+        .ok_or(format!("Currency {} cannot be used with QRIS", currency.to_uppercase()))?;
+    let amount = fiat_amount.rescale(currency_minor_units(currency), RoundingMode::HalfUp);
+
+    encode_payload(&EmvPayload {
+        point_of_initiation: PointOfInitiation::Dynamic,
+        merchant_accounts: vec![MerchantAccountInfo {
+            tag: "26".to_string(),
+            globally_unique_id: QRIS_GLOBAL_ID.to_string(),
+            merchant_pan: None,
+            merchant_id: Some(merchant_id.to_string()),
+            merchant_criteria: None,
+        }],
+        merchant_category_code: QRIS_MERCHANT_CATEGORY.to_string(),
+        currency_numeric: currency_numeric.to_string(),
+        amount: Some(amount.to_string()),
+        country_code: "ID".to_string(),
+        merchant_name: "ICP Payment".to_string(),
+        merchant_city: "Jakarta Selatan".to_string(),
+        postal_code: Some("12400".to_string()),
+        // EMVCo caps reference labels at 25 characters; the id prefix is still unique
+        additional_data: Some(AdditionalData {
+            reference_label: Some(topup_id.chars().take(25).collect()),
+            ..Default::default()
+        }),
+    })
 }
 
 // Generate unique top-up transaction ID
@@ -30,28 +63,23 @@ pub fn generate_topup_id() -> String {
 // Create QRIS top-up transaction
 pub async fn create_qris_topup(
     user_id: Principal,
-    fiat_amount: f64,
+    fiat_amount: Money,
     currency: String,
 ) -> Result<TopUpTransaction, String> {
     let exchange_rate = fetch_live_exchange_rate(currency.clone()).await?;
-    let icp_amount = calculate_icp_amount(fiat_amount, exchange_rate.rate)?;
+    let icp_amount = calculate_icp_amount(&fiat_amount, exchange_rate.rate, RoundingMode::Down)?;
     
     let topup_id = generate_topup_id();
     let current_time = time();
-    let expire_time = current_time + (15 * 60 * 1_000_000_000); 
-    
-    
-    let base_url = get_base_url();
+    let config = crate::current_config();
+    let expire_time = current_time + config.qris_expiry_seconds * 1_000_000_000;
     
-    ic_cdk::println!("🌐 Using base URL: {}", base_url);
+    ic_cdk::println!("🌐 Using base URL: {}", config.public_base_url);
     
     let qris_data = QRISData {
-        qr_code_url: format!("{}/qris/{}", base_url, topup_id),
-        qr_code_data: format!(
-            "00020101021226580014ID.CO.QRIS.WWW0114ID.CO.QRIS.WWW02150000000000000000303ID.CO.QRIS.WWW540{:.2}5303360540{:.2}5802ID5914ICP Payment6015Jakarta Selatan61051240062070703A0163046D4F", 
-            fiat_amount, fiat_amount
-        ),
-        merchant_id: "ICP_PAYMENT_001".to_string(),
+        qr_code_url: format!("{}/qris/{}", config.public_base_url, topup_id),
+        qr_code_data: build_qris_payload(&topup_id, &config.merchant_id, &fiat_amount, &currency)?,
+        merchant_id: config.merchant_id.clone(),
         expire_time,
     };
     
@@ -80,6 +108,7 @@ pub async fn create_qris_topup(
 
 pub fn check_payment_expiration(topup: &TopUpTransaction) -> bool {
     let current_time = time();
+    let config = crate::current_config();
     
     match topup.payment_method {
         TopUpMethod::QRIS => {
@@ -92,14 +121,10 @@ pub fn check_payment_expiration(topup: &TopUpTransaction) -> bool {
             }
         },
         TopUpMethod::CreditCard | TopUpMethod::DebitCard => {
-            
-            let one_hour = 60 * 60 * 1_000_000_000u64;
-            current_time > topup.created_at + one_hour
+            current_time > topup.created_at + config.card_topup_expiry_seconds * 1_000_000_000
         },
         TopUpMethod::Web3Wallet => {
-            
-            let thirty_minutes = 30 * 60 * 1_000_000_000u64;
-            current_time > topup.created_at + thirty_minutes
+            current_time > topup.created_at + config.web3_topup_expiry_seconds * 1_000_000_000
         },
     }
 }
@@ -107,7 +132,7 @@ pub fn check_payment_expiration(topup: &TopUpTransaction) -> bool {
 // Create card top-up transaction
 pub async fn create_card_topup(
     user_id: Principal,
-    fiat_amount: f64,
+    fiat_amount: Money,
     currency: String,
     card_input: CardDataInput,
     is_credit: bool,
@@ -116,7 +141,7 @@ pub async fn create_card_topup(
     validate_card_data(&card_input)?;
     
     let exchange_rate = fetch_live_exchange_rate(currency.clone()).await?;
-    let icp_amount = calculate_icp_amount(fiat_amount, exchange_rate.rate)?;
+    let icp_amount = calculate_icp_amount(&fiat_amount, exchange_rate.rate, RoundingMode::Down)?;
     
     let topup_id = generate_topup_id();
     let current_time = time();
@@ -202,12 +227,12 @@ fn detect_card_type(card_number: &str) -> String {
 
 pub async fn create_web3_topup(
     user_id: Principal,
-    fiat_amount: f64,
+    fiat_amount: Money,
     currency: String,
     wallet_address: String,
 ) -> Result<TopUpTransaction, String> {
     let exchange_rate = fetch_live_exchange_rate(currency.clone()).await?;
-    let icp_amount = calculate_icp_amount(fiat_amount, exchange_rate.rate)?;
+    let icp_amount = calculate_icp_amount(&fiat_amount, exchange_rate.rate, RoundingMode::Down)?;
     
     let topup_id = generate_topup_id();
     let current_time = time();
@@ -250,6 +275,8 @@ pub fn create_user_with_updated_balance(user: &User, amount: u64) -> User {
         username: user.username.clone(),
         email: user.email.clone(),
         balance: user.balance.saturating_add(amount),
+        kyc_tier: user.kyc_tier,
+        kyc_verified_at: user.kyc_verified_at,
     }
 }
 
@@ -258,12 +285,6 @@ pub fn is_topup_expired(topup: &TopUpTransaction) -> bool {
     check_payment_expiration(topup)
 }
 
-// Format balance for display
-pub fn format_balance(balance: u64) -> String {
-    let icp_amount = balance as f64 / 100_000_000.0;
-    format!("{:.8} ICP", icp_amount)
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -274,6 +295,22 @@ mod tests {
         assert_eq!(mask_card_number("123"), "****");
     }
     
+    #[test]
+    fn test_build_qris_payload() {
+        crate::seed_currency_registry();
+
+        let payload = build_qris_payload("TU_0123456789ABCDEF0123456789ABC", "ICP_PAYMENT_001", &Money::new(150_000, 0), "IDR")
+            .unwrap();
+        let decoded = crate::emv::decode_payload(&payload).unwrap();
+
+        assert_eq!(decoded.currency_numeric, "360");
+        assert_eq!(decoded.amount, Some("150000".to_string()));
+        assert_eq!(
+            decoded.additional_data.unwrap().reference_label,
+            Some("TU_0123456789ABCDEF012345".to_string())
+        );
+    }
+
     #[test]
     fn test_detect_card_type() {
         assert_eq!(detect_card_type("4111111111111111"), "Visa");
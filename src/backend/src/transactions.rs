@@ -1,5 +1,7 @@
 use crate::types::*;
 use crate::qr::*;
+use crate::money::Money;
+use crate::formatting::{format_icp, format_money, DEFAULT_LANGUAGE};
 use candid::Principal;
 use ic_cdk::api::time;
 use sha2::{Digest, Sha256};
@@ -20,8 +22,9 @@ pub fn create_transaction(
     qr_code: &QRCode,
     payer: Principal,
     transaction_hash: Option<String>,
+    completed_uses: u64,
 ) -> Result<Transaction, String> {
-    is_qr_code_valid(qr_code)?;
+    is_qr_code_valid(qr_code, payer, completed_uses)?;
     
     let tx_id = generate_transaction_id(payer, qr_code.user_id, qr_code.icp_amount);
     
@@ -40,6 +43,8 @@ pub fn create_transaction(
         qr_id: qr_code.id.clone(),
         transaction_hash,
         fee,
+        kind: TransactionKind::QrPayment,
+        memo: None,
     };
     
     Ok(transaction)
@@ -52,50 +57,74 @@ pub fn calculate_transaction_fee(amount: u64) -> u64 {
     std::cmp::max(percentage_fee, minimum_fee)
 }
 
+const ICP_DECIMALS: u8 = 8;
+const MAX_MEMO_LEN: usize = 140;
+
+// A decimal ICP amount such as "1.5", in e8s
+pub fn parse_icp_amount(amount: &str) -> Result<u64, String> {
+    Ok(Money::parse_scaled(amount, ICP_DECIMALS, "ICP")?.minor_units)
+}
+
+// A blank memo is no memo
+pub fn normalize_memo(memo: Option<String>) -> Result<Option<String>, String> {
+    let memo = memo.map(|m| m.trim().to_string()).filter(|m| !m.is_empty());
+    if memo.as_ref().is_some_and(|m| m.chars().count() > MAX_MEMO_LEN) {
+        return Err(format!("Memo must be at most {} characters", MAX_MEMO_LEN));
+    }
+    Ok(memo)
+}
+
+// Finds a registered user by principal or username ("@name" forces a username
+// lookup). Emails are never checked at registration, so they can't be used to
+// find anyone.
+pub fn resolve_recipient(recipient: &str, users: impl Iterator<Item = User>) -> Result<Principal, String> {
+    let recipient = recipient.trim();
+    if recipient.is_empty() {
+        return Err("Recipient is required".to_string());
+    }
+
+    let principal = Principal::from_text(recipient).ok();
+    let username = recipient.strip_prefix('@');
+    if username.is_none() && recipient.contains('@') {
+        return Err("Send to a principal or @username; email addresses are not verified".to_string());
+    }
+
+    let matches: Vec<Principal> = users
+        .filter(|user| match (username, principal) {
+            (Some(name), _) => user.username.as_ref().is_some_and(|u| u.eq_ignore_ascii_case(name)),
+            (None, Some(principal)) => user.id == principal,
+            (None, None) => user.username.as_ref().is_some_and(|u| u.eq_ignore_ascii_case(recipient)),
+        })
+        .map(|user| user.id)
+        .collect();
+
+    match matches.as_slice() {
+        [] => Err("Recipient not found".to_string()),
+        [recipient] => Ok(*recipient),
+        _ => Err("More than one user matches this recipient; use their principal".to_string()),
+    }
+}
+
 // Transaction validation
 pub fn validate_transaction_amount(amount: u64) -> Result<(), String> {
     if amount == 0 {
         return Err("Transaction amount cannot be zero".to_string());
     }
     
-    if amount < 100_000 {
-        return Err("Transaction amount too small. Minimum: 0.001 ICP".to_string());
+    let config = crate::current_config();
+    if amount < config.min_transaction_e8s {
+        return Err(format!("Transaction amount too small. Minimum: {}",
+            format_icp(config.min_transaction_e8s, DEFAULT_LANGUAGE)));
     }
     
-    if amount > 100_000_000_000 {
-        return Err("Transaction amount too large. Maximum: 1000 ICP".to_string());
+    if amount > config.max_transaction_e8s {
+        return Err(format!("Transaction amount too large. Maximum: {}",
+            format_icp(config.max_transaction_e8s, DEFAULT_LANGUAGE)));
     }
     
     Ok(())
 }
 
-// Get user transaction statistics
-pub fn calculate_user_stats(transactions: &[Transaction], user_id: Principal) -> UserStats {
-    let mut total_sent = 0u64;
-    let mut total_received = 0u64;
-    let mut transaction_count = 0u64;
-    
-    for tx in transactions {
-        if tx.from == user_id {
-            total_sent += tx.amount;
-            transaction_count += 1;
-        } else if tx.to == user_id {
-            total_received += tx.amount;
-            transaction_count += 1;
-        }
-    }
-    
-    UserStats {
-        total_sent,
-        total_received,
-        transaction_count,
-        qr_codes_generated: 0, 
-        total_topup: 0,        
-        topup_count: 0,        
-        current_balance: 0,    
-    }
-}
-
 // Filter transactions by status
 pub fn filter_transactions_by_status(
     transactions: &[Transaction],
@@ -134,11 +163,6 @@ pub fn get_recent_transactions(transactions: &[Transaction]) -> Vec<Transaction>
 }
 
 // Transaction display helpers
-pub fn format_transaction_amount(amount: u64) -> String {
-    let icp_amount = amount as f64 / 100_000_000.0;
-    format!("{:.8} ICP", icp_amount)
-}
-
 pub fn get_transaction_display_status(status: &TransactionStatus) -> String {
     match status {
         TransactionStatus::Pending => "⏳ Pending".to_string(),
@@ -165,6 +189,7 @@ pub struct TransactionSummary {
 pub fn create_transaction_summary(
     transaction: &Transaction,
     user_id: Principal,
+    language: &str,
 ) -> TransactionSummary {
     let is_incoming = transaction.to == user_id;
     let counterpart = if is_incoming {
@@ -175,12 +200,8 @@ pub fn create_transaction_summary(
     
     TransactionSummary {
         id: transaction.id.clone(),
-        amount_icp: format_transaction_amount(transaction.amount),
-        amount_fiat: format!(
-            "{:.2} {}",
-            transaction.fiat_amount,
-            transaction.fiat_currency
-        ),
+        amount_icp: format_icp(transaction.amount, language),
+        amount_fiat: format_money(&transaction.fiat_amount, &transaction.fiat_currency, language),
         currency: transaction.fiat_currency.clone(),
         status: get_transaction_display_status(&transaction.status),
         timestamp: transaction.timestamp,
@@ -223,6 +244,49 @@ mod tests {
         assert_ne!(id1, id2); 
     }
 
+    fn user(id: u8, username: Option<&str>, email: Option<&str>) -> User {
+        User {
+            id: Principal::from_slice(&[id]),
+            wallet_address: String::new(),
+            created_at: 0,
+            username: username.map(str::to_string),
+            email: email.map(str::to_string),
+            balance: 0,
+            kyc_tier: KycTier::None,
+            kyc_verified_at: None,
+        }
+    }
+
+    #[test]
+    fn test_resolve_recipient() {
+        let users = vec![
+            user(1, Some("alice"), Some("Alice@example.com")),
+            user(2, Some("bob"), None),
+            user(3, Some("Bob"), None),
+        ];
+        let alice = Principal::from_slice(&[1]);
+
+        assert_eq!(resolve_recipient(&alice.to_text(), users.clone().into_iter()), Ok(alice));
+        // Emails are unverified, so anyone could claim Alice's
+        assert!(resolve_recipient("alice@example.com", users.clone().into_iter()).is_err());
+        assert_eq!(resolve_recipient("ALICE", users.clone().into_iter()), Ok(alice));
+        assert_eq!(resolve_recipient("@alice", users.clone().into_iter()), Ok(alice));
+        assert!(resolve_recipient("bob", users.clone().into_iter()).is_err());
+        assert!(resolve_recipient("carol", users.clone().into_iter()).is_err());
+        assert!(resolve_recipient(" ", users.into_iter()).is_err());
+    }
+
+    #[test]
+    fn test_parse_icp_amount_and_memo() {
+        assert_eq!(parse_icp_amount("1.5"), Ok(150_000_000));
+        assert_eq!(parse_icp_amount("0.00000001"), Ok(1));
+        assert!(parse_icp_amount("0.000000001").is_err());
+
+        assert_eq!(normalize_memo(Some("  lunch ".to_string())), Ok(Some("lunch".to_string())));
+        assert_eq!(normalize_memo(Some("   ".to_string())), Ok(None));
+        assert!(normalize_memo(Some("x".repeat(141))).is_err());
+    }
+
     #[test]
     fn test_calculate_transaction_fee() {
         assert_eq!(calculate_transaction_fee(1_000_000), 10_000); 
@@ -238,11 +302,4 @@ mod tests {
         assert!(validate_transaction_amount(0).is_err()); 
         assert!(validate_transaction_amount(100_000_000_001).is_err()); 
     }
-
-    #[test]
-    fn test_format_transaction_amount() {
-        assert_eq!(format_transaction_amount(100_000_000), "1.00000000 ICP");
-        assert_eq!(format_transaction_amount(50_000_000), "0.50000000 ICP");
-        assert_eq!(format_transaction_amount(12_345_678), "0.12345678 ICP");
-    }
 }
\ No newline at end of file
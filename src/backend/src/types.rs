@@ -2,6 +2,7 @@ use candid::{CandidType, Deserialize, Principal};
 use ic_stable_structures::{Storable, storable::Bound};
 use serde::Serialize;
 use std::borrow::Cow;
+use crate::money::{currency_minor_units, Money, RoundingMode};
 
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct User {
@@ -11,15 +12,19 @@ pub struct User {
     pub username: Option<String>,
     pub email: Option<String>,
     pub balance: u64,
+    pub kyc_tier: KycTier,
+    // When an admin last approved a KYC submission
+    pub kyc_verified_at: Option<u64>,
 }
 
 impl Storable for User {
-    fn to_bytes(&self) -> Cow<[u8]> {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
         Cow::Owned(candid::encode_one(self).unwrap())
     }
 
     fn from_bytes(bytes: Cow<[u8]>) -> Self {
-        candid::decode_one(&bytes).unwrap()
+        candid::decode_one(&bytes)
+            .unwrap_or_else(|_| candid::decode_one::<UserV1>(&bytes).unwrap().into())
     }
 
     const BOUND: Bound = Bound::Bounded {
@@ -35,23 +40,27 @@ pub struct Transaction {
     pub to: Principal,
     pub amount: u64,           
     pub fiat_currency: String, 
-    pub fiat_amount: f64,      
+    pub fiat_amount: Money,    
     pub icp_amount: u64,       
     pub timestamp: u64,
     pub status: TransactionStatus,
+    // Empty for payments that did not go through a QR code
     pub qr_id: String,
     pub transaction_hash: Option<String>,
     pub fee: u64,              
+    pub kind: TransactionKind,
+    pub memo: Option<String>,
 }
 
 
 impl Storable for Transaction {
-    fn to_bytes(&self) -> Cow<[u8]> {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
         Cow::Owned(candid::encode_one(self).unwrap())
     }
 
     fn from_bytes(bytes: Cow<[u8]>) -> Self {
-        candid::decode_one(&bytes).unwrap()
+        candid::decode_one(&bytes)
+            .unwrap_or_else(|_| candid::decode_one::<LegacyTransaction>(&bytes).unwrap().into())
     }
 
     const BOUND: Bound = Bound::Bounded {
@@ -60,6 +69,31 @@ impl Storable for Transaction {
     };
 }
 
+// What moved the money
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum TransactionKind {
+    QrPayment,
+    PeerTransfer,
+    WalletPayment,
+    Subscription,
+    PaymentRequest,
+    SplitBill,
+    Payout,
+}
+
+impl TransactionKind {
+    // Transactions stored before the kind was recorded
+    fn infer(id: &str) -> Self {
+        if id.starts_with("TX_ICRC_") {
+            TransactionKind::WalletPayment
+        } else if id.starts_with("TX_SUB_") {
+            TransactionKind::Subscription
+        } else {
+            TransactionKind::QrPayment
+        }
+    }
+}
+
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub enum TransactionStatus {
     Pending,
@@ -83,7 +117,7 @@ pub struct BalanceChangeLog {
 }
 
 impl Storable for BalanceChangeLog {
-    fn to_bytes(&self) -> Cow<[u8]> {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
         Cow::Owned(candid::encode_one(self).unwrap())
     }
 
@@ -105,6 +139,9 @@ pub enum BalanceChangeType {
     FeeDeducted,
     Refund,
     Adjustment,
+    // A dispute refund taken back from the merchant; not a payment, so it
+    // stays out of spending limits and risk history
+    Chargeback,
 }
 
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
@@ -119,7 +156,7 @@ pub struct QRUsageLog {
 }
 
 impl Storable for QRUsageLog {
-    fn to_bytes(&self) -> Cow<[u8]> {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
         Cow::Owned(candid::encode_one(self).unwrap())
     }
 
@@ -138,6 +175,14 @@ pub enum QRUsageType {
     PaymentCompleted,
     PaymentFailed,
     PaymentExpired,
+    Cancelled,
+    // A split-bill contribution held until the bill is covered
+    ContributionHeld,
+    ContributionRefunded,
+    // A payment held in escrow or for review; it counts as a use until it is
+    // released (logged as completed) or refunded
+    PaymentHeld,
+    PaymentRefunded,
 }
 
 // User Preferences (Mutable)
@@ -153,7 +198,7 @@ pub struct UserPreferences {
 }
 
 impl Storable for UserPreferences {
-    fn to_bytes(&self) -> Cow<[u8]> {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
         Cow::Owned(candid::encode_one(self).unwrap())
     }
 
@@ -188,7 +233,7 @@ pub struct UserSession {
 }
 
 impl Storable for UserSession {
-    fn to_bytes(&self) -> Cow<[u8]> {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
         Cow::Owned(candid::encode_one(self).unwrap())
     }
 
@@ -206,22 +251,29 @@ impl Storable for UserSession {
 pub struct QRCode {
     pub id: String,
     pub user_id: Principal,
-    pub fiat_amount: f64,
+    pub kind: QRKind,
+    pub fiat_amount: Money,
     pub fiat_currency: String,
     pub icp_amount: u64,
     pub expire_time: u64,
     pub created_at: u64,
-    pub is_used: bool,
+    pub status: QRStatus,
     pub description: Option<String>,
+    // None means the kind's default: dynamic codes pay once, static codes are unlimited
+    pub max_uses: Option<u32>,
+    pub allowed_payer: Option<Principal>,
+    // Set when the code collects (part of) an invoice
+    pub invoice_id: Option<String>,
 }
 
 impl Storable for QRCode {
-    fn to_bytes(&self) -> Cow<[u8]> {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
         Cow::Owned(candid::encode_one(self).unwrap())
     }
 
     fn from_bytes(bytes: Cow<[u8]>) -> Self {
-        candid::decode_one(&bytes).unwrap()
+        candid::decode_one(&bytes)
+            .unwrap_or_else(|_| candid::decode_one::<LegacyQRCode>(&bytes).unwrap().into())
     }
 
     const BOUND: Bound = Bound::Bounded {
@@ -230,20 +282,198 @@ impl Storable for QRCode {
     };
 }
 
+// Dynamic codes carry a fixed amount and are single-use; static codes are
+// printed once, never expire, and the payer enters the amount.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum QRKind {
+    Dynamic,
+    Static,
+    // Several payers contribute until the amount is covered
+    Split,
+}
+
+// Stored lifecycle state. Expired is derived from expire_time when read;
+// Paid and Exhausted are set when the last allowed payment completes.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum QRStatus {
+    Active,
+    Cancelled,
+    Expired,
+    Paid,
+    Exhausted,
+}
+
+// Passed to init and post_upgrade; fields left out keep their current value
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct CanisterArgs {
+    pub public_base_url: Option<String>,
+    pub qr_policy: Option<QRPolicy>,
+    pub qris_expiry_seconds: Option<u64>,
+    pub card_topup_expiry_seconds: Option<u64>,
+    pub web3_topup_expiry_seconds: Option<u64>,
+    pub min_transaction_e8s: Option<u64>,
+    pub max_transaction_e8s: Option<u64>,
+    pub rate_cache_seconds: Option<u64>,
+    pub rate_fetch_max_retries: Option<u32>,
+    pub merchant_id: Option<String>,
+    pub ledger_canister_id: Option<Principal>,
+    pub tier_limits: Option<Vec<TierLimits>>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CanisterConfig {
+    // Prefix for payment links, e.g. "https://<canister-id>.raw.icp0.io"
+    pub public_base_url: String,
+    pub qr_policy: QRPolicy,
+    pub qris_expiry_seconds: u64,
+    pub card_topup_expiry_seconds: u64,
+    pub web3_topup_expiry_seconds: u64,
+    pub min_transaction_e8s: u64,
+    pub max_transaction_e8s: u64,
+    // How long a fetched exchange rate may be reused
+    pub rate_cache_seconds: u64,
+    pub rate_fetch_max_retries: u32,
+    // Our merchant id in QRIS payloads
+    pub merchant_id: String,
+    // ICRC ledger for wallet payments; None disables payment URIs and the watcher
+    pub ledger_canister_id: Option<Principal>,
+    // One entry per KYC tier
+    pub tier_limits: Vec<TierLimits>,
+}
+
+pub const ICP_LEDGER_CANISTER_ID: &str = "ryjl3-tyaaa-aaaaa-aaaba-cai";
+
+impl Default for CanisterConfig {
+    fn default() -> Self {
+        CanisterConfig {
+            public_base_url: "http://localhost:3000".to_string(),
+            qr_policy: QRPolicy::default(),
+            qris_expiry_seconds: 15 * 60,
+            card_topup_expiry_seconds: 60 * 60,
+            web3_topup_expiry_seconds: 30 * 60,
+            min_transaction_e8s: 100_000,
+            max_transaction_e8s: 100_000_000_000,
+            rate_cache_seconds: 300,
+            rate_fetch_max_retries: 3,
+            merchant_id: "ICP_PAYMENT_001".to_string(),
+            ledger_canister_id: Some(Principal::from_text(ICP_LEDGER_CANISTER_ID).unwrap()),
+            tier_limits: default_tier_limits(),
+        }
+    }
+}
+
+impl Storable for CanisterConfig {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+
+    const BOUND: Bound = Bound::Bounded {
+        max_size: 2048,
+        is_fixed_size: false,
+    };
+}
+
+// One admin change to CanisterConfig
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
-pub struct ExchangeRate {
-    pub currency: String,
-    pub rate: f64,           
-    pub timestamp: u64,
-    pub source: String,      
+pub enum ConfigUpdate {
+    PublicBaseUrl(String),
+    QRPolicy(QRPolicy),
+    QrisExpirySeconds(u64),
+    CardTopupExpirySeconds(u64),
+    Web3TopupExpirySeconds(u64),
+    MinTransactionE8s(u64),
+    MaxTransactionE8s(u64),
+    RateCacheSeconds(u64),
+    RateFetchMaxRetries(u32),
+    MerchantId(String),
+    LedgerCanisterId(Option<Principal>),
+    // Replaces the limits of `TierLimits::tier`
+    TierLimits(TierLimits),
 }
 
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
-pub struct PaymentRequest {
+pub struct ConfigChange {
+    pub id: u64,
+    pub field: String,
+    pub old_value: String,
+    pub new_value: String,
+    pub changed_by: Principal,
+    pub changed_at: u64,
+}
+
+impl Storable for ConfigChange {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+
+    const BOUND: Bound = Bound::Bounded {
+        max_size: 2048,
+        is_fixed_size: false,
+    };
+}
+
+// Admin bounds for merchant-chosen QR settings; durations in seconds
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct QRPolicy {
+    pub default_expiry_seconds: u64,
+    pub min_expiry_seconds: u64,
+    pub max_expiry_seconds: u64,
+    pub max_uses_limit: u32,
+}
+
+impl Default for QRPolicy {
+    fn default() -> Self {
+        QRPolicy {
+            default_expiry_seconds: 30 * 60,
+            min_expiry_seconds: 60,
+            max_expiry_seconds: 7 * 24 * 60 * 60,
+            max_uses_limit: 1000,
+        }
+    }
+}
+
+impl Storable for QRPolicy {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+
+    const BOUND: Bound = Bound::Bounded {
+        max_size: 256,
+        is_fixed_size: false,
+    };
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct QRUsageStats {
     pub qr_id: String,
-    pub payer: Principal,
-    pub amount: u64,
+    pub kind: QRKind,
+    pub fiat_currency: String,
+    pub payment_count: u64,
+    pub failed_count: u64,
+    pub unique_payers: u64,
+    pub total_fiat_volume: Money,
+    pub total_icp_volume: u64,
+    pub last_payment_at: Option<u64>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct ExchangeRate {
+    pub currency: String,
+    pub rate: f64,           
     pub timestamp: u64,
+    pub source: String,      
 }
 
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
@@ -282,7 +512,7 @@ pub struct TopUpTransaction {
     pub id: String,
     pub user_id: Principal,
     pub amount: u64,          
-    pub fiat_amount: f64,      
+    pub fiat_amount: Money,    
     pub fiat_currency: String,
     pub payment_method: TopUpMethod,
     pub payment_data: TopUpPaymentData,
@@ -293,12 +523,13 @@ pub struct TopUpTransaction {
 }
 
 impl Storable for TopUpTransaction {
-    fn to_bytes(&self) -> Cow<[u8]> {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
         Cow::Owned(candid::encode_one(&self).unwrap())
     }
 
     fn from_bytes(bytes: Cow<[u8]>) -> Self {
-        candid::decode_one(&bytes).unwrap()
+        candid::decode_one(&bytes)
+            .unwrap_or_else(|_| candid::decode_one::<LegacyTopUpTransaction>(&bytes).unwrap().into())
     }
 
     const BOUND: Bound = Bound::Bounded {
@@ -356,16 +587,32 @@ pub struct Web3Data {
 }
 
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+// balance = available + reserved; held and pending still sit in the escrow
+// account and are not part of balance until they settle.
+// total = balance + held + pending
 pub struct UserBalance {
     pub user_id: Principal,
+    // Credited to the user's own ledger
     pub balance: u64,
     pub formatted_balance: String,
     pub last_updated: u64,
+    // Escrowed payments to this user, not yet released (payments held for
+    // review included)
+    pub held: u64,
+    // Part of balance reserved against open disputes where this user is the
+    // merchant
+    pub reserved: u64,
+    // What can be spent: balance minus reserved
+    pub available: u64,
+    // Split-bill contributions to this user, not credited yet
+    pub pending: u64,
+    // Everything owed to the user, settled or not
+    pub total: u64,
 }
 
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
 pub struct TopUpRequest {
-    pub amount: f64,
+    pub amount: String,
     pub currency: String,
     pub payment_method: TopUpMethod,
     pub card_data: Option<CardDataInput>,
@@ -391,7 +638,7 @@ pub struct EncryptedWalletIdentity {
 }
 
 impl Storable for EncryptedWalletIdentity {
-    fn to_bytes(&self) -> Cow<[u8]> {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
         Cow::Owned(candid::encode_one(self).unwrap())
     }
 
@@ -460,7 +707,7 @@ pub struct NetworkTransaction {
     pub from_user: Option<Principal>,
     pub to_user: Option<Principal>,
     pub amount: u64,
-    pub fiat_amount: f64,
+    pub fiat_amount: Money,
     pub fiat_currency: String,
     pub icp_amount: u64,
     pub timestamp: u64,
@@ -470,3 +717,962 @@ pub struct NetworkTransaction {
     pub fee: Option<u64>,
     pub description: String,
 }
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CurrencyInfo {
+    pub code: String,
+    pub minor_units: u8,
+    pub symbol: String,
+    pub display_name: String,
+    pub countries: Vec<String>,
+    pub enabled: bool,
+    pub min_payment_amount: Option<Money>,
+    pub max_payment_amount: Option<Money>,
+    pub updated_at: u64,
+}
+
+impl Storable for CurrencyInfo {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+
+    const BOUND: Bound = Bound::Bounded {
+        max_size: 2048,
+        is_fixed_size: false,
+    };
+}
+
+// One OHLC bucket of ICP->fiat rates
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RateCandle {
+    pub currency: String,
+    pub bucket_start: u64,
+    pub bucket_seconds: u64,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub sample_count: u32,
+    pub last_updated: u64,
+}
+
+impl Storable for RateCandle {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+
+    const BOUND: Bound = Bound::Bounded {
+        max_size: 256,
+        is_fixed_size: false,
+    };
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum RateBucket {
+    FiveMinutes,
+    Hour,
+    Day,
+    Week,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct CurrencyConversion {
+    pub amount: Money,
+    pub from_currency: String,
+    pub converted_amount: Money,
+    pub to_currency: String,
+    pub cross_rate: f64,
+    // ICP rates used for the triangulation; empty when from == to
+    pub rates_used: Vec<ExchangeRate>,
+}
+
+// Layouts of the previously released canister. Records it wrote are decoded
+// through these and converted on read; the post_upgrade migration rewrites
+// them in the current layout.
+fn legacy_fiat_amount(amount: f64, currency: &str) -> Money {
+    Money::from_f64(amount, currency_minor_units(currency), RoundingMode::HalfEven)
+}
+
+#[derive(CandidType, Deserialize)]
+struct LegacyTransaction {
+    id: String,
+    from: Principal,
+    to: Principal,
+    amount: u64,
+    fiat_currency: String,
+    fiat_amount: f64,
+    icp_amount: u64,
+    timestamp: u64,
+    status: TransactionStatus,
+    qr_id: String,
+    transaction_hash: Option<String>,
+    fee: u64,
+}
+
+impl From<LegacyTransaction> for Transaction {
+    fn from(legacy: LegacyTransaction) -> Self {
+        Transaction {
+            fiat_amount: legacy_fiat_amount(legacy.fiat_amount, &legacy.fiat_currency),
+            kind: TransactionKind::infer(&legacy.id),
+            id: legacy.id,
+            from: legacy.from,
+            to: legacy.to,
+            amount: legacy.amount,
+            fiat_currency: legacy.fiat_currency,
+            icp_amount: legacy.icp_amount,
+            timestamp: legacy.timestamp,
+            status: legacy.status,
+            qr_id: legacy.qr_id,
+            transaction_hash: legacy.transaction_hash,
+            fee: legacy.fee,
+            memo: None,
+        }
+    }
+}
+
+#[derive(CandidType, Deserialize)]
+struct LegacyQRCode {
+    id: String,
+    user_id: Principal,
+    fiat_amount: f64,
+    fiat_currency: String,
+    icp_amount: u64,
+    expire_time: u64,
+    created_at: u64,
+    is_used: bool,
+    description: Option<String>,
+}
+
+impl From<LegacyQRCode> for QRCode {
+    fn from(legacy: LegacyQRCode) -> Self {
+        QRCode {
+            fiat_amount: legacy_fiat_amount(legacy.fiat_amount, &legacy.fiat_currency),
+            id: legacy.id,
+            user_id: legacy.user_id,
+            kind: QRKind::Dynamic,
+            fiat_currency: legacy.fiat_currency,
+            icp_amount: legacy.icp_amount,
+            expire_time: legacy.expire_time,
+            created_at: legacy.created_at,
+            status: status_from_is_used(legacy.is_used),
+            description: legacy.description,
+            max_uses: None,
+            allowed_payer: None,
+            invoice_id: None,
+        }
+    }
+}
+
+#[derive(CandidType, Deserialize)]
+struct LegacyTopUpTransaction {
+    id: String,
+    user_id: Principal,
+    amount: u64,
+    fiat_amount: f64,
+    fiat_currency: String,
+    payment_method: TopUpMethod,
+    payment_data: TopUpPaymentData,
+    status: TopUpStatus,
+    created_at: u64,
+    processed_at: Option<u64>,
+    reference_id: String,
+}
+
+impl From<LegacyTopUpTransaction> for TopUpTransaction {
+    fn from(legacy: LegacyTopUpTransaction) -> Self {
+        TopUpTransaction {
+            fiat_amount: legacy_fiat_amount(legacy.fiat_amount, &legacy.fiat_currency),
+            id: legacy.id,
+            user_id: legacy.user_id,
+            amount: legacy.amount,
+            fiat_currency: legacy.fiat_currency,
+            payment_method: legacy.payment_method,
+            payment_data: legacy.payment_data,
+            status: legacy.status,
+            created_at: legacy.created_at,
+            processed_at: legacy.processed_at,
+            reference_id: legacy.reference_id,
+        }
+    }
+}
+
+fn status_from_is_used(is_used: bool) -> QRStatus {
+    if is_used {
+        QRStatus::Paid
+    } else {
+        QRStatus::Active
+    }
+}
+
+// User before KYC tiers
+#[derive(CandidType, Deserialize)]
+struct UserV1 {
+    id: Principal,
+    wallet_address: String,
+    created_at: u64,
+    username: Option<String>,
+    email: Option<String>,
+    balance: u64,
+}
+
+impl From<UserV1> for User {
+    fn from(v1: UserV1) -> Self {
+        User {
+            id: v1.id,
+            wallet_address: v1.wallet_address,
+            created_at: v1.created_at,
+            username: v1.username,
+            email: v1.email,
+            balance: v1.balance,
+            kyc_tier: KycTier::None,
+            kyc_verified_at: None,
+        }
+    }
+}
+
+// Progress of the ICRC ledger watcher that settles wallet payments
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct LedgerWatcherState {
+    pub ledger: Principal,
+    // Next block index to read
+    pub next_block: u64,
+    pub last_polled_at: u64,
+    pub last_error: Option<String>,
+    pub matched_payments: u64,
+    // Most recent last, capped
+    pub skipped_ranges: Vec<BlockRange>,
+}
+
+impl Storable for LedgerWatcherState {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+
+    const BOUND: Bound = Bound::Bounded {
+        max_size: 2048,
+        is_fixed_size: false,
+    };
+}
+
+// Ledger blocks the watcher could not read, e.g. because an archive did not
+// answer; payments in them have to be reconciled by hand
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct BlockRange {
+    pub start: u64,
+    // Exclusive
+    pub end: u64,
+}
+
+// A wallet transfer carrying a QR memo that could not settle the code
+// (already paid, expired, underpaid, ...). The amount is credited to the
+// payer's balance instead of staying in the merchant subaccount.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct UnmatchedLedgerTransfer {
+    pub id: String,
+    pub ledger: Principal,
+    pub block_index: u64,
+    pub payer: Principal,
+    pub merchant: Principal,
+    pub qr_id: String,
+    pub amount: u64,
+    pub reason: String,
+    pub recorded_at: u64,
+}
+
+impl Storable for UnmatchedLedgerTransfer {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+
+    const BOUND: Bound = Bound::Bounded {
+        max_size: 2048,
+        is_fixed_size: false,
+    };
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum InvoiceStatus {
+    Draft,
+    Issued,
+    PartiallyPaid,
+    Paid,
+    Void,
+}
+
+// Amounts are in the invoice currency; tax rates in basis points (1100 = 11%)
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct InvoiceLineItem {
+    pub name: String,
+    pub quantity: u32,
+    pub unit_price: Money,
+    pub tax_rate_bps: u32,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum InvoiceDiscount {
+    PercentBps(u32),
+    Fixed(Money),
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct InvoiceLineItemInput {
+    pub name: String,
+    pub quantity: u32,
+    pub unit_price: String,
+    pub tax_rate_bps: Option<u32>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum InvoiceDiscountInput {
+    PercentBps(u32),
+    Fixed(String),
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct InvoiceInput {
+    pub currency: String,
+    pub line_items: Vec<InvoiceLineItemInput>,
+    pub discount: Option<InvoiceDiscountInput>,
+    pub due_date: Option<u64>,
+    pub customer_reference: Option<String>,
+    pub notes: Option<String>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct Invoice {
+    pub id: String,
+    pub merchant: Principal,
+    pub currency: String,
+    pub line_items: Vec<InvoiceLineItem>,
+    pub discount: Option<InvoiceDiscount>,
+    pub subtotal: Money,
+    pub discount_amount: Money,
+    pub tax_amount: Money,
+    pub total: Money,
+    pub amount_paid: Money,
+    pub due_date: Option<u64>,
+    pub customer_reference: Option<String>,
+    pub notes: Option<String>,
+    pub status: InvoiceStatus,
+    // QR codes generated to collect this invoice, oldest first
+    pub qr_ids: Vec<String>,
+    pub created_at: u64,
+    pub issued_at: Option<u64>,
+    pub paid_at: Option<u64>,
+}
+
+impl Storable for Invoice {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum BillingInterval {
+    Day,
+    Week,
+    // Fixed 30 and 365 day periods
+    Month,
+    Year,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct SubscriptionPlan {
+    pub id: String,
+    pub merchant: Principal,
+    pub name: String,
+    pub amount: Money,
+    pub currency: String,
+    pub interval: BillingInterval,
+    // Bill every `interval_count` intervals, e.g. 3 x Month
+    pub interval_count: u32,
+    // Inactive plans keep billing existing subscribers but take no new ones
+    pub active: bool,
+    pub created_at: u64,
+}
+
+impl Storable for SubscriptionPlan {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+
+    const BOUND: Bound = Bound::Bounded {
+        max_size: 1024,
+        is_fixed_size: false,
+    };
+}
+
+// Where charges are taken from: the payer's balance here, or an ICRC-2
+// allowance the payer granted this canister on the ledger
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum SubscriptionFunding {
+    Balance,
+    Icrc2Allowance,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum SubscriptionStatus {
+    Active,
+    // Last charge failed; retrying on the dunning schedule
+    PastDue,
+    Paused,
+    Cancelled,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct Subscription {
+    pub id: String,
+    pub plan_id: String,
+    pub merchant: Principal,
+    pub payer: Principal,
+    pub funding: SubscriptionFunding,
+    pub status: SubscriptionStatus,
+    pub next_charge_at: u64,
+    // Failed attempts for the current period
+    pub failed_attempts: u32,
+    pub charge_count: u64,
+    pub last_error: Option<String>,
+    pub created_at: u64,
+    pub cancelled_at: Option<u64>,
+}
+
+impl Storable for Subscription {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+
+    const BOUND: Bound = Bound::Bounded {
+        max_size: 2048,
+        is_fixed_size: false,
+    };
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct SubscriptionCharge {
+    pub id: String,
+    pub subscription_id: String,
+    pub amount: Money,
+    pub currency: String,
+    pub icp_amount: u64,
+    pub succeeded: bool,
+    pub transaction_id: Option<String>,
+    pub error: Option<String>,
+    pub timestamp: u64,
+}
+
+impl Storable for SubscriptionCharge {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+
+    const BOUND: Bound = Bound::Bounded {
+        max_size: 2048,
+        is_fixed_size: false,
+    };
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum PaymentRequestStatus {
+    Pending,
+    Accepted,
+    Declined,
+    Cancelled,
+    Expired,
+}
+
+// One user asking another for money
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct PaymentRequest {
+    pub id: String,
+    pub requester: Principal,
+    pub payer: Principal,
+    pub amount: Money,
+    pub currency: String,
+    pub icp_amount: u64,
+    pub note: Option<String>,
+    pub status: PaymentRequestStatus,
+    pub created_at: u64,
+    pub expires_at: u64,
+    pub responded_at: Option<u64>,
+    pub transaction_id: Option<String>,
+}
+
+impl Storable for PaymentRequest {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+
+    const BOUND: Bound = Bound::Bounded {
+        max_size: 2048,
+        is_fixed_size: false,
+    };
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SplitShare {
+    pub payer: Principal,
+    pub amount: Money,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum SplitMode {
+    // Any `participants` payers each pay an equal share
+    Equal { participants: u32 },
+    // Named payers each pay their own amount
+    Custom(Vec<SplitShare>),
+    // Anyone contributes any amount up to what is left
+    Open,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum SplitStatus {
+    Collecting,
+    Completed,
+    Refunded,
+    Cancelled,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SplitContribution {
+    pub payer: Principal,
+    pub amount: Money,
+    pub icp_amount: u64,
+    // The held (PROCESSING) transaction
+    pub transaction_id: String,
+    pub timestamp: u64,
+}
+
+// A bill shared by several payers; shares its id with its split QR code
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct SplitBill {
+    pub id: String,
+    pub merchant: Principal,
+    pub currency: String,
+    pub target: Money,
+    pub mode: SplitMode,
+    pub contributions: Vec<SplitContribution>,
+    pub status: SplitStatus,
+    pub created_at: u64,
+    pub expires_at: u64,
+    pub settled_at: Option<u64>,
+}
+
+impl Storable for SplitBill {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+// One line of a batch payout as entered by the payer
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PayoutEntry {
+    // Principal, email or username
+    pub recipient: String,
+    pub amount: String,
+    pub memo: Option<String>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum PayoutBatchStatus {
+    // Some entries were invalid; nothing was paid
+    Rejected,
+    // Paid by the payer, recipients still being credited
+    Processing,
+    Completed,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PayoutResult {
+    pub recipient: String,
+    pub recipient_id: Option<Principal>,
+    pub amount: Money,
+    pub icp_amount: u64,
+    pub fee: u64,
+    pub memo: Option<String>,
+    pub transaction_id: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct PayoutBatch {
+    pub id: String,
+    pub payer: Principal,
+    pub currency: String,
+    pub results: Vec<PayoutResult>,
+    pub total_icp: u64,
+    pub total_fee: u64,
+    pub status: PayoutBatchStatus,
+    // First entry not yet credited
+    pub next_index: u32,
+    pub created_at: u64,
+    pub completed_at: Option<u64>,
+}
+
+impl Storable for PayoutBatch {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+// Payer's choice to have a QR payment held until delivery
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct EscrowTerms {
+    // Released to the merchant automatically after this long (default 14 days)
+    pub release_after_seconds: Option<u64>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum EscrowStatus {
+    Held,
+    Disputed,
+    Released,
+    Refunded,
+    // Divided between payer and merchant by an admin
+    Split,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum EscrowResolution {
+    Release,
+    Refund,
+    // Share of the payment returned to the payer, in basis points
+    Split { payer_share_bps: u32 },
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct EscrowDispute {
+    pub opened_by: Principal,
+    pub reason: String,
+    pub opened_at: u64,
+    pub resolution: Option<EscrowResolution>,
+    pub resolved_by: Option<Principal>,
+    pub note: Option<String>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct Escrow {
+    pub id: String,
+    pub qr_id: String,
+    pub payer: Principal,
+    pub merchant: Principal,
+    pub amount: Money,
+    pub currency: String,
+    pub icp_amount: u64,
+    // The held (PROCESSING) transaction
+    pub transaction_id: String,
+    pub status: EscrowStatus,
+    pub created_at: u64,
+    pub release_at: u64,
+    pub dispute: Option<EscrowDispute>,
+    pub settled_at: Option<u64>,
+}
+
+impl Storable for Escrow {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum DisputeStatus {
+    Open,
+    // The merchant has answered; waiting for an admin decision
+    Responded,
+    Resolved,
+    Withdrawn,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum DisputeDecision {
+    RefundPayer,
+    // ICP (e8s) returned to the payer, at most the transaction amount
+    PartialRefund { amount: u64 },
+    Reject,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct DisputeEvent {
+    pub status: DisputeStatus,
+    pub actor: Principal,
+    pub note: Option<String>,
+    pub timestamp: u64,
+}
+
+// A payer contesting a completed transaction
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct Dispute {
+    pub id: String,
+    pub transaction_id: String,
+    pub payer: Principal,
+    pub merchant: Principal,
+    pub icp_amount: u64,
+    pub fiat_amount: Money,
+    pub currency: String,
+    pub reason: String,
+    pub evidence: Option<String>,
+    pub merchant_response: Option<String>,
+    pub status: DisputeStatus,
+    pub decision: Option<DisputeDecision>,
+    pub refunded_amount: u64,
+    // Part of the refund taken back from the merchant and paid to the payer
+    pub recovered_amount: u64,
+    pub timeline: Vec<DisputeEvent>,
+    pub created_at: u64,
+    pub resolved_at: Option<u64>,
+}
+
+impl Storable for Dispute {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+// Amounts in e8s. Outgoing caps count payments sent over rolling
+// 24h / 7d / 30d windows, fees excluded.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TierLimits {
+    pub tier: KycTier,
+    pub daily_outgoing_e8s: u64,
+    pub weekly_outgoing_e8s: u64,
+    pub monthly_outgoing_e8s: u64,
+    pub max_transactions_per_hour: u32,
+    pub max_topup_e8s: u64,
+    // Completed top-ups over a rolling 30 days; None for no cap
+    pub monthly_topup_e8s: Option<u64>,
+}
+
+pub fn default_tier_limits() -> Vec<TierLimits> {
+    const ICP: u64 = 100_000_000;
+    vec![
+        TierLimits {
+            tier: KycTier::None,
+            daily_outgoing_e8s: 50 * ICP,
+            weekly_outgoing_e8s: 200 * ICP,
+            monthly_outgoing_e8s: 500 * ICP,
+            max_transactions_per_hour: 20,
+            max_topup_e8s: 20 * ICP,
+            monthly_topup_e8s: Some(50 * ICP),
+        },
+        TierLimits {
+            tier: KycTier::Basic,
+            daily_outgoing_e8s: 500 * ICP,
+            weekly_outgoing_e8s: 2_000 * ICP,
+            monthly_outgoing_e8s: 5_000 * ICP,
+            max_transactions_per_hour: 60,
+            max_topup_e8s: 200 * ICP,
+            monthly_topup_e8s: Some(2_000 * ICP),
+        },
+        TierLimits {
+            tier: KycTier::Full,
+            daily_outgoing_e8s: 5_000 * ICP,
+            weekly_outgoing_e8s: 20_000 * ICP,
+            monthly_outgoing_e8s: 50_000 * ICP,
+            max_transactions_per_hour: 200,
+            max_topup_e8s: 1_000 * ICP,
+            monthly_topup_e8s: None,
+        },
+    ]
+}
+
+// The caller's limits and what is left of them right now
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct UserLimits {
+    pub tier: KycTier,
+    pub limits: TierLimits,
+    pub daily_remaining_e8s: u64,
+    pub weekly_remaining_e8s: u64,
+    pub monthly_remaining_e8s: u64,
+    pub transactions_remaining_this_hour: u32,
+    pub monthly_topup_remaining_e8s: Option<u64>,
+}
+
+// Identity verification level; decides which limits apply
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+pub enum KycTier {
+    #[default]
+    None,
+    Basic,
+    Full,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum KycDocumentType {
+    NationalId,
+    Passport,
+    DriversLicense,
+    ProofOfAddress,
+    Selfie,
+    BusinessRegistration,
+}
+
+// `reference` points at the document in off-chain storage; it is hashed
+// before anything is stored
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct KycDocumentInput {
+    pub document_type: KycDocumentType,
+    pub reference: String,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct KycDocument {
+    pub document_type: KycDocumentType,
+    // Hex SHA-256 of the submitter and the reference
+    pub reference_hash: String,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum KycSubmissionStatus {
+    Pending,
+    Approved,
+    Rejected,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct KycSubmission {
+    pub id: String,
+    pub user_id: Principal,
+    pub requested_tier: KycTier,
+    pub documents: Vec<KycDocument>,
+    pub status: KycSubmissionStatus,
+    pub submitted_at: u64,
+    pub reviewed_at: Option<u64>,
+    pub reviewed_by: Option<Principal>,
+    pub review_note: Option<String>,
+}
+
+impl Storable for KycSubmission {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+
+    const BOUND: Bound = Bound::Bounded {
+        max_size: 8192,
+        is_fixed_size: false,
+    };
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+pub enum RiskAction {
+    #[default]
+    Payment,
+    CardTopup,
+    QrisTopup,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum RiskDecision {
+    Allow,
+    // Held until an admin approves or rejects it
+    Review,
+    Deny,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum RiskReviewStatus {
+    Pending,
+    Approved,
+    Rejected,
+}
+
+// One flagged payment or top-up and why it was flagged
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct RiskAssessment {
+    pub id: String,
+    pub user_id: Principal,
+    pub action: RiskAction,
+    // The payee, for payments
+    pub counterparty: Option<Principal>,
+    pub amount: u64,
+    pub score: u32,
+    pub decision: RiskDecision,
+    pub reasons: Vec<String>,
+    // The held transaction or top-up
+    pub reference_id: Option<String>,
+    // Set for held items
+    pub review_status: Option<RiskReviewStatus>,
+    pub reviewed_by: Option<Principal>,
+    pub review_note: Option<String>,
+    pub created_at: u64,
+    pub reviewed_at: Option<u64>,
+}
+
+impl Storable for RiskAssessment {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+
+    const BOUND: Bound = Bound::Bounded {
+        max_size: 8192,
+        is_fixed_size: false,
+    };
+}
@@ -1,6 +1,7 @@
 import React, { useState, useEffect } from 'react'
 import { useParams, useNavigate } from 'react-router-dom'
 import { useICP } from '../contexts/ICPContext'
+import { moneyToNumber } from '../services/backend'
 import QRCode from 'react-qr-code'
 import { 
   QrCode, 
@@ -347,7 +348,7 @@ const ClaimQRIS = () => {
                 
                 <p className="text-[#B3B3C2] font-medium mb-3">Amount to Pay</p>
                 <p className="text-5xl font-bold text-[#F5F6FA] mb-4">
-                  {formatCurrency(topupData?.fiat_amount, topupData?.fiat_currency)}
+                  {formatCurrency(moneyToNumber(topupData?.fiat_amount), topupData?.fiat_currency)}
                 </p>
                 <p className="text-xl text-[#885FFF] font-semibold">
                   ≈ {(formatAmount(topupData?.amount) / 100000000).toFixed(8)} ICP
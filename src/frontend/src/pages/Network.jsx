@@ -2,6 +2,7 @@ import React, { useState, useEffect } from 'react'
 import { Link } from 'react-router-dom'
 import { motion } from 'framer-motion'
 import { useICP } from '../contexts/ICPContext'
+import { moneyToNumber } from '../services/backend'
 import { 
   Wallet, 
   QrCode, 
@@ -307,7 +308,7 @@ const NetworkDashboard = () => {
         type: getStatusText(tx.status) === 'Completed' ? 'Payment' : 'Processing',
         countryCode: currencyInfo?.code || 'UN',
         countryName: currencyInfo?.name || 'Unknown',
-        amount: formatCurrency(tx.fiat_amount || 0, currency),
+        amount: formatCurrency(moneyToNumber(tx.fiat_amount), currency),
         status: getStatusText(tx.status),
         icpAmount: formatICP(tx.amount || 0),
         currency: currency
@@ -1,5 +1,6 @@
 import React, { useState, useEffect } from 'react'
 import { useICP } from '../contexts/ICPContext'
+import { moneyToNumber } from '../services/backend'
 import {
   Globe,
   Activity,
@@ -329,7 +330,7 @@ const NetworkStats = () => {
 
       regionStats[region].currencies.push(stat)
       regionStats[region].totalVolume += convertBigIntToNumber(stat.total_icp_volume)
-      regionStats[region].totalFiatVolume += convertBigIntToNumber(stat.total_fiat_volume)
+      regionStats[region].totalFiatVolume += moneyToNumber(stat.total_fiat_volume)
       regionStats[region].transactionCount += convertBigIntToNumber(stat.usage_count)
     })
 
@@ -529,7 +530,7 @@ const NetworkStats = () => {
                               </div>
                               <div className="flex items-center space-x-4">
                                 <span className="text-gray-500">
-                                  {formatCurrency(currency.total_fiat_volume, currency.currency)}
+                                  {formatCurrency(moneyToNumber(currency.total_fiat_volume), currency.currency)}
                                 </span>
                                 <span className="text-blue-600">
                                   {formatICP(currency.total_icp_volume)} ICP
@@ -659,7 +660,7 @@ const NetworkStats = () => {
                           <td className="px-6 py-4 whitespace-nowrap">
                             <div>
                               <div className="text-sm font-medium text-gray-900">
-                                {formatCurrency(tx.fiat_amount, tx.fiat_currency)}
+                                {formatCurrency(moneyToNumber(tx.fiat_amount), tx.fiat_currency)}
                               </div>
                               <div className="text-sm text-gray-500">
                                 {formatICP(tx.amount)} ICP
@@ -709,7 +710,7 @@ const NetworkStats = () => {
                                   <div className="space-y-1">
                                     <div><span className="text-gray-500">Description:</span> {tx.description}</div>
                                     <div><span className="text-gray-500">Currency:</span> {tx.fiat_currency}</div>
-                                    <div><span className="text-gray-500">Fiat Amount:</span> {formatCurrency(tx.fiat_amount, tx.fiat_currency)}</div>
+                                    <div><span className="text-gray-500">Fiat Amount:</span> {formatCurrency(moneyToNumber(tx.fiat_amount), tx.fiat_currency)}</div>
                                     <div><span className="text-gray-500">ICP Amount:</span> {formatICP(tx.icp_amount)} ICP</div>
                                   </div>
                                 </div>
@@ -765,7 +766,7 @@ const NetworkStats = () => {
                       <div className="space-y-2">
                         <div className="flex justify-between">
                           <span className="text-sm text-gray-600">Fiat Volume</span>
-                          <span className="text-sm font-medium">{formatCurrency(stat.total_fiat_volume, stat.currency)}</span>
+                          <span className="text-sm font-medium">{formatCurrency(moneyToNumber(stat.total_fiat_volume), stat.currency)}</span>
                         </div>
                         <div className="flex justify-between">
                           <span className="text-sm text-gray-600">ICP Volume</span>
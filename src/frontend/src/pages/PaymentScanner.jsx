@@ -140,21 +140,23 @@ const PaymentScanner = () => {
     return `${minutes}m ${secs}s`
   }
 
+  const isClosed = (qrInfo) => !('Active' in qrInfo.status)
+
   const getStatusIcon = (qrInfo) => {
     if (qrInfo.is_expired) return <XCircle className="w-5 h-5 text-rose-400" />
-    if (qrInfo.is_used) return <CheckSquare className="w-5 h-5 text-slate-400" />
+    if (isClosed(qrInfo)) return <CheckSquare className="w-5 h-5 text-slate-400" />
     return <CheckCircle className="w-5 h-5 text-emerald-400" />
   }
 
   const getStatusColor = (qrInfo) => {
     if (qrInfo.is_expired) return 'bg-rose-500/10 text-rose-400 border-rose-500/20'
-    if (qrInfo.is_used) return 'bg-slate-500/10 text-slate-400 border-slate-500/20'
+    if (isClosed(qrInfo)) return 'bg-slate-500/10 text-slate-400 border-slate-500/20'
     return 'bg-emerald-500/10 text-emerald-400 border-emerald-500/20'
   }
 
   const getStatusText = (qrInfo) => {
     if (qrInfo.is_expired) return 'Expired'
-    if (qrInfo.is_used) return 'Used'
+    if (isClosed(qrInfo)) return Object.keys(qrInfo.status)[0]
     return 'Active'
   }
 
@@ -319,7 +321,7 @@ const PaymentScanner = () => {
                     <div>
                       <div className="text-sm font-medium text-[#B3B3C2] mb-1">Payment Request</div>
                       <div className="text-4xl font-bold text-[#F5F6FA]">
-                        {qrInfo.fiat_amount} {qrInfo.fiat_currency}
+                        {qrInfo.formatted_fiat}
                       </div>
                       <div className="text-[#885FFF] font-semibold text-lg">
                         ≈ {formatICP(qrInfo.icp_amount)} ICP
@@ -355,7 +357,7 @@ const PaymentScanner = () => {
                       {getStatusIcon(qrInfo)}
                       <span className={`font-bold text-lg ${
                         qrInfo.is_expired ? 'text-rose-400' :
-                        qrInfo.is_used ? 'text-slate-400' : 'text-emerald-400'
+                        isClosed(qrInfo) ? 'text-slate-400' : 'text-emerald-400'
                       }`}>
                         {getStatusText(qrInfo)}
                       </span>
@@ -372,7 +374,7 @@ const PaymentScanner = () => {
               </div>
 
               {/* Payment Button */}
-              {!qrInfo.is_expired && !qrInfo.is_used && (
+              {!qrInfo.is_expired && !isClosed(qrInfo) && (
                 <div className="pt-6 border-t border-[#23253B]">
                   <button
                     onClick={handlePayment}
@@ -387,7 +389,7 @@ const PaymentScanner = () => {
                     ) : (
                       <div className="flex items-center justify-center space-x-3">
                         <Zap className="w-6 h-6" />
-                        <span>Pay {qrInfo.fiat_amount} {qrInfo.fiat_currency} Now</span>
+                        <span>Pay {qrInfo.formatted_fiat} Now</span>
                         <ArrowRight className="w-6 h-6" />
                       </div>
                     )}
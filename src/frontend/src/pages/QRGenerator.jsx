@@ -1,6 +1,7 @@
 import React, { useState, useEffect } from 'react'
 import Flag from 'react-world-flags'
 import { useICP } from '../contexts/ICPContext.jsx'
+import { moneyToNumber } from '../services/backend'
 import { QRCodeCanvas } from "qrcode.react"
 import { 
   QrCode, 
@@ -444,7 +445,7 @@ const QRGenerator = () => {
                       <div className="sm:hidden">
                         <div className="text-xs font-medium text-[#B3B3C2] mb-1">Payment Amount</div>
                         <div className="text-xl font-bold text-[#F5F6FA]">
-                          {formatCurrency(qrCode.fiat_amount, qrCode.fiat_currency)}
+                          {formatCurrency(moneyToNumber(qrCode.fiat_amount), qrCode.fiat_currency)}
                         </div>
                         <div className="text-[#885FFF] font-medium text-sm">
                           ≈ {formatICP(qrCode.icp_amount)} ICP
@@ -454,7 +455,7 @@ const QRGenerator = () => {
                     <div className="hidden sm:block flex-1">
                       <div className="text-xs lg:text-sm font-medium text-[#B3B3C2] mb-1">Payment Amount</div>
                       <div className="text-2xl lg:text-3xl font-bold text-[#F5F6FA]">
-                        {formatCurrency(qrCode.fiat_amount, qrCode.fiat_currency)}
+                        {formatCurrency(moneyToNumber(qrCode.fiat_amount), qrCode.fiat_currency)}
                       </div>
                       <div className="text-[#885FFF] font-medium text-sm lg:text-base">
                         ≈ {formatICP(qrCode.icp_amount)} ICP
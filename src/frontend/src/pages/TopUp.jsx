@@ -1,5 +1,6 @@
 import React, { useState, useEffect } from 'react'
 import { useICP } from '../contexts/ICPContext'
+import { moneyToNumber } from '../services/backend'
 import QRCode from 'react-qr-code'
 import Flag from 'react-world-flags'
 import { 
@@ -566,7 +567,7 @@ const TopUp = () => {
                                       <span className="text-[#B3B3C2]">Amount:</span>
                                     </div>
                                     <span className="text-2xl font-bold text-[#F5F6FA]">
-                                      {formatAmount(currentTopup.fiat_amount).toLocaleString()} {currentTopup.fiat_currency}
+                                      {moneyToNumber(currentTopup.fiat_amount).toLocaleString()} {currentTopup.fiat_currency}
                                     </span>
                                   </div>
                                   
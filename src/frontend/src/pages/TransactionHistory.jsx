@@ -1,5 +1,6 @@
 import React, { useEffect, useState } from 'react'
 import { useICP } from '../contexts/ICPContext.jsx'
+import { moneyToNumber } from '../services/backend'
 import {
   History,
   ArrowUpRight,
@@ -300,6 +301,7 @@ const TransactionHistory = () => {
         return 'text-emerald-400'
       case 'PaymentSent':
       case 'FeeDeducted':
+      case 'Chargeback':
         return 'text-rose-400'
       case 'Adjustment':
         return 'text-orange-400'
@@ -347,7 +349,7 @@ const TransactionHistory = () => {
       id: topup.id,
       type: 'topup',
       method: getStatusText(topup.payment_method),
-      amount_fiat: `${formatAmount(topup.fiat_amount)} ${topup.fiat_currency}`,
+      amount_fiat: `${moneyToNumber(topup.fiat_amount)} ${topup.fiat_currency}`,
       amount_icp: `${formatICP(topup.amount)} ICP`,
       currency: topup.fiat_currency,
       status: getStatusText(topup.status),
@@ -1,6 +1,17 @@
 import { Actor, HttpAgent } from '@dfinity/agent'
 
 export const idlFactory = ({ IDL }) => {
+  const Money = IDL.Record({
+    'minor_units': IDL.Nat64,
+    'scale': IDL.Nat8,
+  })
+
+  const KycTier = IDL.Variant({
+    'None': IDL.Null,
+    'Basic': IDL.Null,
+    'Full': IDL.Null,
+  })
+
   const User = IDL.Record({
     'id': IDL.Principal,
     'wallet_address': IDL.Text,
@@ -8,6 +19,8 @@ export const idlFactory = ({ IDL }) => {
     'username': IDL.Opt(IDL.Text),
     'email': IDL.Opt(IDL.Text),
     'balance': IDL.Nat64,
+    'kyc_tier': KycTier,
+    'kyc_verified_at': IDL.Opt(IDL.Nat64),
   })
 
   const UserBalance = IDL.Record({
@@ -15,6 +28,11 @@ export const idlFactory = ({ IDL }) => {
     'balance': IDL.Nat64,
     'formatted_balance': IDL.Text,
     'last_updated': IDL.Nat64,
+    'held': IDL.Nat64,
+    'reserved': IDL.Nat64,
+    'available': IDL.Nat64,
+    'pending': IDL.Nat64,
+    'total': IDL.Nat64,
   })
 
   // Add new Balance Change Log types
@@ -25,6 +43,7 @@ export const idlFactory = ({ IDL }) => {
     'FeeDeducted': IDL.Null,
     'Refund': IDL.Null,
     'Adjustment': IDL.Null,
+    'Chargeback': IDL.Null,
   })
 
   const BalanceChangeLog = IDL.Record({
@@ -44,6 +63,11 @@ export const idlFactory = ({ IDL }) => {
     'PaymentCompleted': IDL.Null,
     'PaymentFailed': IDL.Null,
     'PaymentExpired': IDL.Null,
+    'Cancelled': IDL.Null,
+    'ContributionHeld': IDL.Null,
+    'ContributionRefunded': IDL.Null,
+    'PaymentHeld': IDL.Null,
+    'PaymentRefunded': IDL.Null,
   })
 
   const QRUsageLog = IDL.Record({
@@ -131,7 +155,7 @@ export const idlFactory = ({ IDL }) => {
     'id': IDL.Text,
     'user_id': IDL.Principal,
     'amount': IDL.Nat64,
-    'fiat_amount': IDL.Float64,
+    'fiat_amount': Money,
     'fiat_currency': IDL.Text,
     'payment_method': TopUpMethod,
     'payment_data': TopUpPaymentData,
@@ -188,29 +212,50 @@ export const idlFactory = ({ IDL }) => {
     'source': IDL.Text,
   })
 
+  const QRKind = IDL.Variant({
+    'Dynamic': IDL.Null,
+    'Static': IDL.Null,
+    'Split': IDL.Null,
+  })
+
+  const QRStatus = IDL.Variant({
+    'Active': IDL.Null,
+    'Cancelled': IDL.Null,
+    'Expired': IDL.Null,
+    'Paid': IDL.Null,
+    'Exhausted': IDL.Null,
+  })
+
   const QRCode = IDL.Record({
     'id': IDL.Text,
     'user_id': IDL.Principal,
-    'fiat_amount': IDL.Float64,
+    'kind': QRKind,
+    'fiat_amount': Money,
     'fiat_currency': IDL.Text,
     'icp_amount': IDL.Nat64,
     'expire_time': IDL.Nat64,
     'created_at': IDL.Nat64,
-    'is_used': IDL.Bool,
+    'status': QRStatus,
     'description': IDL.Opt(IDL.Text),
+    'max_uses': IDL.Opt(IDL.Nat32),
+    'allowed_payer': IDL.Opt(IDL.Principal),
+    'invoice_id': IDL.Opt(IDL.Text),
   })
 
   const QRDisplayInfo = IDL.Record({
     'id': IDL.Text,
-    'fiat_amount': IDL.Float64,
+    'kind': QRKind,
+    'fiat_amount': Money,
     'fiat_currency': IDL.Text,
     'icp_amount': IDL.Nat64,
     'formatted_fiat': IDL.Text,
     'formatted_icp': IDL.Text,
     'time_remaining_seconds': IDL.Opt(IDL.Nat64),
     'is_expired': IDL.Bool,
-    'is_used': IDL.Bool,
+    'status': QRStatus,
+    'remaining_uses': IDL.Opt(IDL.Nat32),
     'description': IDL.Opt(IDL.Text),
+    'payment_uri': IDL.Opt(IDL.Text),
   })
 
   const TransactionStatus = IDL.Variant({
@@ -221,25 +266,37 @@ export const idlFactory = ({ IDL }) => {
     'Expired': IDL.Null,
   })
 
+  const TransactionKind = IDL.Variant({
+    'QrPayment': IDL.Null,
+    'PeerTransfer': IDL.Null,
+    'WalletPayment': IDL.Null,
+    'Subscription': IDL.Null,
+    'PaymentRequest': IDL.Null,
+    'SplitBill': IDL.Null,
+    'Payout': IDL.Null,
+  })
+
   const Transaction = IDL.Record({
     'id': IDL.Text,
     'from': IDL.Principal,
     'to': IDL.Principal,
     'amount': IDL.Nat64,
     'fiat_currency': IDL.Text,
-    'fiat_amount': IDL.Float64,
+    'fiat_amount': Money,
     'icp_amount': IDL.Nat64,
     'timestamp': IDL.Nat64,
     'status': TransactionStatus,
     'qr_id': IDL.Text,
     'transaction_hash': IDL.Opt(IDL.Text),
     'fee': IDL.Nat64,
+    'kind': TransactionKind,
+    'memo': IDL.Opt(IDL.Text),
   })
 
   const CurrencyStatInfo = IDL.Record({
   'currency': IDL.Text,
   'usage_count': IDL.Nat64,
-  'total_fiat_volume': IDL.Float64,
+  'total_fiat_volume': Money,
   'total_icp_volume': IDL.Nat64,
 })
 
@@ -301,7 +358,7 @@ const EncryptedWalletIdentity = IDL.Record({
     'from_user': IDL.Opt(IDL.Principal),
     'to_user': IDL.Opt(IDL.Principal),
     'amount': IDL.Nat64,
-    'fiat_amount': IDL.Float64,
+    'fiat_amount': Money,
     'fiat_currency': IDL.Text,
     'icp_amount': IDL.Nat64,
     'timestamp': IDL.Nat64,
@@ -349,16 +406,16 @@ const EncryptedWalletIdentity = IDL.Record({
     'fetch_exchange_rate': IDL.Func([IDL.Text], [Result_ExchangeRate], []),
     
     // QR code management
-    'generate_qr': IDL.Func([IDL.Float64, IDL.Text, IDL.Opt(IDL.Text)], [Result_QRCode], []),
+    'generate_qr': IDL.Func([IDL.Text, IDL.Text, IDL.Opt(IDL.Text)], [Result_QRCode], []),
     'validate_qr_code': IDL.Func([IDL.Text], [Result_QRDisplayInfo], []),
     
     // Transaction management - FIX: Use Result_Transaction
-    'process_payment': IDL.Func([IDL.Text, IDL.Opt(IDL.Text)], [Result_Transaction], []),
+    'process_payment': IDL.Func([IDL.Text, IDL.Opt(IDL.Text), IDL.Opt(IDL.Text)], [Result_Transaction], []),
     
     // Balance & Top-up Management
     'get_user_balance': IDL.Func([], [IDL.Opt(UserBalance)], ['query']),
-    'create_qris_topup': IDL.Func([IDL.Float64, IDL.Text], [Result_TopUpTransaction], []),
-    'create_card_topup': IDL.Func([IDL.Float64, IDL.Text, CardDataInput, IDL.Bool], [Result_TopUpTransaction], []),
+    'create_qris_topup': IDL.Func([IDL.Text, IDL.Text], [Result_TopUpTransaction], []),
+    'create_card_topup': IDL.Func([IDL.Text, IDL.Text, CardDataInput, IDL.Bool], [Result_TopUpTransaction], []),
     'claim_qris_payment': IDL.Func([IDL.Text], [Result_TopUpTransaction], []),
     'get_topup_transaction': IDL.Func([IDL.Text], [IDL.Opt(TopUpTransaction)], ['query']),
     'get_user_topup_history': IDL.Func([], [IDL.Vec(TopUpTransaction)], ['query']),
@@ -387,6 +444,14 @@ const EncryptedWalletIdentity = IDL.Record({
   })
 }
 
+// Money records ({ minor_units, scale }) to a plain number for display
+export const moneyToNumber = (money) => {
+  if (money && typeof money === 'object' && 'minor_units' in money) {
+    return Number(money.minor_units) / 10 ** Number(money.scale)
+  }
+  return Number(money || 0)
+}
+
 export const createActor = (canisterId, options = {}) => {
   const host = options.host || import.meta.env.VITE_IC_HOST || 'http://localhost:4943'
   const agent = options.agent || new HttpAgent({
@@ -591,7 +656,7 @@ export class PaymentBackendService {
   async generateQR(fiatAmount, currency, description) {
     try {
       return await this.actor.generate_qr(
-        fiatAmount,
+        String(fiatAmount),
         currency,
         description ? [description] : []
       )
@@ -610,11 +675,12 @@ export class PaymentBackendService {
     }
   }
 
-  async processPayment(qrId, transactionHash) {
+  async processPayment(qrId, transactionHash, payerAmount) {
     try {
       const result = await this.actor.process_payment(
         qrId,
-        transactionHash ? [transactionHash] : []
+        transactionHash ? [transactionHash] : [],
+        payerAmount != null ? [String(payerAmount)] : []
       )
       return result
     } catch (error) {
@@ -648,7 +714,7 @@ export class PaymentBackendService {
 
   async createQRISTopup(amount, currency) {
     try {
-      const result = await this.actor.create_qris_topup(amount, currency)
+      const result = await this.actor.create_qris_topup(String(amount), currency)
       return result
     } catch (error) {
       console.error('QRIS topup error details:', error)
@@ -661,7 +727,7 @@ export class PaymentBackendService {
   async createCardTopup(amount, currency, cardData, isCredit) {
     try {
       return await this.actor.create_card_topup(
-        amount,
+        String(amount),
         currency,
         cardData,
         isCredit